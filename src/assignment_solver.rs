@@ -0,0 +1,160 @@
+use itertools::Itertools;
+
+use crate::{
+    placement::PotentialLocation,
+    utils::{fmin, Side},
+};
+
+// costs are capped, so hungarian doesn't work with huge numbers
+const MAX_COST: f64 = 100.0;
+
+#[derive(Clone, Copy, Debug)]
+pub struct SlotAssignment {
+    // index in the slots list
+    pub slot: usize,
+    // same meaning as in `Search3StateWithScore::first_sides`
+    pub first_side: Side,
+    // average dist over edges with existing figures
+    pub cost: f64,
+    // how much worse is the best other piece for this slot
+    pub confidence: f64,
+}
+
+// Min-cost assignment of rows to different columns, rows.len() <= columns.len().
+// Returns column for each row.
+pub fn hungarian(cost: &[Vec<f64>]) -> Vec<usize> {
+    let n = cost.len();
+    if n == 0 {
+        return vec![];
+    }
+    let m = cost[0].len();
+    assert!(n <= m);
+    // 1-indexed, column 0 is fake
+    let mut u = vec![0.0; n + 1];
+    let mut v = vec![0.0; m + 1];
+    let mut matched_row = vec![0; m + 1];
+    let mut way = vec![0; m + 1];
+    for row in 1..=n {
+        matched_row[0] = row;
+        let mut col0 = 0;
+        let mut min_v = vec![f64::MAX; m + 1];
+        let mut used = vec![false; m + 1];
+        loop {
+            used[col0] = true;
+            let row0 = matched_row[col0];
+            let mut delta = f64::MAX;
+            let mut col1 = 0;
+            for col in 1..=m {
+                if used[col] {
+                    continue;
+                }
+                let cur = cost[row0 - 1][col - 1] - u[row0] - v[col];
+                if cur < min_v[col] {
+                    min_v[col] = cur;
+                    way[col] = col0;
+                }
+                if min_v[col] < delta {
+                    delta = min_v[col];
+                    col1 = col;
+                }
+            }
+            for col in 0..=m {
+                if used[col] {
+                    u[matched_row[col]] += delta;
+                    v[col] -= delta;
+                } else {
+                    min_v[col] -= delta;
+                }
+            }
+            col0 = col1;
+            if matched_row[col0] == 0 {
+                break;
+            }
+        }
+        loop {
+            let col1 = way[col0];
+            matched_row[col0] = matched_row[col1];
+            col0 = col1;
+            if col0 == 0 {
+                break;
+            }
+        }
+    }
+    let mut res = vec![0; n];
+    for col in 1..=m {
+        if matched_row[col] != 0 {
+            res[matched_row[col] - 1] = col - 1;
+        }
+    }
+    res
+}
+
+// Each slot only looks at its existing neighbours. Adjacencies between two slots
+// (new-new edges) are ignored, so pieces assigned to neighbouring slots are not
+// checked against each other here. Such edges are only scored, when the whole group
+// is checked with `PotentialGroupLocation::check_way`.
+pub fn assign_pieces(
+    slots: &[PotentialLocation],
+    used: &[bool],
+    dist: impl Fn(Side, Side) -> f64,
+) -> Vec<SlotAssignment> {
+    let pieces = (0..used.len()).filter(|&fig| !used[fig]).collect_vec();
+    if pieces.len() < slots.len() {
+        eprintln!(
+            "Not enough pieces ({}) for {} slots",
+            pieces.len(),
+            slots.len()
+        );
+        return vec![];
+    }
+    // (cost, first side) for each slot and piece
+    let best_rotations = slots
+        .iter()
+        .map(|slot| {
+            pieces
+                .iter()
+                .map(|&fig| {
+                    (0..4)
+                        .map(|side| {
+                            let first_side = Side { fig, side };
+                            let edges = (0..4)
+                                .filter_map(|offset| {
+                                    let s = slot.neighbors[offset]?;
+                                    Some(fmin(dist(s, first_side.ne_offset(offset)), MAX_COST))
+                                })
+                                .collect_vec();
+                            let cost = edges.iter().sum::<f64>() / (edges.len().max(1) as f64);
+                            (cost, first_side)
+                        })
+                        .min_by(|(c1, _), (c2, _)| c1.total_cmp(c2))
+                        .unwrap()
+                })
+                .collect_vec()
+        })
+        .collect_vec();
+    let cost = best_rotations
+        .iter()
+        .map(|row| row.iter().map(|(c, _)| *c).collect_vec())
+        .collect_vec();
+    let assigned = hungarian(&cost);
+    let mut res = vec![];
+    for (slot, &piece_id) in assigned.iter().enumerate() {
+        let (cost, first_side) = best_rotations[slot][piece_id];
+        if cost >= MAX_COST {
+            continue;
+        }
+        let best_other = best_rotations[slot]
+            .iter()
+            .enumerate()
+            .filter(|(id, _)| *id != piece_id)
+            .map(|(_, (c, _))| *c)
+            .fold(MAX_COST, fmin);
+        res.push(SlotAssignment {
+            slot,
+            first_side,
+            cost,
+            confidence: best_other - cost,
+        });
+    }
+    res
+}
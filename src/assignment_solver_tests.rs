@@ -0,0 +1,63 @@
+#[cfg(test)]
+mod tests {
+    use crate::{
+        assignment_solver::{assign_pieces, hungarian},
+        placement::{Pos, PotentialLocation},
+        utils::Side,
+    };
+
+    #[test]
+    pub fn hungarian_small() {
+        let cost = vec![
+            vec![4.0, 1.0, 3.0, 9.0],
+            vec![2.0, 0.0, 5.0, 9.0],
+            vec![3.0, 2.0, 2.0, 9.0],
+        ];
+        assert_eq!(hungarian(&cost), vec![1, 0, 2]);
+    }
+
+    #[test]
+    pub fn assign_two_slots() {
+        // figures 0 and 1 are placed, slot 0 is to the right of 0, slot 1 is below 1.
+        // Slots are next to each other, but this edge is not checked.
+        let slots = vec![
+            PotentialLocation {
+                neighbors: [None, None, None, Some(Side { fig: 0, side: 1 })],
+                pos: Pos::new(0, 1),
+            },
+            PotentialLocation {
+                neighbors: [Some(Side { fig: 1, side: 2 }), None, None, None],
+                pos: Pos::new(1, 1),
+            },
+        ];
+        let used = [true, true, false, false, false];
+        // only side 0 of new pieces matches anything
+        let dist = |s1: Side, s2: Side| -> f64 {
+            if s2.side != 0 {
+                return 1000.0;
+            }
+            match (s1.fig, s2.fig) {
+                (0, 2) => 1.0,
+                (0, 3) => 2.0,
+                (0, 4) => 50.0,
+                (1, 2) => 1.5,
+                (1, 3) => 20.0,
+                (1, 4) => 30.0,
+                _ => 1000.0,
+            }
+        };
+        // greedy would take piece 2 for slot 0
+        let res = assign_pieces(&slots, &used, dist);
+        assert_eq!(res.len(), 2);
+        assert_eq!(res[0].slot, 0);
+        assert_eq!(res[0].first_side, Side { fig: 3, side: 1 });
+        assert_eq!(res[0].cost, 2.0);
+        assert_eq!(res[0].confidence, -1.0);
+        assert_eq!(res[1].slot, 1);
+        assert_eq!(res[1].first_side, Side { fig: 2, side: 0 });
+        assert_eq!(res[1].cost, 1.5);
+        assert_eq!(res[1].confidence, 18.5);
+
+        assert!(assign_pieces(&slots, &[true, true, true, true, false], dist).is_empty());
+    }
+}
@@ -0,0 +1,192 @@
+use std::{
+    collections::BTreeSet,
+    time::{Duration, Instant},
+};
+
+use itertools::Itertools;
+
+use crate::{
+    borders_graph::Graph,
+    dsu::Dsu,
+    graph_solver::CandidatePlacement,
+    known_facts::KnownFacts,
+    parsed_puzzles::ParsedPuzzles,
+    placement::Placement,
+    solver_strategy::{SolverConfig, SolverStrategy},
+    solver_worker::ProgressSink,
+    utils::dedup_edges,
+};
+
+#[derive(Clone, Debug)]
+pub struct Accuracy {
+    // fraction of ground truth edges, which are present in the placement
+    pub neighbour: f64,
+    // fraction of ground truth figures in the same cell and rotation
+    pub direct: f64,
+    // fraction of edges added by the solver, which are correct
+    pub new_edges: f64,
+    pub cnt_new_edges: usize,
+    // in figures, only correct edges are used
+    pub largest_component: usize,
+    // edges of the candidate, which contradict the known placement, they are not applied
+    pub cnt_conflicts: usize,
+}
+
+// Candidate is applied on top of the placement from known facts, the same way as the UI does.
+pub fn evaluate(
+    candidate: &CandidatePlacement,
+    known_placement: &Placement,
+    truth_placement: &Placement,
+) -> Accuracy {
+    let mut placement = known_placement.clone();
+    let cnt_conflicts = candidate
+        .edges
+        .iter()
+        .filter(|&&(s1, s2)| placement.join_sides(s1, s2).is_none())
+        .count();
+    let truth_edges: BTreeSet<_> = dedup_edges(&truth_placement.get_all_neighbours())
+        .into_iter()
+        .collect();
+    let known_edges: BTreeSet<_> = dedup_edges(&known_placement.get_all_neighbours())
+        .into_iter()
+        .collect();
+    let all_edges = dedup_edges(&placement.get_all_neighbours());
+    let correct = all_edges
+        .iter()
+        .filter(|e| truth_edges.contains(e))
+        .collect_vec();
+    let new_edges = all_edges
+        .iter()
+        .filter(|e| !known_edges.contains(e))
+        .collect_vec();
+    let cnt_new_correct = new_edges.iter().filter(|e| truth_edges.contains(e)).count();
+
+    let n = truth_edges
+        .iter()
+        .chain(all_edges.iter())
+        .map(|(s1, s2)| s1.fig.max(s2.fig) + 1)
+        .max()
+        .unwrap_or(0);
+    let mut dsu = Dsu::new(n);
+    for (s1, s2) in correct.iter() {
+        dsu.unite(s1.fig, s2.fig);
+    }
+    let largest_component = correct
+        .iter()
+        .map(|(s1, _)| dsu.get_size(s1.fig))
+        .max()
+        .unwrap_or(0);
+
+    let direct = placement.count_same_cells(truth_placement);
+    let ratio = |a: usize, b: usize| if b == 0 { 0.0 } else { a as f64 / b as f64 };
+    Accuracy {
+        neighbour: ratio(correct.len(), truth_edges.len()),
+        direct: ratio(direct, truth_placement.get_cnt_figures()),
+        new_edges: ratio(cnt_new_correct, new_edges.len()),
+        cnt_new_edges: new_edges.len(),
+        largest_component,
+        cnt_conflicts,
+    }
+}
+
+pub struct BenchmarkResult {
+    pub strategy: &'static str,
+    pub cnt_candidates: usize,
+    // of the best ranked candidate, None if nothing was found
+    pub accuracy: Option<Accuracy>,
+    pub runtime: Duration,
+}
+
+impl BenchmarkResult {
+    pub fn describe(&self) -> String {
+        let accuracy = match &self.accuracy {
+            Some(a) => format!(
+                "neighbour {:.3}, direct {:.3}, new edges {:.3} ({}), largest component {}, \
+                conflicts {}",
+                a.neighbour,
+                a.direct,
+                a.new_edges,
+                a.cnt_new_edges,
+                a.largest_component,
+                a.cnt_conflicts
+            ),
+            None => "no candidates".to_owned(),
+        };
+        format!(
+            "{}: {} candidates in {:?}. {}",
+            self.strategy, self.cnt_candidates, self.runtime, accuracy
+        )
+    }
+}
+
+// Figures of the ground truth component with `start_vertex` in the upper half of its rows,
+// None if `start_vertex` is not in the ground truth.
+pub fn seed_figures(truth_placement: &Placement, start_vertex: usize) -> Option<BTreeSet<usize>> {
+    let cells = truth_placement
+        .get_cells_by_component()
+        .into_iter()
+        .find(|cells| cells.iter().any(|(_, sides)| sides[0].fig == start_vertex))?;
+    let max_row = cells.iter().map(|((row, _), _)| *row).max()?;
+    Some(
+        cells
+            .iter()
+            .filter(|((row, _), _)| *row <= max_row / 2)
+            .map(|(_, sides)| sides[0].fig)
+            .collect(),
+    )
+}
+
+// Good facts about ground truth edges are kept only between `seed` figures, so solvers
+// don't get the answer as input. Everything else is kept as is.
+pub fn hold_out_truth(
+    known_facts: &KnownFacts,
+    truth_placement: &Placement,
+    seed: &BTreeSet<usize>,
+) -> KnownFacts {
+    let truth_edges: BTreeSet<_> = dedup_edges(&truth_placement.get_all_neighbours())
+        .into_iter()
+        .collect();
+    let facts = known_facts
+        .facts
+        .iter()
+        .filter(|fact| {
+            let in_seed = seed.contains(&fact.side1.fig) && seed.contains(&fact.side2.fig);
+            !fact.good_edge || in_seed || !truth_edges.contains(&(fact.side1, fact.side2))
+        })
+        .cloned()
+        .collect_vec();
+    let mut res = KnownFacts::from_facts(facts);
+    res.piece_facts = known_facts.piece_facts.clone();
+    res.board_size = known_facts.board_size;
+    res
+}
+
+// Known facts shouldn't contain the ground truth itself, see [hold_out_truth]. Ground
+// truth is converted into placement by the caller, see [GroundTruth::to_placement].
+pub fn run_benchmark(
+    strategy: &dyn SolverStrategy,
+    graph: &Graph,
+    parsed_puzzles: &ParsedPuzzles,
+    known_facts: &KnownFacts,
+    config: &SolverConfig,
+    truth_placement: &Placement,
+) -> BenchmarkResult {
+    let start = Instant::now();
+    let candidates = strategy.solve_ranked(
+        graph,
+        parsed_puzzles,
+        known_facts,
+        config,
+        &ProgressSink::none(),
+    );
+    let runtime = start.elapsed();
+    let known_placement = known_facts.build_placement();
+    BenchmarkResult {
+        strategy: strategy.name(),
+        cnt_candidates: candidates.len(),
+        accuracy: candidates
+            .first()
+            .map(|best| evaluate(best, &known_placement, truth_placement)),
+        runtime,
+    }
+}
@@ -0,0 +1,86 @@
+#[cfg(test)]
+mod tests {
+    use crate::{
+        benchmark::{evaluate, hold_out_truth, seed_figures},
+        graph_solver::CandidatePlacement,
+        known_facts::{Fact, KnownFacts},
+        placement::Placement,
+        utils::{dedup_edges, Side},
+    };
+
+    fn side(fig: usize, side: usize) -> Side {
+        Side { fig, side }
+    }
+
+    #[test]
+    pub fn half_correct_candidate() {
+        // 0 1
+        // 2 3
+        let mut truth_placement = Placement::new();
+        truth_placement.join_sides(side(0, 1), side(1, 3)).unwrap();
+        truth_placement.join_sides(side(0, 2), side(2, 0)).unwrap();
+        truth_placement.join_sides(side(2, 1), side(3, 3)).unwrap();
+        let mut known_placement = Placement::new();
+        known_placement.join_sides(side(0, 1), side(1, 3)).unwrap();
+
+        // 2 is correct, 4 is placed instead of 3
+        let candidate = CandidatePlacement::from_edges(
+            vec![(side(0, 2), side(2, 0)), (side(2, 1), side(4, 3))],
+            0.0,
+            String::new(),
+        );
+        let accuracy = evaluate(&candidate, &known_placement, &truth_placement);
+        assert_eq!(accuracy.neighbour, 0.5);
+        assert_eq!(accuracy.direct, 0.75);
+        // 4 also touches 1
+        assert_eq!(accuracy.cnt_new_edges, 3);
+        assert!((accuracy.new_edges - 1.0 / 3.0).abs() < 1e-9);
+        assert_eq!(accuracy.largest_component, 3);
+
+        let perfect = CandidatePlacement::from_edges(
+            truth_placement.get_all_neighbours(),
+            0.0,
+            String::new(),
+        );
+        let accuracy = evaluate(&perfect, &known_placement, &truth_placement);
+        assert_eq!(accuracy.neighbour, 1.0);
+        assert_eq!(accuracy.direct, 1.0);
+        assert_eq!(accuracy.cnt_conflicts, 0);
+
+        // right side of 0 is already taken by 1
+        let conflicting = CandidatePlacement::from_edges(
+            vec![(side(0, 2), side(2, 0)), (side(0, 1), side(3, 3))],
+            0.0,
+            String::new(),
+        );
+        let accuracy = evaluate(&conflicting, &known_placement, &truth_placement);
+        assert_eq!(accuracy.cnt_conflicts, 1);
+        assert_eq!(accuracy.cnt_new_edges, 1);
+    }
+
+    #[test]
+    pub fn truth_is_held_out() {
+        let mut truth_placement = Placement::new();
+        truth_placement.join_sides(side(0, 1), side(1, 3)).unwrap();
+        truth_placement.join_sides(side(0, 2), side(2, 0)).unwrap();
+        truth_placement.join_sides(side(2, 1), side(3, 3)).unwrap();
+        assert!(seed_figures(&truth_placement, 5).is_none());
+        let seed = seed_figures(&truth_placement, 0).unwrap();
+        assert_eq!(seed.len(), 2);
+        assert!(seed.contains(&0));
+
+        let mut facts = dedup_edges(&truth_placement.get_all_neighbours())
+            .into_iter()
+            .map(|(s1, s2)| Fact::new(s1, s2, true))
+            .collect::<Vec<_>>();
+        facts.push(Fact::new(side(0, 0), side(4, 2), true));
+        facts.push(Fact::new(side(1, 2), side(2, 2), false));
+        let known_facts = KnownFacts::from_facts(facts);
+        let held_out = hold_out_truth(&known_facts, &truth_placement, &seed);
+        // one edge inside the seed, and facts which are not about the ground truth
+        assert_eq!(held_out.facts.len(), 3);
+        let placement = held_out.build_placement();
+        let edges = dedup_edges(&placement.get_all_neighbours_in_same_component(0));
+        assert_eq!(edges.len(), 2);
+    }
+}
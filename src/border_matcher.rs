@@ -3,7 +3,9 @@ use std::cmp::min;
 use itertools::Itertools;
 
 use crate::{
+    config::MatcherConfig,
     coordinate_system::CoordinateSystem,
+    edge_scoring_params::EdgeScoringParams,
     figure::Figure,
     parsed_puzzles::ParsedPuzzles,
     point::{find_center, PointF},
@@ -17,8 +19,6 @@ pub struct BorderAndNeighbors {
 }
 
 impl BorderAndNeighbors {
-    pub const NEIGHBORS: usize = 10;
-
     pub fn reverse(&self) -> Self {
         let mut border = self.border.clone();
         border.reverse();
@@ -31,18 +31,17 @@ impl BorderAndNeighbors {
 }
 
 // smaller -> better
-pub fn match_placed_borders_center(lhs: &[PointF], rhs: &[PointF]) -> f64 {
+pub fn match_placed_borders_center(lhs: &[PointF], rhs: &[PointF], config: &MatcherConfig) -> f64 {
     // TODO: smarter logic
 
-    const CHECK_NEXT: usize = 3;
-    const OK_MIN_DIFF: f64 = 0.2;
+    let params = &config.scoring;
     let score_one_side = |lhs: &[PointF], rhs: &[PointF]| -> f64 {
         let socket_detector = SocketDetector::new(lhs);
 
         let mut iter = 0;
         let mut sum_dists = 0.0;
         for p in lhs.iter() {
-            let dists = rhs[iter..min(iter + CHECK_NEXT, rhs.len())]
+            let dists = rhs[iter..min(iter + config.check_next, rhs.len())]
                 .iter()
                 .map(|rhs_p| p.dist2(rhs_p))
                 .collect_vec();
@@ -54,12 +53,12 @@ pub fn match_placed_borders_center(lhs: &[PointF], rhs: &[PointF]) -> f64 {
             }
             iter += min_shift;
             let coef = if socket_detector.is_socket_point(p) {
-                1.0
+                params.socket_weight
             } else {
-                6.0
+                params.plain_weight
             };
 
-            sum_dists += coef * fmax(0.0, dists[min_shift] - OK_MIN_DIFF);
+            sum_dists += coef * fmax(0.0, dists[min_shift] - params.center_ok_min_diff);
         }
         sum_dists / (lhs.len() as f64)
     };
@@ -171,7 +170,11 @@ fn find_polynomial_coefs(pts: &[PointF]) -> Vec<f64> {
     res
 }
 
-fn interpolate_with_polynomial_score(pts: &[PointF], debug: bool) -> (f64, Vec<PointF>) {
+fn interpolate_with_polynomial_score(
+    pts: &[PointF],
+    params: &EdgeScoringParams,
+    debug: bool,
+) -> (f64, Vec<PointF>) {
     let first = pts[0];
     let last = *pts.last().unwrap();
     let dir = (last - first).norm();
@@ -185,20 +188,17 @@ fn interpolate_with_polynomial_score(pts: &[PointF], debug: bool) -> (f64, Vec<P
         .collect_vec();
     // let mut coefs = vec![0.0; 3];
 
-    const OK_MIN_DIFF: f64 = 0.25;
-    const MULTIPLIER: f64 = 30.0;
-
     let calc_score = |coefs: &[f64]| -> f64 {
         normalized
             .iter()
             .map(|p| {
                 let poly_y = coefs[0] + coefs[1] * p.x + coefs[2] * p.x * p.x;
                 let diff_y = (poly_y - p.y).abs();
-                let diff_y = fmax(0.0, diff_y - OK_MIN_DIFF);
+                let diff_y = fmax(0.0, diff_y - params.poly_ok_min_diff);
                 diff_y * diff_y
             })
             .sum::<f64>()
-            * MULTIPLIER
+            * params.poly_multiplier
             / normalized.len() as f64
     };
     // local_optimize(&mut coefs, calc_score, 1000);
@@ -220,22 +220,31 @@ fn interpolate_with_polynomial_score(pts: &[PointF], debug: bool) -> (f64, Vec<P
     (calc_score(&coefs), debug_points)
 }
 
-pub fn match_side_borders_v2(lhs: &[PointF], rhs: &[PointF], debug: bool) -> (f64, Vec<PointF>) {
-    const REM_FIRST: usize = 2;
-    if lhs.len() <= REM_FIRST || rhs.len() <= REM_FIRST {
+pub fn match_side_borders_v2(
+    lhs: &[PointF],
+    rhs: &[PointF],
+    config: &MatcherConfig,
+    debug: bool,
+) -> (f64, Vec<PointF>) {
+    let rem_first = config.rem_first;
+    if lhs.len() <= rem_first || rhs.len() <= rem_first {
         return (f64::MAX / 100.0, vec![]);
     }
-    let lhs = &lhs[REM_FIRST..];
-    let rhs = &rhs[REM_FIRST..];
+    let lhs = &lhs[rem_first..];
+    let rhs = &rhs[rem_first..];
 
     let sum_pts = lhs.iter().rev().chain(rhs.iter()).cloned().collect_vec();
-    interpolate_with_polynomial_score(&sum_pts, debug)
+    interpolate_with_polynomial_score(&sum_pts, &config.scoring, debug)
 }
 
-pub fn match_placed_borders(lhs: &BorderAndNeighbors, rhs: &BorderAndNeighbors) -> f64 {
-    let center = match_placed_borders_center(&lhs.border, &rhs.border);
-    let prev = match_side_borders_v2(&lhs.prev, &rhs.prev, false).0;
-    let next = match_side_borders_v2(&lhs.next, &rhs.next, false).0;
+pub fn match_placed_borders(
+    lhs: &BorderAndNeighbors,
+    rhs: &BorderAndNeighbors,
+    config: &MatcherConfig,
+) -> f64 {
+    let center = match_placed_borders_center(&lhs.border, &rhs.border, config);
+    let prev = match_side_borders_v2(&lhs.prev, &rhs.prev, config, false).0;
+    let next = match_side_borders_v2(&lhs.next, &rhs.next, config, false).0;
     center + (prev + next)
 }
 
@@ -311,14 +320,16 @@ fn get_figure_border(figure: &Figure, border_id: usize) -> Vec<PointF> {
     res
 }
 
-fn estimate_coordinate_system_by_border(border: &[PointF]) -> Option<CoordinateSystem> {
-    const OFFSET: usize = 5;
-    if border.len() <= OFFSET * 2 + 3 {
+fn estimate_coordinate_system_by_border(
+    border: &[PointF],
+    offset: usize,
+) -> Option<CoordinateSystem> {
+    if border.len() <= offset * 2 + 3 {
         return None;
     }
     let mid = border.len() / 2;
-    let p1 = find_center(&border[OFFSET..mid]);
-    let p2 = find_center(&border[mid..border.len() - OFFSET]);
+    let p1 = find_center(&border[offset..mid]);
+    let p2 = find_center(&border[mid..border.len() - offset]);
     if p1 == p2 {
         return None;
     }
@@ -329,6 +340,7 @@ pub fn local_optimize_coordinate_systems(
     start_cs: &[CoordinateSystem],
     mut scorer: impl FnMut(&[CoordinateSystem]) -> f64,
     only_use_ids: &[usize],
+    config: &MatcherConfig,
 ) -> Vec<CoordinateSystem> {
     let start_score = scorer(&start_cs);
     let mut last_score = start_score;
@@ -336,8 +348,6 @@ pub fn local_optimize_coordinate_systems(
     // TODO: think about constants
     let mut start_coord_step = 10.0;
     let mut dir_step = 0.1;
-    const MIN_EPS: f64 = 1e-2;
-    const MULT: f64 = 0.3;
     let moves = vec![
         PointF { x: 1.0, y: 0.0 },
         PointF { x: -1.0, y: 0.0 },
@@ -346,18 +356,16 @@ pub fn local_optimize_coordinate_systems(
     ];
     let mut cs = start_cs.to_vec();
     let mut changed_steps = 0;
-    const MAX_CHANGED_STEPS: usize = 5;
-    const ITERS_INSIDE: usize = 10;
-    while start_coord_step > MIN_EPS || dir_step > MIN_EPS {
+    while start_coord_step > config.cs_min_eps || dir_step > config.cs_min_eps {
         changed_steps += 1;
-        if changed_steps > MAX_CHANGED_STEPS {
+        if changed_steps > config.cs_max_changed_steps {
             changed_steps = 0;
         }
         {
             let mut changed = false;
             for &cs_id in only_use_ids.iter() {
                 for mv in moves.iter() {
-                    for _it in 0..ITERS_INSIDE {
+                    for _it in 0..config.cs_iters_inside {
                         let ncs = CoordinateSystem::new(
                             cs[cs_id].start + *mv * start_coord_step,
                             cs[cs_id].x_dir,
@@ -375,15 +383,15 @@ pub fn local_optimize_coordinate_systems(
                     }
                 }
             }
-            if !changed || changed_steps == MAX_CHANGED_STEPS {
-                start_coord_step *= MULT;
+            if !changed || changed_steps == config.cs_max_changed_steps {
+                start_coord_step *= config.cs_step_mult;
             }
         }
         {
             let mut changed = false;
             for &cs_id in only_use_ids.iter() {
                 for mv in moves.iter() {
-                    for _it in 0..ITERS_INSIDE {
+                    for _it in 0..config.cs_iters_inside {
                         let ncs = CoordinateSystem::new(
                             cs[cs_id].start,
                             cs[cs_id].x_dir + *mv * dir_step,
@@ -401,8 +409,8 @@ pub fn local_optimize_coordinate_systems(
                     }
                 }
             }
-            if !changed || changed_steps == MAX_CHANGED_STEPS {
-                dir_step *= MULT;
+            if !changed || changed_steps == config.cs_max_changed_steps {
+                dir_step *= config.cs_step_mult;
             }
         }
     }
@@ -427,9 +435,13 @@ impl SocketDetector {
         }
     }
 
+    // distance to the line between the first and the last point, relative to the length
+    pub fn deviation(&self, p: &PointF) -> f64 {
+        ((p.x - self.start.x) * self.dir.y - self.dir.x * (p.y - self.start.y)).abs() / self.len
+    }
+
     pub fn is_socket_point(&self, p: &PointF) -> bool {
-        ((p.x - self.start.x) * self.dir.y - self.dir.x * (p.y - self.start.y)).abs()
-            > 0.1 * self.len
+        self.deviation(p) > 0.1
     }
 }
 
@@ -443,16 +455,29 @@ pub fn is_picture_border(figure: &Figure, border_id: usize) -> bool {
     is_picture_border_impl(&pts)
 }
 
-fn get_figure_border_and_neighbors(figure: &Figure, border_id: usize) -> BorderAndNeighbors {
+// smaller -> more likely to be a picture border
+pub fn picture_border_deviation(figure: &Figure, border_id: usize) -> f64 {
+    let pts = get_figure_border(figure, border_id);
+    let socket_detector = SocketDetector::new(&pts);
+    pts.iter()
+        .map(|p| socket_detector.deviation(p))
+        .fold(0.0, fmax)
+}
+
+fn get_figure_border_and_neighbors(
+    figure: &Figure,
+    border_id: usize,
+    neighbors: usize,
+) -> BorderAndNeighbors {
     let mut prev = get_figure_border(figure, (border_id + 3) % 4);
     {
-        let sz = min(BorderAndNeighbors::NEIGHBORS, prev.len());
+        let sz = min(neighbors, prev.len());
         prev.rotate_right(sz);
         prev.truncate(sz);
         prev.reverse();
     }
     let mut next = get_figure_border(figure, (border_id + 1) % 4);
-    next.truncate(BorderAndNeighbors::NEIGHBORS);
+    next.truncate(neighbors);
 
     BorderAndNeighbors {
         border: get_figure_border(figure, border_id),
@@ -465,12 +490,13 @@ pub fn match_borders(
     parsed_puzzles: &ParsedPuzzles,
     side1: Side,
     side2: Side,
+    config: &MatcherConfig,
 ) -> Option<MatchResult> {
     let lhs_figure = &parsed_puzzles.figures[side1.fig];
     let rhs_figure = &parsed_puzzles.figures[side2.fig];
 
-    let lhs = get_figure_border_and_neighbors(&lhs_figure, side1.side);
-    let rhs = get_figure_border_and_neighbors(&rhs_figure, side2.side);
+    let lhs = get_figure_border_and_neighbors(lhs_figure, side1.side, config.scoring.neighbors);
+    let rhs = get_figure_border_and_neighbors(rhs_figure, side2.side, config.scoring.neighbors);
     let rhs = rhs.reverse();
 
     if is_picture_border_impl(&lhs.border) || is_picture_border_impl(&rhs.border) {
@@ -489,8 +515,8 @@ pub fn match_borders(
         return None;
     }
 
-    let to_cs = estimate_coordinate_system_by_border(&lhs.border)?;
-    let from_cs_estimation = estimate_coordinate_system_by_border(&rhs.border)?;
+    let to_cs = estimate_coordinate_system_by_border(&lhs.border, config.cs_offset)?;
+    let from_cs_estimation = estimate_coordinate_system_by_border(&rhs.border, config.cs_offset)?;
 
     let conv_point =
         |from_cs: &CoordinateSystem, p: PointF| -> PointF { to_cs.to_real(from_cs.create(p)) };
@@ -506,19 +532,22 @@ pub fn match_borders(
         }
     };
 
-    let from_cs = if match_placed_borders(&lhs, &move_rhs(&from_cs_estimation)) > 100.0 {
+    let from_cs = if match_placed_borders(&lhs, &move_rhs(&from_cs_estimation), config)
+        > config.max_score_to_optimize
+    {
         from_cs_estimation
     } else {
         local_optimize_coordinate_systems(
             &[from_cs_estimation],
-            |from_cs| match_placed_borders(&lhs, &move_rhs(&from_cs[0])),
+            |from_cs| match_placed_borders(&lhs, &move_rhs(&from_cs[0]), config),
             &[0],
+            config,
         )[0]
         .clone()
     };
 
     let res = MatchResult::new(
-        match_placed_borders(&lhs, &move_rhs(&from_cs)),
+        match_placed_borders(&lhs, &move_rhs(&from_cs), config),
         lhs_figure.border.iter().map(|p| p.conv_f64()).collect_vec(),
         rhs_figure
             .border
@@ -538,14 +567,15 @@ pub fn match_borders_without_move(
     rhs_border_id: usize,
     lhs_id: usize,
     rhs_id: usize,
+    config: &MatcherConfig,
 ) -> Option<MatchResult> {
-    let lhs = get_figure_border_and_neighbors(&lhs_figure, lhs_border_id);
-    let mut rhs = get_figure_border_and_neighbors(&rhs_figure, rhs_border_id);
+    let lhs = get_figure_border_and_neighbors(lhs_figure, lhs_border_id, config.scoring.neighbors);
+    let rhs = get_figure_border_and_neighbors(rhs_figure, rhs_border_id, config.scoring.neighbors);
     let rhs = rhs.reverse();
 
-    let score = match_placed_borders(&lhs, &rhs);
+    let score = match_placed_borders(&lhs, &rhs, config);
 
-    if score > 30.0 {
+    if score > config.max_score_without_move {
         return None;
     }
 
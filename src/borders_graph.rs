@@ -1,7 +1,8 @@
-use std::collections::BTreeSet;
+use std::{collections::BTreeSet, fs};
 
 use crate::{
     border_matcher::{match_borders, match_borders_without_move},
+    config::MatcherConfig,
     parsed_puzzles::ParsedPuzzles,
     placement::Placement,
     point::PointF,
@@ -45,8 +46,21 @@ pub struct Graph {
     pub n: usize,
     pub all_edges: Vec<Edge>,
     pub parsed_puzzles_hash: u64,
+    // of the `MatcherConfig` used for scores, the graph should be rebuilt when it changes
+    #[serde(default)]
+    pub matcher_hash: u64,
 }
 impl Graph {
+    pub fn load(path: &str, matcher: &MatcherConfig) -> Self {
+        let graph: Self = serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+        assert_eq!(
+            graph.matcher_hash,
+            matcher.calc_hash(),
+            "{path} is built with other matcher params, rebuild it"
+        );
+        graph
+    }
+
     pub fn get_subgraph(&self, placement: &Placement) -> Self {
         let all_sides: BTreeSet<_> = placement.get_all_neighbours().into_iter().collect();
         let all_edges = self
@@ -58,11 +72,13 @@ impl Graph {
         Self {
             n: self.n,
             parsed_puzzles_hash: self.parsed_puzzles_hash,
+            matcher_hash: self.matcher_hash,
             all_edges,
         }
     }
 
-    pub fn new(parsed_puzzles: &ParsedPuzzles, only_borders: bool) -> Self {
+    pub fn new(parsed_puzzles: &ParsedPuzzles, only_borders: bool, params: &MatcherConfig) -> Self {
+        eprintln!("Matcher config: {params:?}");
         let mut all_edges = vec![];
         let figures = &parsed_puzzles.figures;
 
@@ -113,9 +129,10 @@ impl Graph {
                                 side2,
                                 fig1,
                                 fig2,
+                                params,
                             )
                             .is_some();
-                            if let Some(res) = match_borders(parsed_puzzles, s1, s2) {
+                            if let Some(res) = match_borders(parsed_puzzles, s1, s2, params) {
                                 let score = res.score;
                                 let (i1, i2) =
                                     parsed_puzzles.figures[s2.fig].get_cs_points_indexes();
@@ -147,6 +164,7 @@ impl Graph {
             n: parsed_puzzles.figures.len(),
             all_edges,
             parsed_puzzles_hash: parsed_puzzles.calc_hash(),
+            matcher_hash: params.calc_hash(),
         }
     }
 
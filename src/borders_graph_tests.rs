@@ -0,0 +1,38 @@
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::{borders_graph::Graph, config::MatcherConfig};
+
+    fn save_graph(name: &str, matcher: &MatcherConfig) -> String {
+        let graph = Graph {
+            n: 2,
+            all_edges: vec![],
+            parsed_puzzles_hash: 0,
+            matcher_hash: matcher.calc_hash(),
+        };
+        let path = std::env::temp_dir().join(name);
+        let path = path.to_str().unwrap().to_owned();
+        fs::write(&path, serde_json::to_string(&graph).unwrap()).unwrap();
+        path
+    }
+
+    #[test]
+    pub fn load_with_same_matcher() {
+        let matcher = MatcherConfig::default();
+        let path = save_graph("graph_same_matcher_test.json", &matcher);
+        assert_eq!(Graph::load(&path, &matcher).n, 2);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "other matcher params")]
+    pub fn load_with_tuned_matcher() {
+        let matcher = MatcherConfig::default();
+        let path = save_graph("graph_tuned_matcher_test.json", &matcher);
+        let mut tuned = matcher.clone();
+        tuned.scoring.socket_weight *= 1.5;
+        assert_ne!(tuned.calc_hash(), matcher.calc_hash());
+        Graph::load(&path, &tuned);
+    }
+}
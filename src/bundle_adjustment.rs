@@ -0,0 +1,174 @@
+use itertools::Itertools;
+
+use crate::{
+    config::SurfaceConfig,
+    parsed_puzzles::ParsedPuzzles,
+    placement::Placement,
+    point::{find_center, PointF},
+    pose_refinement::{gen_correspondences, refine_poses, rms, Correspondence, ANCHOR},
+    utils::{dedup_edges, gauss, Side},
+};
+
+#[derive(Clone, Copy, Debug)]
+pub struct EdgeResidual {
+    pub s1: Side,
+    pub s2: Side,
+    // between matched borders, in pixels
+    pub rms: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct AdjustmentReport {
+    pub start_rms: f64,
+    pub final_rms: f64,
+    // worst first
+    pub residuals: Vec<EdgeResidual>,
+}
+
+impl AdjustmentReport {
+    pub fn describe(&self) -> String {
+        let worst = match self.residuals.first() {
+            Some(r) => format!(", worst edge {}-{}: {:.3}", r.s1.fig, r.s2.fig, r.rms),
+            None => String::new(),
+        };
+        format!(
+            "rms {:.3} -> {:.3} over {} edges{worst}",
+            self.start_rms,
+            self.final_rms,
+            self.residuals.len()
+        )
+    }
+}
+
+// Cell (col, row) of the component is expected around `origin + col_step * col + row_step * row`.
+struct GridFrame {
+    origin: PointF,
+    col_step: PointF,
+    row_step: PointF,
+}
+
+impl GridFrame {
+    // Least squares by figure centers, None if the component is one row or column.
+    fn fit(centers: &[((usize, usize), PointF)]) -> Option<Self> {
+        let cnt_cols = centers.iter().map(|((c, _), _)| c).unique().count();
+        let cnt_rows = centers.iter().map(|((_, r), _)| r).unique().count();
+        if cnt_cols < 2 || cnt_rows < 2 {
+            return None;
+        }
+        let solve = |coord: &dyn Fn(PointF) -> f64| {
+            let mut equations = vec![vec![0.0; 4]; 3];
+            for &((c, r), center) in centers.iter() {
+                let x = [1.0, c as f64, r as f64];
+                for i in 0..3 {
+                    for j in 0..3 {
+                        equations[i][j] += x[i] * x[j];
+                    }
+                    equations[i][3] -= x[i] * coord(center);
+                }
+            }
+            gauss(&mut equations)
+        };
+        let xs = solve(&|p| p.x);
+        let ys = solve(&|p| p.y);
+        Some(Self {
+            origin: PointF { x: xs[0], y: ys[0] },
+            col_step: PointF { x: xs[1], y: ys[1] },
+            row_step: PointF { x: xs[2], y: ys[2] },
+        })
+    }
+
+    fn cell_center(&self, col: usize, row: usize) -> PointF {
+        self.origin + self.col_step * (col as f64) + self.row_step * (row as f64)
+    }
+}
+
+// Soft prior: centers of figures should stay on the grid of their component.
+fn gen_grid_prior(
+    cells: &[Vec<((usize, usize), usize)>],
+    positions: &[Option<Vec<PointF>>],
+    weight: f64,
+) -> Vec<Correspondence> {
+    let mut res = vec![];
+    for component in cells.iter() {
+        let centers = component
+            .iter()
+            .filter_map(|&(cell, fig)| Some((cell, find_center(positions[fig].as_ref()?))))
+            .collect_vec();
+        let frame = match GridFrame::fit(&centers) {
+            Some(frame) => frame,
+            None => continue,
+        };
+        for (&(_, fig), &((col, row), center)) in component
+            .iter()
+            .filter(|(_, fig)| positions[*fig].is_some())
+            .zip(centers.iter())
+        {
+            res.push(Correspondence {
+                fig1: fig,
+                p1: center,
+                fig2: ANCHOR,
+                p2: frame.cell_center(col, row),
+                weight,
+            });
+        }
+    }
+    res
+}
+
+// All placed figures of `placement` are moved together. Figures without positions are skipped.
+pub fn adjust_placement(
+    parsed_puzzles: &ParsedPuzzles,
+    placement: &Placement,
+    positions: &mut [Option<Vec<PointF>>],
+    config: &SurfaceConfig,
+) -> AdjustmentReport {
+    let edges = dedup_edges(&placement.get_all_neighbours())
+        .into_iter()
+        .filter(|(s1, s2)| positions[s1.fig].is_some() && positions[s2.fig].is_some())
+        .collect_vec();
+    let cells = placement
+        .get_cells_by_component()
+        .into_iter()
+        .map(|component| {
+            component
+                .into_iter()
+                .map(|(cell, sides)| (cell, sides[0].fig))
+                .collect_vec()
+        })
+        .collect_vec();
+
+    let start_rms = rms(&gen_correspondences(
+        parsed_puzzles,
+        &edges,
+        positions,
+        config.pose_samples,
+    ));
+    let final_rms = refine_poses(
+        parsed_puzzles,
+        &edges,
+        positions,
+        &[],
+        config,
+        |positions| gen_grid_prior(&cells, positions, config.grid_prior_weight),
+    );
+
+    let residuals = edges
+        .iter()
+        .map(|&(s1, s2)| EdgeResidual {
+            s1,
+            s2,
+            rms: rms(&gen_correspondences(
+                parsed_puzzles,
+                &[(s1, s2)],
+                positions,
+                config.pose_samples,
+            )),
+        })
+        .sorted_by(|r1, r2| r2.rms.total_cmp(&r1.rms))
+        .collect_vec();
+    AdjustmentReport {
+        start_rms,
+        final_rms,
+        residuals,
+    }
+}
@@ -0,0 +1,41 @@
+#[cfg(test)]
+mod tests {
+    use crate::{
+        bundle_adjustment::adjust_placement,
+        config::SurfaceConfig,
+        parsed_puzzles::ParsedPuzzles,
+        point::{find_center, PointF},
+        synthetic::{gen_pattern_image, SyntheticPuzzle},
+        utils::dedup_edges,
+    };
+
+    #[test]
+    pub fn fix_moved_figures() {
+        let source = gen_pattern_image(400, 300, 1);
+        let puzzle = SyntheticPuzzle::generate(&source, 3, 4, 2);
+        let parsed_puzzles = ParsedPuzzles::new(&puzzle.image);
+        let placement = puzzle
+            .to_ground_truth(&parsed_puzzles)
+            .to_placement(&|_, _| 0.0)
+            .unwrap();
+        let mut positions = puzzle.assembled_positions(&parsed_puzzles);
+        let config = SurfaceConfig::default();
+        let truth = adjust_placement(&parsed_puzzles, &placement, &mut positions, &config);
+
+        for (fig, angle, shift) in [(0, 0.05, 6.0), (5, -0.03, -4.0)] {
+            let pts = positions[fig].as_mut().unwrap();
+            let center = find_center(pts);
+            for p in pts.iter_mut() {
+                *p = center + (*p - center).rotate(angle) + PointF { x: shift, y: shift };
+            }
+        }
+        let report = adjust_placement(&parsed_puzzles, &placement, &mut positions, &config);
+        assert_eq!(
+            report.residuals.len(),
+            dedup_edges(&placement.get_all_neighbours()).len()
+        );
+        assert!(report.start_rms > truth.final_rms + 1.0, "{report:?}");
+        assert!(report.final_rms < truth.final_rms + 0.2, "{report:?}");
+        assert!(report.residuals[0].rms >= report.residuals.last().unwrap().rms);
+    }
+}
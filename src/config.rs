@@ -0,0 +1,125 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    fs,
+    hash::{Hash, Hasher},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{edge_scoring_params::EdgeScoringParams, solver_strategy::SolverConfig};
+
+pub const DEFAULT_PATH: &str = "config.json";
+
+// How borders are matched, see `border_matcher`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct MatcherConfig {
+    // tuned by `tune_edge_scoring`
+    pub scoring: EdgeScoringParams,
+    // positions on the surface are optimized with the hand-picked params, tuned ones
+    // are only for ranking
+    pub placement_scoring: EdgeScoringParams,
+    // points of the other border checked for each point in the center term
+    pub check_next: usize,
+    // points near the corner, which are skipped in polynomial terms
+    pub rem_first: usize,
+    // points near corners, which are skipped when coordinate system is estimated
+    pub cs_offset: usize,
+    // local optimization of coordinate systems
+    pub cs_min_eps: f64,
+    pub cs_step_mult: f64,
+    pub cs_max_changed_steps: usize,
+    pub cs_iters_inside: usize,
+    // estimated coordinate system with a bigger score is not optimized locally
+    pub max_score_to_optimize: f64,
+    // borders, which are not moved, with a bigger score are not matched
+    pub max_score_without_move: f64,
+}
+
+impl Default for MatcherConfig {
+    fn default() -> Self {
+        Self {
+            scoring: EdgeScoringParams::default(),
+            placement_scoring: EdgeScoringParams::default(),
+            check_next: 3,
+            rem_first: 2,
+            cs_offset: 5,
+            cs_min_eps: 1e-2,
+            cs_step_mult: 0.3,
+            cs_max_changed_steps: 5,
+            cs_iters_inside: 10,
+            max_score_to_optimize: 100.0,
+            max_score_without_move: 30.0,
+        }
+    }
+}
+
+impl MatcherConfig {
+    // floats can't be hashed directly, so the serialized config is
+    pub fn calc_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        serde_json::to_string(self).unwrap().hash(&mut hasher);
+        hasher.finish()
+    }
+
+    pub fn for_placement(&self) -> Self {
+        Self {
+            scoring: self.placement_scoring.clone(),
+            ..self.clone()
+        }
+    }
+}
+
+// How figures are placed on the surface, see `surface_placer`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct SurfaceConfig {
+    // components with more figures are placed incrementally
+    pub big_component: usize,
+    // positions of components with at least this number of figures are cached
+    pub cache_component: usize,
+    // pose refinement: points sampled on each border, max correspondence rebuilds,
+    // and min decrease of RMS distance to continue
+    pub pose_samples: usize,
+    pub pose_iters: usize,
+    pub pose_min_improvement: f64,
+    // global refinement: weight of keeping figure centers on the grid, relative to
+    // one pair of matched border points
+    pub grid_prior_weight: f64,
+}
+
+impl Default for SurfaceConfig {
+    fn default() -> Self {
+        Self {
+            big_component: 26,
+            cache_component: 26,
+            pose_samples: 16,
+            pose_iters: 20,
+            pose_min_improvement: 0.01,
+            grid_prior_weight: 1.0,
+        }
+    }
+}
+
+// Everything, which could be changed without recompiling.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct Config {
+    pub matcher: MatcherConfig,
+    pub solver: SolverConfig,
+    pub surface: SurfaceConfig,
+}
+
+impl Config {
+    pub fn load() -> Self {
+        if let Ok(content) = fs::read_to_string(DEFAULT_PATH) {
+            serde_json::from_str(&content).unwrap()
+        } else {
+            Self::default()
+        }
+    }
+
+    pub fn save(&self) {
+        fs::write(DEFAULT_PATH, serde_json::to_string_pretty(self).unwrap()).unwrap();
+    }
+}
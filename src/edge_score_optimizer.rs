@@ -1,13 +1,17 @@
 use std::collections::BTreeMap;
 
 use itertools::Itertools;
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
 use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
 
 use crate::{
     border_matcher::{match_borders, match_side_borders_v2},
     borders_graph::Graph,
+    config::{Config, MatcherConfig},
+    edge_scoring_params::EdgeScoringParams,
     graph_solver::{find_sides_by_known_edge, PlacedFigure, PotentialSolution},
-    known_positions::get_known_placement,
+    ground_truth::load_known_placement,
+    known_facts::KnownFacts,
     parsed_puzzles::ParsedPuzzles,
     placement::Placement,
     point::PointF,
@@ -41,14 +45,16 @@ pub fn optimize_edge_scores(
     parsed_puzzles: &ParsedPuzzles,
     graph: &Graph,
     calc_new_scores: bool,
+    config: &Config,
 ) -> Vec<PotentialSolution> {
     let base_points_matrix = graph.get_base_points_matrix();
     let dist = graph.gen_adj_matrix();
     let dist = |s1: Side, s2: Side| -> f64 { dist[[s1.fig, s1.side, s2.fig, s2.side]] };
-    let placement = get_known_placement(graph);
+    let placement = load_known_placement(graph);
     let known_sides = dedup_edges(&placement.get_all_neighbours());
 
     let all_sides: Vec<Side> = parsed_puzzles.gen_all_sides();
+    let params = &config.matcher;
 
     let mut places = BTreeMap::new();
 
@@ -68,13 +74,13 @@ pub fn optimize_edge_scores(
                 // eprintln!("Checking {:?} {:?}. Start dist: {cur_dist}", s1, s2);
                 // eprintln!("THIS IS FAIL.");
                 // eprintln!("For {:?}, on position {better}", stay_edge);
-                let new_my_score = match_borders(parsed_puzzles, stay_edge, other_side)
+                let new_my_score = match_borders(parsed_puzzles, stay_edge, other_side, params)
                     .unwrap()
                     .score;
                 let new_better = all_sides
                     .par_iter()
                     .filter(|another_side| {
-                        match_borders(parsed_puzzles, stay_edge, **another_side)
+                        match_borders(parsed_puzzles, stay_edge, **another_side, params)
                             .map(|mr| mr.score)
                             .unwrap_or(f64::MAX)
                             <= new_my_score
@@ -104,6 +110,7 @@ pub fn optimize_edge_scores(
             &used_edges,
             &mut positions,
             &base_points_matrix,
+            config,
         )
         .unwrap_or(f64::MAX);
         rotate_component(
@@ -141,14 +148,15 @@ pub fn optimize_edge_scores(
             let pos1 = positions[s1.fig].as_ref().unwrap();
             let pos2 = positions[s2.fig].as_ref().unwrap();
 
-            let border1 = get_border_and_neighbors(pos1, parsed_puzzles, s1);
-            let border2 = get_border_and_neighbors(pos2, parsed_puzzles, s2);
+            let neighbors = params.scoring.neighbors;
+            let border1 = get_border_and_neighbors(pos1, parsed_puzzles, s1, neighbors);
+            let border2 = get_border_and_neighbors(pos2, parsed_puzzles, s2, neighbors);
             let border2 = border2.reverse();
 
             {
-                let res1 = match_side_borders_v2(&border1.prev, &border2.prev, true);
+                let res1 = match_side_borders_v2(&border1.prev, &border2.prev, params, true);
                 debug_lines.push(res1.1);
-                let res2 = match_side_borders_v2(&border1.next, &border2.next, true);
+                let res2 = match_side_borders_v2(&border1.next, &border2.next, params, true);
                 debug_lines.push(res2.1);
 
                 additional_text += &format!(". borders: {:.3}", res1.0 + res2.0);
@@ -163,9 +171,181 @@ pub fn optimize_edge_scores(
             debug_lines,
             bbox: (PointF::ZERO, PointF::ZERO),
             new_edges_used: vec![],
+            new_edge_scores: vec![],
             new_figures_used: vec![],
+            closure_score: 0.0,
+            overlap_score: 0.0,
+            ranking_score: placement_score,
         });
     }
     solutions.sort_by(|s1, s2| s1.placement_score.total_cmp(&s2.placement_score));
     solutions
 }
+
+// Verified edge from one side with sides, which compete with the correct one.
+pub struct TuningSample {
+    pub stay: Side,
+    pub correct: Side,
+    pub others: Vec<Side>,
+}
+
+fn calc_match_score(
+    parsed_puzzles: &ParsedPuzzles,
+    s1: Side,
+    s2: Side,
+    params: &MatcherConfig,
+) -> f64 {
+    match_borders(parsed_puzzles, s1, s2, params)
+        .map(|mr| mr.score)
+        .unwrap_or(f64::MAX)
+}
+
+// Mean log rank of the correct side, smaller is better.
+pub fn calc_tuning_quality(
+    parsed_puzzles: &ParsedPuzzles,
+    samples: &[TuningSample],
+    params: &MatcherConfig,
+) -> f64 {
+    let positions: Vec<usize> = samples
+        .par_iter()
+        .map(|sample| {
+            let score = |other: Side| calc_match_score(parsed_puzzles, sample.stay, other, params);
+            let correct_score = score(sample.correct);
+            1 + sample
+                .others
+                .iter()
+                .filter(|&&other| score(other) <= correct_score)
+                .count()
+        })
+        .collect();
+    let mut quality = ScoringFunctionQuality::default();
+    for position in positions {
+        quality.add_score(position);
+    }
+    quality.get_quality()
+}
+
+// `others` of each sample are the `cnt` closest sides of its pool by `params`.
+fn draw_competitors(
+    parsed_puzzles: &ParsedPuzzles,
+    pools: &[TuningSample],
+    params: &MatcherConfig,
+    cnt: usize,
+) -> Vec<TuningSample> {
+    pools
+        .par_iter()
+        .map(|pool| TuningSample {
+            stay: pool.stay,
+            correct: pool.correct,
+            others: pool
+                .others
+                .iter()
+                .map(|&other| {
+                    let score = calc_match_score(parsed_puzzles, pool.stay, other, params);
+                    (other, score)
+                })
+                .sorted_by(|(_, a), (_, b)| a.total_cmp(b))
+                .take(cnt)
+                .map(|(other, _)| other)
+                .collect(),
+        })
+        .collect()
+}
+
+// Coordinate search over `EdgeScoringParams` with multiplicative steps. Ranks are
+// computed only among a pool of sides for each verified edge: the closest ones by the
+// graph scores and some random ones, otherwise one evaluation takes too long. Before
+// each round competitors are drawn from the pool with the current params, so sides,
+// which only the new params rank high, are checked too. Some verified edges are held
+// out, quality on them is reported before and after tuning.
+// Other matcher constants are taken from `matcher`.
+// Ranks don't change if all weights are scaled together, but absolute cutoffs on
+// scores do, so `plain_weight` is kept as the scale reference.
+pub fn tune_edge_scoring(
+    parsed_puzzles: &ParsedPuzzles,
+    graph: &Graph,
+    known_facts: &KnownFacts,
+    matcher: &MatcherConfig,
+) -> EdgeScoringParams {
+    const MAX_EDGES: usize = 80;
+    const HOLD_OUT_EVERY: usize = 4;
+    const POOL_CLOSEST: usize = 100;
+    const POOL_RANDOM: usize = 50;
+    const CANDIDATES: usize = 30;
+    const START_STEP: f64 = 0.5;
+    const MIN_STEP: f64 = 0.05;
+    const MAX_ROUNDS: usize = 20;
+    const FIXED_PARAM: usize = 1;
+    const SEED: u64 = 787788;
+
+    let dist = graph.gen_adj_matrix();
+    let dist = |s1: Side, s2: Side| -> f64 { dist[[s1.fig, s1.side, s2.fig, s2.side]] };
+    let all_sides = parsed_puzzles.gen_all_sides();
+    let verified = dedup_edges(&known_facts.build_placement().get_all_neighbours());
+    let step_by = (verified.len() / MAX_EDGES).max(1);
+    let mut rng = StdRng::seed_from_u64(SEED);
+    let mut pools = vec![];
+    let mut held_out = vec![];
+    for (edge_id, &(s1, s2)) in verified.iter().step_by(step_by).enumerate() {
+        for (stay, correct) in [(s1, s2), (s2, s1)] {
+            let mut others = all_sides
+                .iter()
+                .filter(|s| s.fig != stay.fig && **s != correct)
+                .sorted_by(|a, b| dist(stay, **a).total_cmp(&dist(stay, **b)))
+                .cloned()
+                .collect_vec();
+            let rest = others.split_off(POOL_CLOSEST.min(others.len()));
+            others.extend(rest.choose_multiple(&mut rng, POOL_RANDOM).cloned());
+            let sample = TuningSample {
+                stay,
+                correct,
+                others,
+            };
+            if edge_id % HOLD_OUT_EVERY == 0 {
+                held_out.push(sample);
+            } else {
+                pools.push(sample);
+            }
+        }
+    }
+    eprintln!(
+        "Tuning on {} samples, {} held out, from {} verified edges",
+        pools.len(),
+        held_out.len(),
+        verified.len()
+    );
+
+    let with_scoring = |v: &[f64]| MatcherConfig {
+        scoring: EdgeScoringParams::from_vec(v),
+        ..matcher.clone()
+    };
+    let mut best = matcher.scoring.to_vec();
+    let start_held_out = calc_tuning_quality(parsed_puzzles, &held_out, &with_scoring(&best));
+    let mut step = START_STEP;
+    for round in 0..MAX_ROUNDS {
+        if step < MIN_STEP {
+            break;
+        }
+        let samples = draw_competitors(parsed_puzzles, &pools, &with_scoring(&best), CANDIDATES);
+        let mut best_quality = calc_tuning_quality(parsed_puzzles, &samples, &with_scoring(&best));
+        let start_quality = best_quality;
+        for i in (0..best.len()).filter(|&i| i != FIXED_PARAM) {
+            for mult in [1.0 + step, 1.0 / (1.0 + step)] {
+                let mut check = best.clone();
+                check[i] *= mult;
+                let quality = calc_tuning_quality(parsed_puzzles, &samples, &with_scoring(&check));
+                if quality < best_quality {
+                    best_quality = quality;
+                    best = check;
+                }
+            }
+        }
+        eprintln!("Round {round}, step {step:.3}: quality {start_quality:.4} -> {best_quality:.4}");
+        if best_quality == start_quality {
+            step /= 2.0;
+        }
+    }
+    let end_held_out = calc_tuning_quality(parsed_puzzles, &held_out, &with_scoring(&best));
+    eprintln!("Held out quality: {start_held_out:.4} -> {end_held_out:.4}");
+    EdgeScoringParams::from_vec(&best)
+}
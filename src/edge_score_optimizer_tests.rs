@@ -0,0 +1,52 @@
+#[cfg(test)]
+mod tests {
+    use crate::{
+        config::MatcherConfig,
+        edge_score_optimizer::{calc_tuning_quality, TuningSample},
+        parsed_puzzles::ParsedPuzzles,
+        synthetic::{gen_pattern_image, SyntheticPuzzle},
+        utils::dedup_edges,
+    };
+
+    #[test]
+    pub fn tuning_quality_on_synthetic() {
+        let source = gen_pattern_image(400, 300, 1);
+        let puzzle = SyntheticPuzzle::generate(&source, 3, 4, 2);
+        let parsed_puzzles = ParsedPuzzles::new(&puzzle.image);
+        let placement = puzzle
+            .to_ground_truth(&parsed_puzzles)
+            .to_placement(&|_, _| 0.0)
+            .unwrap();
+        let all_sides = parsed_puzzles.gen_all_sides();
+        let params = MatcherConfig::default();
+
+        let edges = dedup_edges(&placement.get_all_neighbours());
+        let samples = edges
+            .iter()
+            .map(|&(stay, correct)| TuningSample {
+                stay,
+                correct,
+                others: all_sides
+                    .iter()
+                    .filter(|s| s.fig != stay.fig && **s != correct)
+                    .cloned()
+                    .collect(),
+            })
+            .collect::<Vec<_>>();
+        let quality = calc_tuning_quality(&parsed_puzzles, &samples, &params);
+        assert!(quality < 0.5, "quality {quality}");
+
+        // the true neighbour is among the others, so it is ranked first instead
+        let swapped = samples
+            .iter()
+            .map(|sample| TuningSample {
+                stay: sample.stay,
+                correct: sample.others[0],
+                others: vec![sample.correct],
+            })
+            .collect::<Vec<_>>();
+        let swapped_quality = calc_tuning_quality(&parsed_puzzles, &swapped, &params);
+        assert!(swapped_quality > 0.9, "quality {swapped_quality}");
+        assert_eq!(calc_tuning_quality(&parsed_puzzles, &[], &params), 0.0);
+    }
+}
@@ -0,0 +1,56 @@
+use serde::{Deserialize, Serialize};
+
+// Constants of [crate::border_matcher::match_placed_borders]. Defaults are hand-picked,
+// tuned ones are found by `tune_edge_scoring` and stored in the config.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct EdgeScoringParams {
+    // center term: weight of points in the socket and of all other points
+    pub socket_weight: f64,
+    pub plain_weight: f64,
+    // squared distance which is not penalized
+    pub center_ok_min_diff: f64,
+    // prev/next polynomial terms
+    pub poly_multiplier: f64,
+    pub poly_ok_min_diff: f64,
+    // points of the neighbouring sides used in polynomial terms
+    pub neighbors: usize,
+}
+
+impl Default for EdgeScoringParams {
+    fn default() -> Self {
+        Self {
+            socket_weight: 1.0,
+            plain_weight: 6.0,
+            center_ok_min_diff: 0.2,
+            poly_multiplier: 30.0,
+            poly_ok_min_diff: 0.25,
+            neighbors: 10,
+        }
+    }
+}
+
+impl EdgeScoringParams {
+    // `neighbors` is rounded, the rest is used as is
+    pub fn to_vec(&self) -> Vec<f64> {
+        vec![
+            self.socket_weight,
+            self.plain_weight,
+            self.center_ok_min_diff,
+            self.poly_multiplier,
+            self.poly_ok_min_diff,
+            self.neighbors as f64,
+        ]
+    }
+
+    pub fn from_vec(v: &[f64]) -> Self {
+        Self {
+            socket_weight: v[0],
+            plain_weight: v[1],
+            center_ok_min_diff: v[2],
+            poly_multiplier: v[3],
+            poly_ok_min_diff: v[4],
+            neighbors: v[5].round().max(3.0) as usize,
+        }
+    }
+}
@@ -0,0 +1,22 @@
+#[cfg(test)]
+mod tests {
+    use crate::edge_scoring_params::EdgeScoringParams;
+
+    #[test]
+    pub fn vec_round_trip() {
+        let params = EdgeScoringParams::default();
+        let v = params.to_vec();
+        assert_eq!(v.len(), 6);
+        assert_eq!(v[1], params.plain_weight);
+        assert_eq!(EdgeScoringParams::from_vec(&v), params);
+    }
+
+    #[test]
+    pub fn neighbors_are_rounded() {
+        let mut v = EdgeScoringParams::default().to_vec();
+        v[5] = 7.6;
+        assert_eq!(EdgeScoringParams::from_vec(&v).neighbors, 8);
+        v[5] = 0.4;
+        assert_eq!(EdgeScoringParams::from_vec(&v).neighbors, 3);
+    }
+}
@@ -335,6 +335,19 @@ pub struct BorderFigure {
 }
 
 impl BorderFigure {
+    pub fn is_corner(&self) -> bool {
+        self.left_side.ne() == self.right_side
+    }
+
+    // sides, which are part of the picture border
+    pub fn picture_sides(&self) -> Vec<Side> {
+        if self.is_corner() {
+            vec![self.right_side.ne(), self.right_side.ne2()]
+        } else {
+            vec![self.right_side.ne()]
+        }
+    }
+
     pub fn new(figure_id: usize, left_side: usize, right_side: usize) -> Self {
         Self {
             figure_id,
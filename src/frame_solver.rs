@@ -0,0 +1,356 @@
+use std::cmp::{max, min};
+
+use itertools::Itertools;
+use ndarray::Array4;
+
+use crate::{
+    borders_graph::Graph,
+    figure::BorderFigure,
+    graph_solver::calc_sorted_by_dist,
+    known_facts::{EdgeState, KnownFacts},
+    parsed_puzzles::ParsedPuzzles,
+    placement::Placement,
+    utils::{fmax, fmin, Side},
+};
+
+#[derive(Clone, Debug)]
+pub struct FrameSolution {
+    // closed ring, last figure is connected to the first one. Starts with a corner.
+    pub order: Vec<BorderFigure>,
+    pub cost: f64,
+    pub max_edge_dist: f64,
+    // number of non-corner figures between corners
+    pub segments: [usize; 4],
+}
+
+impl FrameSolution {
+    pub fn edges(&self) -> Vec<(Side, Side)> {
+        self.order
+            .iter()
+            .circular_tuple_windows()
+            .map(|(f1, f2)| (f1.right_side, f2.left_side))
+            .collect()
+    }
+
+    pub fn to_placement(&self) -> Option<Placement> {
+        let mut placement = Placement::new();
+        for (s1, s2) in self.edges() {
+            placement.join_sides(s1, s2)?;
+        }
+        Some(placement)
+    }
+}
+
+// Looks at neighbours of both sides, which are not on the border. If two border figures
+// are really adjacent, their inner neighbours should also match each other.
+fn calc_border_dists(
+    graph: &Graph,
+    parsed_puzzles: &ParsedPuzzles,
+    figures_on_border: &[BorderFigure],
+) -> Array4<f64> {
+    let n = graph.n;
+    let dist = graph.gen_adj_matrix();
+    let dist = |s1: Side, s2: Side| -> f64 { dist[[s1.fig, s1.side, s2.fig, s2.side]] };
+    let sorted_by_dist = calc_sorted_by_dist(parsed_puzzles, dist);
+
+    const MAX_DIST: f64 = 4.5;
+
+    let mut dist2 = Array4::<f64>::from_elem((n, 4, n, 4), f64::MAX / 50.0);
+    for (iter, left_fig) in figures_on_border.iter().enumerate() {
+        eprintln!("dist2 iter: {iter}/{}", figures_on_border.len());
+        for right_fig in figures_on_border.iter() {
+            if left_fig.figure_id == right_fig.figure_id {
+                continue;
+            }
+            let s1 = left_fig.right_side;
+            let s1_up = s1.pr();
+            let s2 = right_fig.left_side;
+            let s2_up = s2.ne();
+            let mut cur_res = f64::MAX;
+
+            let start_dist = dist(s1, s2);
+
+            if start_dist > MAX_DIST {
+                continue;
+            }
+
+            let list1 = &sorted_by_dist[s1.fig][s1_up.side];
+            let list2 = &sorted_by_dist[s2.fig][s2_up.side];
+            for sum_ix in 0..list1.len() + list2.len() - 1 {
+                {
+                    let ix1 = sum_ix / 2;
+                    let ix2 = sum_ix - ix1;
+                    let s3 = list1[ix1];
+                    let s4 = list2[ix2];
+                    if dist(s1_up, s3) > cur_res && dist(s2_up, s4) > cur_res {
+                        break;
+                    }
+                }
+                for (ix1, &s3) in list1.iter().enumerate().take(sum_ix + 1) {
+                    let ix2 = sum_ix - ix1;
+                    if ix2 < list2.len() {
+                        let s4 = list2[ix2];
+                        let d1_up = dist(s1_up, s3);
+                        let d2_up = dist(s2_up, s4);
+                        if d1_up > cur_res || d2_up > cur_res {
+                            continue;
+                        }
+                        let check_res = fmax(start_dist, dist(s3.pr(), s4.ne()));
+                        let check_res = fmax(fmax(d1_up, d2_up), check_res);
+                        cur_res = fmin(cur_res, check_res);
+                    }
+                }
+            }
+
+            dist2[[s1.fig, s1.side, s2.fig, s2.side]] = cur_res;
+        }
+    }
+    dist2
+}
+
+struct FrameSearch<'a> {
+    figures: &'a [BorderFigure],
+    // for each figure, list of (next figure, cost) sorted by cost
+    next: Vec<Vec<(usize, f64)>>,
+    ring_len: usize,
+    // allowed (short, long) segment lengths, if board size is known
+    board_segments: Option<(usize, usize)>,
+    pins_check: &'a dyn Fn(&FrameSolution) -> bool,
+    used: Vec<bool>,
+    order: Vec<usize>,
+    segments: Vec<usize>,
+    cost: f64,
+    min_edge_cost: f64,
+    iters: usize,
+    best: Option<FrameSolution>,
+}
+
+impl<'a> FrameSearch<'a> {
+    const MAX_ITERS: usize = 10_000_000;
+
+    fn segment_ok(&self, segment_id: usize, len: usize, finished: bool) -> bool {
+        let max_len = match (segment_id, self.board_segments) {
+            (0, Some((short, long))) => {
+                if finished && len != short && len != long {
+                    return false;
+                }
+                long
+            }
+            (0, None) => self.ring_len,
+            (1, Some((short, long))) => {
+                let other = if self.segments[0] == short {
+                    long
+                } else {
+                    short
+                };
+                if finished && len != other {
+                    return false;
+                }
+                other
+            }
+            (1, None) => self.ring_len,
+            _ => {
+                // opposite sides of the rectangle have the same length
+                let opposite = self.segments[segment_id - 2];
+                if finished && len != opposite {
+                    return false;
+                }
+                opposite
+            }
+        };
+        len <= max_len
+    }
+
+    fn go(&mut self, cur: usize) {
+        self.iters += 1;
+        if self.iters > Self::MAX_ITERS {
+            return;
+        }
+        if let Some(best) = &self.best {
+            let left = self.ring_len + 1 - self.order.len();
+            if self.cost + (left as f64) * self.min_edge_cost >= best.cost {
+                return;
+            }
+        }
+        let first = self.order[0];
+        if self.order.len() == self.ring_len {
+            if let Some(&(_, cost)) = self.next[cur].iter().find(|(next, _)| *next == first) {
+                if self.segments.len() == 4 && self.segment_ok(3, self.segments[3], true) {
+                    self.check_solution(cost);
+                }
+            }
+            return;
+        }
+        for i in 0..self.next[cur].len() {
+            let (next, cost) = self.next[cur][i];
+            if self.used[next] {
+                continue;
+            }
+            let is_corner = self.figures[next].is_corner();
+            let segment_id = self.segments.len() - 1;
+            if is_corner {
+                if self.segments.len() == 4
+                    || !self.segment_ok(segment_id, self.segments[segment_id], true)
+                {
+                    continue;
+                }
+                self.segments.push(0);
+            } else {
+                if !self.segment_ok(segment_id, self.segments[segment_id] + 1, false) {
+                    continue;
+                }
+                self.segments[segment_id] += 1;
+            }
+            self.used[next] = true;
+            self.order.push(next);
+            self.cost += cost;
+
+            self.go(next);
+
+            self.cost -= cost;
+            self.order.pop();
+            self.used[next] = false;
+            if is_corner {
+                self.segments.pop();
+            } else {
+                self.segments[segment_id] -= 1;
+            }
+        }
+    }
+
+    fn check_solution(&mut self, last_cost: f64) {
+        let order = self.order.iter().map(|&id| self.figures[id]).collect_vec();
+        let edge_costs = self
+            .order
+            .iter()
+            .circular_tuple_windows()
+            .map(|(&f1, &f2)| self.next[f1].iter().find(|(id, _)| *id == f2).unwrap().1)
+            .collect_vec();
+        let solution = FrameSolution {
+            order,
+            cost: self.cost + last_cost,
+            max_edge_dist: edge_costs.iter().cloned().fold(0.0, fmax),
+            segments: [
+                self.segments[0],
+                self.segments[1],
+                self.segments[2],
+                self.segments[3],
+            ],
+        };
+        if !(self.pins_check)(&solution) {
+            return;
+        }
+        eprintln!(
+            "Found frame with cost {:.3} (max edge {:.3}), segments: {:?}",
+            solution.cost, solution.max_edge_dist, solution.segments
+        );
+        self.best = Some(solution);
+    }
+}
+
+// Orders all border figures into a closed ring with four corners.
+// Pieces, which don't fit into the ring (badly detected borders) make the search fail,
+// mark them as excluded in this case.
+pub fn solve_frame(
+    graph: &Graph,
+    parsed_puzzles: &ParsedPuzzles,
+    known_facts: &KnownFacts,
+) -> Option<FrameSolution> {
+    assert_eq!(graph.parsed_puzzles_hash, parsed_puzzles.calc_hash());
+
+    const MAX_NEXT: usize = 8;
+    const MAX_DIST2: f64 = 10.0;
+
+    let figures = parsed_puzzles.calc_figures_on_border_with_facts(known_facts);
+    let cnt_corners = figures.iter().filter(|f| f.is_corner()).count();
+    eprintln!(
+        "Number of figures on border: {}, corners: {cnt_corners}",
+        figures.len()
+    );
+    if cnt_corners != 4 {
+        eprintln!("Can't build frame without exactly 4 corners");
+        return None;
+    }
+    let board_segments = match known_facts.board_size {
+        Some((rows, cols)) if rows < 2 || cols < 2 => {
+            eprintln!("Board {rows}x{cols} is too small for a frame");
+            return None;
+        }
+        Some((rows, cols)) => {
+            let (short, long) = (min(rows, cols), max(rows, cols));
+            Some(((short - 2) as usize, (long - 2) as usize))
+        }
+        None => None,
+    };
+    let ring_len = match board_segments {
+        Some((short, long)) => 2 * (short + long) + 4,
+        None => figures.len(),
+    };
+    if ring_len > figures.len() {
+        eprintln!(
+            "Board needs {ring_len} border figures, but only {} found",
+            figures.len()
+        );
+        return None;
+    }
+
+    let dist2 = calc_border_dists(graph, parsed_puzzles, &figures);
+    let dist2 = |s1: Side, s2: Side| -> f64 { dist2[[s1.fig, s1.side, s2.fig, s2.side]] };
+    let mut next = vec![vec![]; figures.len()];
+    for (i, f1) in figures.iter().enumerate() {
+        for (j, f2) in figures.iter().enumerate() {
+            if i == j {
+                continue;
+            }
+            let (s1, s2) = (f1.right_side, f2.left_side);
+            match known_facts.get_edge_state(s1, s2) {
+                EdgeState::GoodEdge => next[i].push((j, 0.0)),
+                EdgeState::WrongEdge => {}
+                EdgeState::Unknown => {
+                    let cost = dist2(s1, s2);
+                    if cost < MAX_DIST2 {
+                        next[i].push((j, cost));
+                    }
+                }
+            }
+        }
+        next[i].sort_by(|(_, c1), (_, c2)| c1.total_cmp(c2));
+        next[i].truncate(MAX_NEXT);
+    }
+    let min_edge_cost = next
+        .iter()
+        .filter_map(|v| v.first())
+        .map(|(_, cost)| *cost)
+        .fold(f64::MAX, fmin);
+
+    let pins = known_facts.get_pinned_positions();
+    let board_size = known_facts.board_size;
+    let pins_check = |solution: &FrameSolution| -> bool {
+        match solution.to_placement() {
+            Some(placement) => placement.is_consistent_with_anchors(&pins, board_size),
+            None => false,
+        }
+    };
+
+    let start = (0..figures.len())
+        .find(|&id| figures[id].is_corner())
+        .unwrap();
+    let mut search = FrameSearch {
+        figures: &figures,
+        next,
+        ring_len,
+        board_segments,
+        pins_check: &pins_check,
+        used: vec![false; figures.len()],
+        order: vec![start],
+        segments: vec![0],
+        cost: 0.0,
+        min_edge_cost,
+        iters: 0,
+        best: None,
+    };
+    search.used[start] = true;
+    search.go(start);
+    eprintln!("Frame search finished after {} iterations", search.iters);
+    search.best
+}
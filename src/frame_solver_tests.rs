@@ -0,0 +1,50 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use crate::{
+        borders_graph::Graph,
+        config::MatcherConfig,
+        frame_solver::solve_frame,
+        known_facts::KnownFacts,
+        parsed_puzzles::ParsedPuzzles,
+        synthetic::{gen_pattern_image, SyntheticPuzzle},
+    };
+
+    #[test]
+    pub fn synthetic_ring() {
+        let (rows, cols) = (4, 5);
+        let source = gen_pattern_image(500, 400, 3);
+        let puzzle = SyntheticPuzzle::generate(&source, rows, cols, 6);
+        let parsed_puzzles = ParsedPuzzles::new(&puzzle.image);
+        let graph = Graph::new(&parsed_puzzles, false, &MatcherConfig::default());
+        let truth = puzzle
+            .to_ground_truth(&parsed_puzzles)
+            .to_placement(&|_, _| 0.0)
+            .unwrap();
+        let truth_edges: BTreeSet<_> = truth.get_all_neighbours().into_iter().collect();
+        let ring_len = 2 * (rows + cols) - 4;
+
+        let mut known_facts = KnownFacts::from_facts(vec![]);
+        for board_size in [None, Some((rows as i32, cols as i32))] {
+            known_facts.board_size = board_size;
+            let solution = solve_frame(&graph, &parsed_puzzles, &known_facts).unwrap();
+            assert_eq!(solution.order.len(), ring_len);
+            let mut segments = solution.segments;
+            segments.sort();
+            assert_eq!(segments, [rows - 2, rows - 2, cols - 2, cols - 2]);
+            for (s1, s2) in solution.edges() {
+                assert!(
+                    truth_edges.contains(&(s1, s2)) || truth_edges.contains(&(s2, s1)),
+                    "{s1:?} {s2:?}"
+                );
+            }
+            let placement = solution.to_placement().unwrap();
+            assert_eq!(placement.get_cnt_figures(), ring_len);
+            assert!(placement.is_consistent_with_anchors(&[], Some((rows as i32, cols as i32))));
+        }
+        // set directly, `set_board_size` doesn't accept it
+        known_facts.board_size = Some((1, cols as i32));
+        assert!(solve_frame(&graph, &parsed_puzzles, &known_facts).is_none());
+    }
+}
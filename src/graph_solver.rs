@@ -1,31 +1,38 @@
 use std::{
     cmp::{max, min},
     collections::{hash_map::DefaultHasher, BTreeMap, BTreeSet, BinaryHeap, VecDeque},
+    fs,
     hash::{Hash, Hasher},
     i16::MAX,
     process,
+    time::{Duration, Instant},
 };
 
 use eframe::epaint::{Color32, ColorImage};
 use itertools::Itertools;
 use ndarray::Array4;
-use rand::{rngs::StdRng, Rng, SeedableRng};
 use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    border_matcher::is_picture_border,
+    assignment_solver::assign_pieces,
     borders_graph::Graph,
-    figure::BorderFigure,
+    config::Config,
+    ground_truth::load_known_placement,
+    hole_solver::solve_hole,
     interactive_solutions_picker::InteractiveSolutionPicker,
     known_facts::{self, EdgeState, Fact, KnownFacts},
-    known_positions::get_known_placement,
+    loop_closure::{calc_closure_score, calc_corner_closures_with_figures},
+    overlap::{calc_overlap_score, calc_overlaps},
     parsed_puzzles::ParsedPuzzles,
     placement::{Placement, PotentialGroupLocation, Search3StateWithScore},
     point::{Point, PointF},
     positions_cache::PositionsCache,
     rects_fitter::get_bounding_box,
     search_states_cache::SearchStatesCache,
-    surface_placer::{place_one_connected_component, rotate_component},
+    solver_strategy::SolverConfig,
+    solver_worker::ProgressSink,
+    surface_placer::{get_borders_dist, rotate_component},
     topn::TopN,
     utils::{fmax, fmin, normalize_bounding_box, Side},
 };
@@ -37,8 +44,8 @@ struct Two {
     dist: f64,
 }
 
-#[derive(Clone, PartialEq, Debug)]
-struct SearchState {
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct SearchState {
     all_edges: Vec<(Side, Side)>,
     vertices: BTreeSet<usize>,
     score: f64,
@@ -81,6 +88,83 @@ impl Ord for SearchState {
     }
 }
 
+// Frontier of [solve_graph], so it can be stopped and continued later.
+#[derive(Serialize, Deserialize)]
+pub struct SolveGraphCheckpoint {
+    // see [SolveGraphCheckpoint::calc_inputs_hash]
+    pub inputs_hash: u64,
+    pub cnt_vertices: usize,
+    pub pq: Vec<TopN<SearchState>>,
+    // states of size `cnt_vertices`, which are not expanded yet, best first
+    pub pending: Vec<SearchState>,
+    pub states_explored: usize,
+}
+
+const CHECKPOINT_PATH: &str = "solve_graph_checkpoint.json";
+
+impl SolveGraphCheckpoint {
+    // Everything, which changes the search: puzzles and edge scores (both are in the graph),
+    // start state, facts used by the search, and its settings.
+    pub fn calc_inputs_hash(
+        graph: &Graph,
+        start_state_edges: &[(Side, Side)],
+        known_facts: &KnownFacts,
+        settings: &str,
+    ) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        serde_json::to_string(graph).unwrap().hash(&mut hasher);
+        start_state_edges.hash(&mut hasher);
+        known_facts.get_excluded_figures().hash(&mut hasher);
+        known_facts.get_pinned_positions().hash(&mut hasher);
+        known_facts.board_size.hash(&mut hasher);
+        settings.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    pub fn load(path: &str, inputs_hash: u64) -> Option<Self> {
+        let content = fs::read_to_string(path).ok()?;
+        let res: Self = match serde_json::from_str(&content) {
+            Ok(res) => res,
+            Err(err) => {
+                eprintln!("Can't parse checkpoint {path}, ignoring it: {err}");
+                return None;
+            }
+        };
+        if res.inputs_hash != inputs_hash {
+            eprintln!("Checkpoint {path} is for different puzzles, facts or settings, ignoring it");
+            return None;
+        }
+        Some(res)
+    }
+
+    pub fn save(&self, path: &str) {
+        let start = Instant::now();
+        // write to tmp file first, so killing the process while saving doesn't break the checkpoint
+        let tmp_path = format!("{path}.tmp");
+        fs::write(&tmp_path, serde_json::to_string(self).unwrap()).unwrap();
+        fs::rename(&tmp_path, path).unwrap();
+        eprintln!("Checkpoint saved in {:?}", start.elapsed());
+    }
+
+    pub fn remove(path: &str) {
+        fs::remove_file(path).ok();
+    }
+}
+
+fn report_progress(pq: &[TopN<SearchState>], states_explored: usize) {
+    eprintln!("States explored: {states_explored}");
+    for (size, top) in pq.iter().enumerate() {
+        if let Some(best) = top.get_best() {
+            eprintln!(
+                "  size {size}: {} states, best score = {:.3}, av edge = {:.3}",
+                top.set.len(),
+                best.score,
+                best.av_edge_dist
+            );
+        }
+    }
+}
+
 fn optimize_start_position(
     mut placement: Placement,
     n: usize,
@@ -179,6 +263,14 @@ pub struct PotentialSolution {
     pub bbox: (PointF, PointF),
     pub new_figures_used: Vec<usize>,
     pub new_edges_used: Vec<(Side, Side)>,
+    // distance between placed borders of each of `new_edges_used`, smaller is better
+    pub new_edge_scores: Vec<f64>,
+    // see [calc_closure_score]
+    pub closure_score: f64,
+    // see [calc_overlap_score]
+    pub overlap_score: f64,
+    // placement score with closure and overlap penalties, candidates are sorted by it
+    pub ranking_score: f64,
 }
 
 impl PartialOrd for PotentialSolution {
@@ -191,11 +283,11 @@ impl Eq for PotentialSolution {}
 
 impl Ord for PotentialSolution {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.placement_score.total_cmp(&other.placement_score)
+        self.ranking_score.total_cmp(&other.ranking_score)
     }
 }
 
-fn is_inside(border: &[PointF], p: PointF) -> bool {
+pub fn is_inside(border: &[PointF], p: PointF) -> bool {
     let mut cnt_intersections = 0;
     for (p1, p2) in border.iter().circular_tuple_windows() {
         let (p_min, p_max) = if p1.y < p2.y { (p1, p2) } else { (p2, p1) };
@@ -228,6 +320,17 @@ fn get_pixels_inside_figure(border: &[PointF]) -> Vec<Point> {
 }
 
 impl PotentialSolution {
+    pub fn calc_ranking_score(
+        placement_score: f64,
+        closure_score: f64,
+        overlap_score: f64,
+        config: &SolverConfig,
+    ) -> f64 {
+        placement_score
+            + config.closure_weight * closure_score
+            + config.overlap_weight * overlap_score
+    }
+
     pub fn gen_image(ps: &[Self]) -> ColorImage {
         eprintln!("Start generating solutions mask");
         let mut max_x = 0;
@@ -272,9 +375,13 @@ impl PotentialSolution {
             return None;
         }
         let mut new_edges_used = vec![];
-        for &(s1, s2) in self.new_edges_used.iter() {
+        let mut new_edge_scores = vec![];
+        for (&(s1, s2), &score) in self.new_edges_used.iter().zip(self.new_edge_scores.iter()) {
             match known_facts.get_edge_state(s1, s2) {
-                EdgeState::Unknown => new_edges_used.push((s1, s2)),
+                EdgeState::Unknown => {
+                    new_edges_used.push((s1, s2));
+                    new_edge_scores.push(score);
+                }
                 EdgeState::GoodEdge => {}
                 EdgeState::WrongEdge => return None,
             }
@@ -285,6 +392,7 @@ impl PotentialSolution {
         let mut res = self.clone();
         res.new_figures_used = new_figures_used;
         res.new_edges_used = new_edges_used;
+        res.new_edge_scores = new_edge_scores;
         Some(res)
     }
 }
@@ -320,7 +428,10 @@ pub fn solve_graph(
     graph: &Graph,
     parsed_puzzles: &ParsedPuzzles,
     prev_state: Option<Graph>,
-) -> Vec<PotentialSolution> {
+    known_facts: &KnownFacts,
+    config: &SolverConfig,
+    sink: &ProgressSink,
+) -> Vec<CandidatePlacement> {
     assert_eq!(graph.parsed_puzzles_hash, parsed_puzzles.calc_hash());
 
     eprintln!("Hello there!");
@@ -328,8 +439,6 @@ pub fn solve_graph(
     let base_dist = graph.gen_adj_matrix();
     eprintln!("nd array created!");
 
-    let base_points_matrix = graph.get_base_points_matrix();
-
     let all_sides = (0..n)
         .cartesian_product(0..4)
         .map(|(fig, side)| Side { fig, side })
@@ -408,14 +517,13 @@ pub fn solve_graph(
         eprintln!("{:?}", e);
     }
 
-    const MAX_DIST: f64 = 35.8;
-    const MAX_DIST_MULIPLIERS: [f64; 5] = [0.0, 1.0, 1.1, 1.3, 2.0];
-
     let mut twos = vec![];
     for &s0 in &all_sides {
         for &s1 in &all_sides {
             let d0 = dist(s0, s1);
-            if d0 > MAX_DIST * *MAX_DIST_MULIPLIERS.last().unwrap() || s0.fig >= s1.fig {
+            if d0 > config.max_dist * *config.max_dist_multipliers.last().unwrap()
+                || s0.fig >= s1.fig
+            {
                 continue;
             }
             twos.push(Two { s0, s1, dist: d0 });
@@ -441,15 +549,28 @@ pub fn solve_graph(
         SearchState::new(new_state_edges, sum_dists / (cnt_edges as f64), bb)
     };
 
-    const MAX_CNT: usize = 5000;
-    let mut pq = vec![TopN::new(MAX_CNT); graph.n + 1];
+    let mut pq = vec![TopN::new(config.max_cnt); graph.n + 1];
     // pq[0].push(SearchState::new(vec![], 0.0));
 
-    let start_vertices_num = {
-        let mut start_state_edges = vec![];
-        if let Some(prev_state) = prev_state {
-            for e in prev_state.all_edges.iter() {
-                eprintln!("WANT TO USE EDGE FROM PREV SOL: {:?}", e);
+    let mut start_state_edges = vec![];
+    if let Some(prev_state) = prev_state {
+        for e in prev_state.all_edges.iter() {
+            eprintln!("WANT TO USE EDGE FROM PREV SOL: {:?}", e);
+            start_state_edges.push((
+                Side {
+                    fig: e.fig1,
+                    side: e.side1,
+                },
+                Side {
+                    fig: e.fig2,
+                    side: e.side2,
+                },
+            ))
+        }
+    } else {
+        for e in graph.all_edges.iter() {
+            if e.existing_edge {
+                eprintln!("WANT TO USE EDGE: {:?}", e);
                 start_state_edges.push((
                     Side {
                         fig: e.fig1,
@@ -461,74 +582,118 @@ pub fn solve_graph(
                     },
                 ))
             }
-        } else {
-            for e in graph.all_edges.iter() {
-                if e.existing_edge {
-                    eprintln!("WANT TO USE EDGE: {:?}", e);
-                    start_state_edges.push((
-                        Side {
-                            fig: e.fig1,
-                            side: e.side1,
-                        },
-                        Side {
-                            fig: e.fig2,
-                            side: e.side2,
-                        },
-                    ))
-                }
-            }
-        }
-        {
-            const START_VERTEX: usize = 999;
-            let placement = get_known_placement(graph);
-            start_state_edges.extend(placement.get_all_neighbours_in_same_component(START_VERTEX));
-        }
-        if start_state_edges.is_empty() {
-            start_state_edges.push((twos[0].s0, twos[0].s1));
         }
-        let new_state = SearchState::new(start_state_edges, 0.0, (1, 1));
-        let placement = gen_placement(&new_state);
-        // let new_state = gen_state(&optimize_start_position(placement, n, dist));
-        let new_state = gen_state(&placement);
-
-        let start_vertices = new_state.vertices.len();
-        eprintln!("start vertices: {start_vertices}");
-        pq[start_vertices].insert(new_state);
-        start_vertices
-    };
+    }
+    {
+        let placement = load_known_placement(graph);
+        start_state_edges
+            .extend(placement.get_all_neighbours_in_same_component(config.start_vertex));
+    }
+    if start_state_edges.is_empty() {
+        start_state_edges.push((twos[0].s0, twos[0].s1));
+    }
+    let inputs_hash = SolveGraphCheckpoint::calc_inputs_hash(
+        graph,
+        &start_state_edges,
+        known_facts,
+        &format!(
+            "{} {:?} {}",
+            config.max_dist, config.max_dist_multipliers, config.max_cnt
+        ),
+    );
+
+    let mut states_explored = 0;
+    let mut pending = None;
+    let start_vertices_num =
+        if let Some(checkpoint) = SolveGraphCheckpoint::load(CHECKPOINT_PATH, inputs_hash) {
+            eprintln!(
+                "Resuming from checkpoint, {} pending states of size {}",
+                checkpoint.pending.len(),
+                checkpoint.cnt_vertices
+            );
+            pq = checkpoint.pq;
+            states_explored = checkpoint.states_explored;
+            pending = Some(checkpoint.pending);
+            report_progress(&pq, states_explored);
+            checkpoint.cnt_vertices
+        } else {
+            let new_state = SearchState::new(start_state_edges, 0.0, (1, 1));
+            let placement = gen_placement(&new_state);
+            // let new_state = gen_state(&optimize_start_position(placement, n, dist));
+            let new_state = gen_state(&placement);
+
+            let start_vertices = new_state.vertices.len();
+            eprintln!("start vertices: {start_vertices}");
+            pq[start_vertices].insert(new_state);
+            start_vertices
+        };
 
     // let banned_figures = [
     //     190, 162, 568, 291, 132, 606, 745, 267, 179, 931, 989, 583, 751, 864, 934, 929, 822, 442,
     //     973, 427, 238, 947, 887, 783, 1035, 673, 890, 498, 324, 490,
     // ];
 
-    let banned_figures: [usize; 0] = [];
+    let banned_figures = known_facts.get_excluded_figures();
+    let pins = known_facts.get_pinned_positions();
     // let banned_figures = [716, 912, 928, 114, 656, 719];
     // let banned_figures = [
     //     864, 267, 783, 190, 275, 179, 427, 238, 890, 1035, 275, 583, 783, 673, 934, 929, 864, 989,
     //     931, 568, 190, 291, 162, 745, 132, 606,
     // ];
 
+    const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(300);
+    let mut last_checkpoint = Instant::now();
     let max_v = start_vertices_num + 1;
     for cnt_vertices in start_vertices_num..min(max_v, pq.len()) {
-        let mut iter = 0;
-
-        let cur_pq = std::mem::replace(&mut pq[cnt_vertices], TopN::new(0));
-
-        for state in cur_pq.set.iter().rev() {
+        let cur_pq = match pending.take() {
+            Some(pending) => pending,
+            None => std::mem::replace(&mut pq[cnt_vertices], TopN::new(0))
+                .set
+                .into_iter()
+                .rev()
+                .collect_vec(),
+        };
+
+        for (state_id, state) in cur_pq.iter().enumerate() {
+            let cancelled = sink.is_cancelled();
+            if cancelled || last_checkpoint.elapsed() > CHECKPOINT_INTERVAL {
+                report_progress(&pq, states_explored);
+                SolveGraphCheckpoint {
+                    inputs_hash,
+                    cnt_vertices,
+                    pq: pq.clone(),
+                    pending: cur_pq[state_id..].to_vec(),
+                    states_explored,
+                }
+                .save(CHECKPOINT_PATH);
+                last_checkpoint = Instant::now();
+                if cancelled {
+                    return vec![];
+                }
+            }
+            if state_id % 100 == 0 {
+                sink.report(
+                    &format!("Extending states of size {cnt_vertices}"),
+                    state_id as f32 * 100.0 / (cur_pq.len() as f32),
+                    // the last size has nothing to extend into
+                    pq.get(cnt_vertices + 1)
+                        .and_then(|top| top.get_best())
+                        .map(|best| best.av_edge_dist),
+                );
+            }
+            states_explored += 1;
             let cur_placement = gen_placement(&state);
             let cur_bb = normalize_bounding_box(cur_placement.get_bounding_box());
-            if iter < 30 {
+            if state_id < 30 {
                 eprintln!(
                     "iter = {}. num vertices = {}, score = {}, av edge = {}, bbox : {:?}",
-                    iter,
+                    state_id,
                     state.vertices.len(),
                     state.score,
                     state.av_edge_dist,
                     cur_bb
                 );
             }
-            iter += 1;
             let mut new_placement = cur_placement.clone();
             let mut can_use_figure = vec![true; n];
             for i in 0..n {
@@ -568,21 +733,29 @@ pub fn solve_graph(
                             }
                         }
                         let max_dist_with_multiplier =
-                            MAX_DIST * MAX_DIST_MULIPLIERS[cnt_connections];
+                            config.max_dist * config.max_dist_multipliers[cnt_connections];
                         if max_dist <= max_dist_with_multiplier {
                             let (s0, s1) = example_side_pair.unwrap();
                             if let Some(_new_edges) = new_placement.join_sides(s0, s1) {
                                 let new_bounding_box =
                                     normalize_bounding_box(new_placement.get_bounding_box());
                                 let mut ok = true;
-                                if is_bad_bounding_box(new_bounding_box) {
-                                    ok = false;
-                                }
-                                if new_bounding_box != cur_bb
-                                    && !ok_to_increase_bb(cur_bb, cnt_vertices)
+                                if !new_placement
+                                    .is_consistent_with_anchors(&pins, known_facts.board_size)
                                 {
                                     ok = false;
                                 }
+                                // without real board size we can only guess
+                                if known_facts.board_size.is_none() {
+                                    if is_bad_bounding_box(new_bounding_box) {
+                                        ok = false;
+                                    }
+                                    if new_bounding_box != cur_bb
+                                        && !ok_to_increase_bb(cur_bb, cnt_vertices)
+                                    {
+                                        ok = false;
+                                    }
+                                }
 
                                 if ok {
                                     let new_state = gen_state(&new_placement);
@@ -603,63 +776,21 @@ pub fn solve_graph(
         }
     }
 
-    let mut solutions = vec![];
+    report_progress(&pq, states_explored);
+    SolveGraphCheckpoint::remove(CHECKPOINT_PATH);
+
     let mut all = pq[max_v].set.iter().cloned().collect_vec();
     all.reverse();
     all.truncate(100);
-    for state in all {
-        eprintln!(
-            "Try state... score = {}, cur_comp = {:?}",
-            state.score, state.vertices
-        );
-
-        let cur_component = state.vertices.iter().cloned().collect_vec();
-        let used_edges = state.all_edges.clone();
-        let mut positions = vec![None; n];
-        let placement_score = place_one_connected_component(
-            parsed_puzzles,
-            &cur_component,
-            &used_edges,
-            &mut positions,
-            &base_points_matrix,
-        )
-        .unwrap_or(f64::MAX);
-        eprintln!("placed score: {placement_score}");
-        rotate_component(
-            &cur_component,
-            &mut positions,
-            graph,
-            parsed_puzzles,
-            &[],
-            &[],
-        );
-
-        let mut placed_figures = vec![];
-        for (figure_id, positions) in positions.iter().enumerate() {
-            if let Some(positions) = positions {
-                placed_figures.push(PlacedFigure {
-                    figure_id,
-                    positions: positions.clone(),
-                });
-            }
-        }
-
-        solutions.push(PotentialSolution {
-            placed_figures,
-            placement_score,
-            text_offset: PointF { x: 0.0, y: 0.0 },
-            additional_text: "".to_owned(),
-            debug_lines: vec![],
-            bbox: (PointF::ZERO, PointF::ZERO),
-            new_edges_used: vec![],
-            new_figures_used: vec![],
-        });
-    }
-    solutions.sort_by(|s1, s2| s1.placement_score.total_cmp(&s2.placement_score));
-    solutions
-
-    // let placement = gen_placement(&last_state.unwrap());
-    // graph.get_subgraph(&placement)
+    all.into_iter()
+        .map(|state| {
+            CandidatePlacement::from_edges(
+                state.all_edges,
+                state.av_edge_dist,
+                format!(". edges per figure = {:.3}", state.score),
+            )
+        })
+        .collect_vec()
 }
 
 fn is_bad_bounding_box((min, max): (i32, i32)) -> bool {
@@ -672,7 +803,7 @@ fn ok_to_increase_bb((x, y): (i32, i32), cnt: usize) -> bool {
     cnt >= ok_from
 }
 
-fn calc_sorted_by_dist(
+pub fn calc_sorted_by_dist(
     parsed_puzzles: &ParsedPuzzles,
     mut dist: impl FnMut(Side, Side) -> f64,
 ) -> Vec<Vec<Vec<Side>>> {
@@ -706,183 +837,6 @@ fn calc_sorted_by_dist(
     sorted_by_dist
 }
 
-pub fn solve_graph_border(graph: &Graph, parsed_puzzles: &ParsedPuzzles) -> Graph {
-    assert_eq!(graph.parsed_puzzles_hash, parsed_puzzles.calc_hash());
-
-    eprintln!("Hello there!");
-    let n = graph.n;
-    let dist = graph.gen_adj_matrix();
-    eprintln!("nd array created!");
-
-    let mut figures_on_border = parsed_puzzles.calc_figures_on_border();
-
-    let sz = figures_on_border.len();
-    eprintln!("Number of figures on border: {}", sz);
-
-    let dist = |s1: Side, s2: Side| -> f64 { dist[[s1.fig, s1.side, s2.fig, s2.side]] };
-
-    let sorted_by_dist = calc_sorted_by_dist(parsed_puzzles, dist);
-
-    const MAX_DIST: f64 = 4.5;
-
-    let mut dist2 = Array4::<f64>::from_elem((n, 4, n, 4), f64::MAX / 50.0);
-    for (iter, left_fig) in figures_on_border.iter().enumerate() {
-        eprintln!("dist2 iter: {iter}/{}", figures_on_border.len());
-        for right_fig in figures_on_border.iter() {
-            if left_fig.figure_id == right_fig.figure_id {
-                continue;
-            }
-            let s1 = left_fig.right_side;
-            let s1_up = s1.pr();
-            let s2 = right_fig.left_side;
-            let s2_up = s2.ne();
-            let mut cur_res = f64::MAX;
-
-            let start_dist = dist(s1, s2);
-
-            if start_dist > MAX_DIST {
-                continue;
-            }
-
-            let list1 = &sorted_by_dist[s1.fig][s1_up.side];
-            let list2 = &sorted_by_dist[s2.fig][s2_up.side];
-            for sum_ix in 0..list1.len() + list2.len() - 1 {
-                {
-                    let ix1 = sum_ix / 2;
-                    let ix2 = sum_ix - ix1;
-                    let s3 = list1[ix1];
-                    let s4 = list2[ix2];
-                    if dist(s1_up, s3) > cur_res && dist(s2_up, s4) > cur_res {
-                        // eprintln!(
-                        //     "Break at idx: {sum_ix}. Dists: {} {}",
-                        //     dist(s1_up, s3),
-                        //     dist(s2_up, s4)
-                        // );
-                        break;
-                    }
-                }
-                for ix1 in 0..min(sum_ix + 1, list1.len()) {
-                    let ix2 = sum_ix - ix1;
-                    if ix2 < list2.len() {
-                        let s3 = list1[ix1];
-                        let s4 = list2[ix2];
-                        let d1_up = dist(s1_up, s3);
-                        let d2_up = dist(s2_up, s4);
-                        if d1_up > cur_res || d2_up > cur_res {
-                            continue;
-                        }
-                        let check_res = fmax(start_dist, dist(s3.pr(), s4.ne()));
-                        let check_res = fmax(fmax(d1_up, d2_up), check_res);
-                        cur_res = fmin(cur_res, check_res);
-                    }
-                }
-            }
-
-            // if start_dist < 2.0 {
-            //     eprintln!(
-            //         "Calculated dist2: {:?}..{:?}: {start_dist} -> {cur_res}",
-            //         left_fig, right_fig
-            //     );
-            // }
-            dist2[[s1.fig, s1.side, s2.fig, s2.side]] = cur_res;
-            dist2[[s2.fig, s2.side, s1.fig, s1.side]] = cur_res;
-        }
-    }
-
-    let dist2 = |s1: Side, s2: Side| -> f64 { dist2[[s1.fig, s1.side, s2.fig, s2.side]] };
-
-    let mut rnd = StdRng::seed_from_u64(123);
-
-    let segm_cost = |border: &[BorderFigure],
-                     mut i1: usize,
-                     mut i2: usize,
-                     mut i3: usize,
-                     mut i4: usize|
-     -> f64 {
-        i1 %= sz;
-        i2 %= sz;
-        i3 %= sz;
-        i4 %= sz;
-        dist2(border[i1].right_side, border[i2].left_side)
-            + dist2(border[i3].right_side, border[i4].left_side)
-    };
-
-    for iter in 0..100_000_000 {
-        let start1 = rnd.gen_range(0..sz - 1);
-        let len1 = rnd.gen_range(1..sz - 3);
-
-        let offset = rnd.gen_range(1..sz - len1 - 2);
-        let start2 = (start1 + len1 + offset) % sz;
-        let len2 = rnd.gen_range(1..sz - len1 - offset);
-
-        assert!(len1 + offset + len2 < sz);
-
-        let start_cost = segm_cost(
-            &figures_on_border,
-            start1 + sz - 1,
-            start1,
-            start1 + len1 - 1,
-            start1 + len1,
-        ) + segm_cost(
-            &figures_on_border,
-            start2 + sz - 1,
-            start2,
-            start2 + len2 - 1,
-            start2 + len2,
-        );
-
-        let end_cost = segm_cost(
-            &figures_on_border,
-            start1 + sz - 1,
-            start2,
-            start2 + len2 - 1,
-            start1 + len1,
-        ) + segm_cost(
-            &figures_on_border,
-            start2 + sz - 1,
-            start1,
-            start1 + len1 - 1,
-            start2 + len2,
-        );
-
-        if end_cost < start_cost {
-            eprintln!("Optimize (iter = {iter}): {start_cost} -> {end_cost}");
-
-            figures_on_border.rotate_left(start1);
-            let start2 = len1 + offset;
-
-            let next_order = figures_on_border[start2..start2 + len2]
-                .iter()
-                .chain(figures_on_border[len1..start2].iter())
-                .chain(figures_on_border[..len1].iter())
-                .chain(figures_on_border[start2 + len2..].iter())
-                .cloned()
-                .collect_vec();
-            assert_eq!(figures_on_border.len(), next_order.len());
-            figures_on_border = next_order;
-        }
-    }
-
-    let gen_placement = |order: &[BorderFigure]| -> Placement {
-        let mut placement = Placement::new();
-        for (f1, f2) in order.iter().circular_tuple_windows() {
-            let d = dist(f1.right_side, f2.left_side);
-            if d > MAX_DIST {
-                continue;
-            }
-            eprintln!("Use score: {d}");
-            if placement.join_sides(f1.right_side, f2.left_side).is_none() {
-                eprintln!("Can't join sides");
-            }
-        }
-        placement
-    };
-
-    let placement = gen_placement(&figures_on_border);
-    graph.get_subgraph(&placement)
-    //
-}
-
 fn gen_relative_dists(graph: &Graph, parsed_puzzles: &ParsedPuzzles) -> Array4<f64> {
     let n = graph.n;
 
@@ -1005,106 +959,317 @@ fn find_best_next(
     next
 }
 
-const START_VERTEX: usize = 628;
+// Result of any solver, see [crate::solver_strategy::SolverStrategy].
+#[derive(Clone, Debug)]
+pub struct CandidatePlacement {
+    // all edges of the placement, could include already known ones
+    pub edges: Vec<(Side, Side)>,
+    // candidates with the same group fill the same cells, picker shows the best of them
+    pub group: u64,
+    // key in SearchStatesCache
+    pub hash: u64,
+    // smaller is better, only comparable between candidates of the same solver
+    pub score: f64,
+    pub note: String,
+}
 
-pub fn solve_graph_add_by_3(
-    graph: &Graph,
-    parsed_puzzles: &ParsedPuzzles,
-    _prev_state: Option<Graph>,
-    known_facts: &mut KnownFacts,
-) -> InteractiveSolutionPicker {
-    assert_eq!(graph.parsed_puzzles_hash, parsed_puzzles.calc_hash());
+impl CandidatePlacement {
+    fn from_state(state: &Search3StateWithScore, note: String) -> Self {
+        Self {
+            edges: state.all_edges(),
+            group: state.get_group_hash(),
+            hash: state.get_hash(),
+            score: state.score,
+            note,
+        }
+    }
 
-    eprintln!("Hello there!");
-    let n = graph.n;
-    eprintln!("nd array created!");
-    let base_points_matrix = graph.get_base_points_matrix();
-    let positions_cache = PositionsCache::load(&parsed_puzzles);
+    pub fn from_edges(edges: Vec<(Side, Side)>, score: f64, note: String) -> Self {
+        let mut hasher = DefaultHasher::new();
+        edges.hash(&mut hasher);
+        let hash = hasher.finish();
+        Self {
+            edges,
+            group: hash,
+            hash,
+            score,
+            note,
+        }
+    }
+}
+
+const MX: f64 = f64::MAX / 100000.0;
 
-    let mut on_border = vec![false; n];
-    for v in 0..n {
-        if !parsed_puzzles.figures[v].is_good_puzzle() {
+// Picture borders of pieces marked as border or corner shouldn't be glued to anything.
+// Flat sides of other pieces are only guessed by the parser, so they are left as is.
+pub fn block_picture_sides(
+    dist: &mut Array4<f64>,
+    parsed_puzzles: &ParsedPuzzles,
+    known_facts: &KnownFacts,
+) {
+    let all_sides = parsed_puzzles.gen_all_sides();
+    let marked: BTreeSet<usize> = known_facts
+        .get_known_border_figures()
+        .into_iter()
+        .map(|(fig, _)| fig)
+        .collect();
+    for border_figure in parsed_puzzles.calc_figures_on_border_with_facts(known_facts) {
+        if !marked.contains(&border_figure.figure_id) {
             continue;
         }
-        for side in 0..4 {
-            if is_picture_border(&parsed_puzzles.figures[v], side) {
-                on_border[v] = true;
+        for s1 in border_figure.picture_sides() {
+            for &s2 in all_sides.iter() {
+                dist[[s1.fig, s1.side, s2.fig, s2.side]] = MX;
+                dist[[s2.fig, s2.side, s1.fig, s1.side]] = MX;
             }
         }
     }
+}
 
-    let all_sides = parsed_puzzles.gen_all_sides();
+// Everything solvers, which extend the component with `SolverConfig::start_vertex`, need
+// before the search.
+struct SolverSetup {
+    dist: Array4<f64>,
+    // placed or excluded figures
+    used: Vec<bool>,
+    my_comp_placement: Placement,
+}
+
+impl SolverSetup {
+    fn new(
+        graph: &Graph,
+        parsed_puzzles: &ParsedPuzzles,
+        known_facts: &KnownFacts,
+        start_vertex: usize,
+    ) -> Self {
+        assert_eq!(graph.parsed_puzzles_hash, parsed_puzzles.calc_hash());
 
-    let mut dist = gen_relative_dists(graph, parsed_puzzles);
-    const MX: f64 = f64::MAX / 100000.0;
-    for fact in known_facts.facts.iter() {
-        if !fact.good_edge {
-            dist[[
-                fact.side1.fig,
-                fact.side1.side,
-                fact.side2.fig,
-                fact.side2.side,
-            ]] = MX;
-            dist[[
-                fact.side2.fig,
-                fact.side2.side,
-                fact.side1.fig,
-                fact.side1.side,
-            ]] = MX;
+        eprintln!("Hello there!");
+        let all_sides = parsed_puzzles.gen_all_sides();
+
+        let mut dist = gen_relative_dists(graph, parsed_puzzles);
+        for fact in known_facts.facts.iter() {
+            if !fact.good_edge {
+                dist[[
+                    fact.side1.fig,
+                    fact.side1.side,
+                    fact.side2.fig,
+                    fact.side2.side,
+                ]] = MX;
+                dist[[
+                    fact.side2.fig,
+                    fact.side2.side,
+                    fact.side1.fig,
+                    fact.side1.side,
+                ]] = MX;
+            }
         }
-    }
-    let mut start_placement = Placement::new();
-    for fact in known_facts.facts.iter() {
-        if fact.good_edge {
-            start_placement.join_sides(fact.side1, fact.side2).unwrap();
+        let conflicts = known_facts.check_consistency();
+        if !conflicts.is_empty() {
+            eprintln!(
+                "Found {} conflicting facts, they will be ignored:",
+                conflicts.len()
+            );
+            for conflict in conflicts.iter() {
+                eprintln!("  {}", conflict.describe());
+            }
         }
-    }
-    for (s1, s2) in start_placement.get_all_neighbours() {
-        for &s3 in all_sides.iter() {
-            if s2 != s3 {
-                dist[[s1.fig, s1.side, s3.fig, s3.side]] = MX;
+        let start_placement = known_facts.build_placement();
+        for (s1, s2) in start_placement.get_all_neighbours() {
+            for &s3 in all_sides.iter() {
+                if s2 != s3 {
+                    dist[[s1.fig, s1.side, s3.fig, s3.side]] = MX;
+                }
             }
         }
-    }
-    let dist = |s1: Side, s2: Side| -> f64 { dist[[s1.fig, s1.side, s2.fig, s2.side]] };
-
-    let rot_positions;
+        block_picture_sides(&mut dist, parsed_puzzles, known_facts);
 
-    let states;
-    let mut used = vec![false; graph.n];
-    {
-        let mut placement = Placement::new();
-        for fact in known_facts.facts.iter() {
-            if fact.good_edge {
-                placement.join_sides(fact.side1, fact.side2).unwrap();
-            }
+        let mut used = vec![false; graph.n];
+        for v in start_placement.get_all_used_figures() {
+            used[v] = true;
         }
-        for v in placement.get_all_used_figures() {
+        for v in known_facts.get_excluded_figures() {
             used[v] = true;
         }
-        let my_comp_placement = placement.get_only_one_component_placement(START_VERTEX);
-        let edges = my_comp_placement.get_all_neighbours();
-        rot_positions = get_correct_rotation_positions(
-            &edges,
-            parsed_puzzles,
-            graph,
-            &positions_cache,
-            &base_points_matrix,
-        );
+        let my_comp_placement = start_placement.get_only_one_component_placement(start_vertex);
+        Self {
+            dist,
+            used,
+            my_comp_placement,
+        }
+    }
+
+    fn dist_fn(&self) -> impl Fn(Side, Side) -> f64 + Copy + Sync + '_ {
+        let dist = &self.dist;
+        move |s1: Side, s2: Side| -> f64 { dist[[s1.fig, s1.side, s2.fig, s2.side]] }
+    }
 
-        states = my_comp_placement.get_potential_group_locations(3);
+    fn filter_by_anchors(
+        &self,
+        states: Vec<Search3StateWithScore>,
+        known_facts: &KnownFacts,
+    ) -> Vec<Search3StateWithScore> {
+        let pins = known_facts.get_pinned_positions();
+        if pins.is_empty() && known_facts.board_size.is_none() {
+            return states;
+        }
+        let res = states
+            .into_iter()
+            .filter(|state| {
+                let mut placement = self.my_comp_placement.clone();
+                for (s1, s2) in state.all_edges() {
+                    if placement.join_sides(s1, s2).is_none() {
+                        return false;
+                    }
+                }
+                placement.is_consistent_with_anchors(&pins, known_facts.board_size)
+            })
+            .collect_vec();
+        eprintln!("After checking anchors: {} states", res.len());
+        res
+    }
 
-        eprintln!("Start states: {}", states.len());
+    // `notes` are added to candidates generated from states with the same hash
+    fn gen_candidates(
+        states: &[Search3StateWithScore],
+        notes: &[(u64, String)],
+    ) -> Vec<CandidatePlacement> {
+        states
+            .iter()
+            .map(|state| {
+                let note = notes
+                    .iter()
+                    .find(|(h, _)| *h == state.get_hash())
+                    .map(|(_, note)| note.clone())
+                    .unwrap_or_default();
+                CandidatePlacement::from_state(state, note)
+            })
+            .collect_vec()
     }
+}
+
+// Places candidates on the surface next to the known component with `start_vertex`.
+// Solutions are generated in chunks, each chunk is streamed into `sink`.
+pub fn build_solutions_picker(
+    candidates: &[CandidatePlacement],
+    graph: &Graph,
+    parsed_puzzles: &ParsedPuzzles,
+    known_facts: &KnownFacts,
+    config: &Config,
+    sink: &ProgressSink,
+) -> Option<InteractiveSolutionPicker> {
+    const CHUNK_SIZE: usize = 64;
+
+    sink.report("Preparing picker", 0.0, None);
+    let base_points_matrix = graph.get_base_points_matrix();
+    let positions_cache = PositionsCache::load(parsed_puzzles, config);
+    let my_comp_placement = known_facts
+        .build_placement()
+        .get_only_one_component_placement(config.solver.start_vertex);
+    let rot_positions = get_correct_rotation_positions(
+        &my_comp_placement.get_all_neighbours(),
+        config.solver.start_vertex,
+        parsed_puzzles,
+        graph,
+        &positions_cache,
+        &base_points_matrix,
+    );
+
+    let mut more_res = vec![];
+    let mut best_score: Option<f64> = None;
+    for (chunk_id, chunk) in candidates.chunks(CHUNK_SIZE).enumerate() {
+        if sink.is_cancelled() {
+            break;
+        }
+        let mut chunk_res: Vec<_> = chunk
+            .par_iter()
+            .filter_map(|candidate| {
+                Some((
+                    candidate,
+                    gen_potential_solution(
+                        &candidate.edges,
+                        parsed_puzzles,
+                        graph,
+                        None,
+                        known_facts,
+                        &my_comp_placement,
+                        &rot_positions,
+                        &positions_cache,
+                        &base_points_matrix,
+                    )?,
+                ))
+            })
+            .collect();
+        for (candidate, solution) in chunk_res.iter_mut() {
+            solution.additional_text += &candidate.note;
+            best_score = Some(fmin(
+                best_score.unwrap_or(f64::MAX),
+                solution.placement_score,
+            ));
+        }
+        sink.send_candidates(chunk_res.iter().map(|(_, sol)| sol.clone()).collect());
+        sink.report(
+            "Generating solutions",
+            ((chunk_id + 1) * CHUNK_SIZE).min(candidates.len()) as f32 * 100.0
+                / (candidates.len() as f32),
+            best_score,
+        );
+        more_res.extend(chunk_res);
+    }
+    eprintln!("Generated {} solutions.", more_res.len());
+    // cancelled run still shows, what was found before it
+    let sink = if sink.is_cancelled() {
+        if more_res.is_empty() {
+            return None;
+        }
+        ProgressSink::none()
+    } else {
+        sink.clone()
+    };
+    let states_cache = SearchStatesCache::load();
+    for (candidate, solution) in more_res.iter() {
+        states_cache.insert(candidate.hash, solution.placement_score);
+    }
+    states_cache.save();
+    Some(InteractiveSolutionPicker::new(
+        more_res
+            .into_iter()
+            .map(|(candidate, solution)| (candidate.group, solution))
+            .collect(),
+        config.solver.start_vertex,
+        rot_positions,
+        positions_cache,
+        base_points_matrix,
+        known_facts,
+        parsed_puzzles,
+        graph,
+        &sink,
+    ))
+}
+
+pub fn solve_graph_add_by_3(
+    graph: &Graph,
+    parsed_puzzles: &ParsedPuzzles,
+    known_facts: &KnownFacts,
+    config: &SolverConfig,
+    sink: &ProgressSink,
+) -> Vec<CandidatePlacement> {
+    sink.report("Preparing", 0.0, None);
+    let setup = SolverSetup::new(graph, parsed_puzzles, known_facts, config.start_vertex);
+    let dist = setup.dist_fn();
+    let used = &setup.used;
+
+    let states = setup
+        .my_comp_placement
+        .get_potential_group_locations(config.group_max_cnt, config.group_window);
+    eprintln!("Start states: {}", states.len());
 
-    const CHECK_BEST: usize = 500;
-    const TOO_BIG_COST: f64 = 100.0;
     let mut to_check = calc_sorted_by_dist(parsed_puzzles, dist);
     for fig in 0..to_check.len() {
         for side in 0..4 {
-            to_check[fig][side].truncate(CHECK_BEST);
+            to_check[fig][side].truncate(config.check_best);
             while let Some(s2) = to_check[fig][side].last() {
-                if dist(Side { fig, side }, *s2) > TOO_BIG_COST {
+                if dist(Side { fig, side }, *s2) > config.too_big_cost {
                     to_check[fig][side].pop();
                 } else {
                     break;
@@ -1113,60 +1278,152 @@ pub fn solve_graph_add_by_3(
         }
     }
 
-    const LIMIT: usize = 250;
-    const LIMIT_RES: usize = 50;
+    sink.report("Searching next states", 0.0, None);
     let next_states: Vec<_> = states
         .par_iter()
-        .map(|st| find_best_next(&st, LIMIT, LIMIT_RES, &to_check, &used, dist))
+        .map(|st| {
+            if sink.is_cancelled() {
+                return vec![];
+            }
+            find_best_next(st, config.limit, config.limit_res, &to_check, used, dist)
+        })
         .collect();
+    if sink.is_cancelled() {
+        return vec![];
+    }
     let mut next_states = next_states.into_iter().flatten().collect_vec();
     next_states.sort();
     eprintln!("Generated {} start states.", next_states.len());
     let states_cache = SearchStatesCache::load();
-    let next_states: Vec<_> = next_states
+    let mut next_states: Vec<_> = next_states
         .into_iter()
         .filter(|state| !states_cache.contains(state.get_hash(), 5.0))
         .collect_vec();
     eprintln!("After filtering: {} states", next_states.len());
+    // small holes are solved exactly, best fill is shown together with other states
+    let mut hole_notes = vec![];
+    sink.report("Solving holes", 0.0, None);
+    for hole in setup
+        .my_comp_placement
+        .get_enclosed_holes(config.max_hole_cells)
+    {
+        if sink.is_cancelled() {
+            return vec![];
+        }
+        if let Some(solution) = solve_hole(&hole, used, dist, sink) {
+            eprintln!("Solved hole{}", solution.describe());
+            hole_notes.push((solution.best.get_hash(), solution.describe()));
+            next_states.push(solution.best);
+        }
+    }
+    if sink.is_cancelled() {
+        return vec![];
+    }
+    let next_states = setup.filter_by_anchors(next_states, known_facts);
+    SolverSetup::gen_candidates(&next_states, &hole_notes)
+}
 
-    let more_res: Vec<_> = next_states
-        .par_iter()
-        .filter_map(|state| {
-            let used_edges = state.all_edges();
-            Some((
-                state.clone(),
-                gen_potential_solution(
-                    &used_edges,
-                    parsed_puzzles,
-                    graph,
-                    None,
-                    known_facts,
-                    &rot_positions,
-                    &positions_cache,
-                    &base_points_matrix,
-                )?,
-            ))
-        })
-        .collect();
-    eprintln!("Generated All solutions.");
-    for (a, b) in more_res.iter() {
-        states_cache.insert(a.get_hash(), b.placement_score);
+// All empty cells next to the component are filled at once, pieces for them are
+// chosen by min-cost assignment. Most confident slots are proposed first.
+pub fn solve_graph_assignment(
+    graph: &Graph,
+    parsed_puzzles: &ParsedPuzzles,
+    known_facts: &KnownFacts,
+    config: &SolverConfig,
+    sink: &ProgressSink,
+) -> Vec<CandidatePlacement> {
+    sink.report("Preparing", 0.0, None);
+    let setup = SolverSetup::new(graph, parsed_puzzles, known_facts, config.start_vertex);
+    let dist = setup.dist_fn();
+
+    sink.report("Assigning pieces", 0.0, None);
+    let slots = setup.my_comp_placement.get_potential_locations(false);
+    eprintln!("Slots: {}", slots.len());
+    let mut assignments = assign_pieces(&slots, &setup.used, dist);
+    assignments.sort_by(|a1, a2| a2.confidence.total_cmp(&a1.confidence));
+    for a in assignments.iter() {
+        eprintln!(
+            "Slot {} -> {:?}, cost = {:.3}, confidence = {:.3}",
+            a.slot, a.first_side, a.cost, a.confidence
+        );
     }
-    states_cache.save();
-    InteractiveSolutionPicker::new(
-        more_res,
-        START_VERTEX,
-        rot_positions,
-        positions_cache,
-        base_points_matrix,
-        known_facts,
-        parsed_puzzles,
-        graph,
-    )
+
+    // single pieces for the most confident slots, and then bigger and bigger groups
+    let mut groups = (1..=min(config.max_single, assignments.len()))
+        .map(|i| assignments[i - 1..i].to_vec())
+        .collect_vec();
+    let mut group_size = 2;
+    while group_size <= assignments.len() {
+        groups.push(assignments[..group_size].to_vec());
+        group_size *= 2;
+    }
+    let all_neighbours = setup.my_comp_placement.get_all_neighbours();
+    let mut states = vec![];
+    let mut notes = vec![];
+    for group in groups {
+        let locations = group.iter().map(|a| slots[a.slot].clone()).collect_vec();
+        let first_sides = group.iter().map(|a| a.first_side).collect_vec();
+        let state = setup
+            .my_comp_placement
+            .gen_group_location(locations, &all_neighbours)
+            .check_way(&first_sides, dist);
+        let min_confidence = group.iter().map(|a| a.confidence).fold(f64::MAX, fmin);
+        notes.push((
+            state.get_hash(),
+            format!(
+                ". assignment of {}, min confidence {:.3}",
+                group.len(),
+                min_confidence
+            ),
+        ));
+        states.push(state);
+    }
+    let states = setup.filter_by_anchors(states, known_facts);
+    SolverSetup::gen_candidates(&states, &notes)
+}
+
+// Replaces figures of the known component with unused ones, if they fit better.
+pub fn solve_local_improvement(
+    graph: &Graph,
+    parsed_puzzles: &ParsedPuzzles,
+    known_facts: &KnownFacts,
+    config: &SolverConfig,
+) -> Vec<CandidatePlacement> {
+    let dist = gen_relative_dists(graph, parsed_puzzles);
+    let dist = |s1: Side, s2: Side| -> f64 {
+        if known_facts.get_edge_state(s1, s2) == EdgeState::WrongEdge {
+            f64::MAX
+        } else {
+            dist[[s1.fig, s1.side, s2.fig, s2.side]]
+        }
+    };
+    let placement = known_facts
+        .build_placement()
+        .get_only_one_component_placement(config.start_vertex);
+    let placement = optimize_start_position(placement, graph.n, dist);
+    let edges = placement.get_all_neighbours();
+    // wrong edges, which could not be replaced, are not scored, otherwise the score is infinite
+    let scored_edges = edges
+        .iter()
+        .filter(|&&(s1, s2)| known_facts.get_edge_state(s1, s2) != EdgeState::WrongEdge)
+        .collect_vec();
+    let av_edge_dist = scored_edges
+        .iter()
+        .map(|&&(s1, s2)| dist(s1, s2))
+        .sum::<f64>()
+        / (scored_edges.len().max(1) as f64);
+    // each edge is listed in both directions
+    let cnt_wrong = (edges.len() - scored_edges.len()) / 2;
+    vec![CandidatePlacement::from_edges(
+        edges,
+        av_edge_dist,
+        format!(". local improvement, wrong edges left: {cnt_wrong}"),
+    )]
 }
 
 fn get_correct_rotation_positions(
     edges: &[(Side, Side)],
+    start_vertex: usize,
     parsed_puzzles: &ParsedPuzzles,
     graph: &Graph,
     positions_cache: &PositionsCache,
@@ -1196,7 +1453,7 @@ fn get_correct_rotation_positions(
         &mut positions,
         graph,
         parsed_puzzles,
-        &[START_VERTEX],
+        &[start_vertex],
         &[],
     );
     positions
@@ -1208,6 +1465,7 @@ pub fn gen_potential_solution(
     graph: &Graph,
     set_fixed_score: Option<f64>,
     known_facts: &KnownFacts,
+    known_placement: &Placement,
     rot_positions: &[Option<Vec<PointF>>],
     positions_cache: &PositionsCache,
     base_points_matrix: &Array4<[PointF; 2]>,
@@ -1254,6 +1512,46 @@ pub fn gen_potential_solution(
         .filter(|x| !placed_vertices.contains(x))
         .cloned()
         .collect_vec();
+
+    let closure_score = if set_fixed_score.is_some() {
+        0.0
+    } else {
+        // only edges with figures outside of the known component are added
+        let mut in_known = vec![false; parsed_puzzles.figures.len()];
+        for fig in known_placement.get_all_used_figures() {
+            in_known[fig] = true;
+        }
+        let mut placement = known_placement.clone();
+        for &(s1, s2) in edges.iter() {
+            if !in_known[s1.fig] || !in_known[s2.fig] {
+                placement.join_sides(s1, s2);
+            }
+        }
+        calc_closure_score(&calc_corner_closures_with_figures(
+            &placement,
+            parsed_puzzles,
+            &placed_figures,
+            &new_figures_used,
+        ))
+    };
+
+    let overlap_score = if set_fixed_score.is_some() {
+        0.0
+    } else {
+        let config = &positions_cache.config().solver;
+        let overlaps = calc_overlaps(&placed_figures, &new_figures_used, config);
+        if let Some(worst) = overlaps
+            .iter()
+            .find(|overlap| overlap.overlap > config.max_overlap)
+        {
+            eprintln!(
+                "Figure {} overlaps figure {} by {:.3}, skipping",
+                worst.fig, worst.other, worst.overlap
+            );
+            return None;
+        }
+        calc_overlap_score(&overlaps, config)
+    };
     let new_edges_used = edges
         .iter()
         .filter(|&&(s1, s2)| {
@@ -1263,15 +1561,39 @@ pub fn gen_potential_solution(
         })
         .cloned()
         .collect_vec();
+    let new_edge_scores = new_edges_used
+        .iter()
+        .map(|&(s1, s2)| {
+            get_borders_dist(
+                positions[s1.fig].as_ref().unwrap(),
+                positions[s2.fig].as_ref().unwrap(),
+                parsed_puzzles,
+                s1,
+                s2,
+                &positions_cache.config().matcher,
+            )
+        })
+        .collect_vec();
 
+    let placement_score = set_fixed_score.unwrap_or(placement_score);
+    let ranking_score = PotentialSolution::calc_ranking_score(
+        placement_score,
+        closure_score,
+        overlap_score,
+        &positions_cache.config().solver,
+    );
     Some(PotentialSolution {
         placed_figures,
-        placement_score: set_fixed_score.unwrap_or(placement_score),
+        placement_score,
         text_offset: PointF { x: 0.0, y: 0.0 },
         additional_text: "".to_owned(),
         debug_lines: vec![],
         bbox: (PointF::ZERO, PointF::ZERO),
         new_figures_used,
         new_edges_used,
+        new_edge_scores,
+        closure_score,
+        overlap_score,
+        ranking_score,
     })
 }
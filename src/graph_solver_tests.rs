@@ -0,0 +1,122 @@
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use ndarray::Array4;
+
+    use crate::{
+        borders_graph::Graph,
+        graph_solver::{block_picture_sides, SearchState, SolveGraphCheckpoint},
+        known_facts::{KnownFacts, PieceFact, PieceFactKind},
+        parsed_puzzles::ParsedPuzzles,
+        synthetic::{gen_pattern_image, SyntheticPuzzle},
+        topn::TopN,
+        utils::Side,
+    };
+
+    fn side(fig: usize, side: usize) -> Side {
+        Side { fig, side }
+    }
+
+    #[test]
+    pub fn checkpoint_save_and_resume() {
+        let path = std::env::temp_dir().join("solve_graph_checkpoint_test.json");
+        let path = path.to_str().unwrap();
+        let small = SearchState::new(vec![(side(0, 1), side(1, 3))], 1.0, (1, 2));
+        let big = SearchState::new(
+            vec![(side(0, 1), side(1, 3)), (side(1, 2), side(2, 0))],
+            2.0,
+            (2, 2),
+        );
+        let mut pq = vec![TopN::new(5); 4];
+        pq[3].insert(big.clone());
+        SolveGraphCheckpoint {
+            inputs_hash: 42,
+            cnt_vertices: 2,
+            pq,
+            pending: vec![small.clone()],
+            states_explored: 7,
+        }
+        .save(path);
+
+        let resumed = SolveGraphCheckpoint::load(path, 42).unwrap();
+        assert_eq!(resumed.cnt_vertices, 2);
+        assert_eq!(resumed.states_explored, 7);
+        assert_eq!(resumed.pending, vec![small]);
+        assert_eq!(resumed.pq.len(), 4);
+        assert!(resumed.pq[2].set.is_empty());
+        assert_eq!(resumed.pq[3].get_best(), Some(big));
+
+        // other inputs
+        assert!(SolveGraphCheckpoint::load(path, 43).is_none());
+        // broken file
+        fs::write(path, "{").unwrap();
+        assert!(SolveGraphCheckpoint::load(path, 42).is_none());
+        SolveGraphCheckpoint::remove(path);
+        assert!(SolveGraphCheckpoint::load(path, 42).is_none());
+    }
+
+    #[test]
+    pub fn checkpoint_inputs_hash() {
+        let graph = Graph {
+            n: 3,
+            all_edges: vec![],
+            parsed_puzzles_hash: 1,
+            matcher_hash: 0,
+        };
+        let edges = vec![(side(0, 1), side(1, 3))];
+        let known_facts = KnownFacts::from_facts(vec![]);
+        let hash = |graph: &Graph, edges: &[(Side, Side)], known_facts: &KnownFacts, settings| {
+            SolveGraphCheckpoint::calc_inputs_hash(graph, edges, known_facts, settings)
+        };
+        let base = hash(&graph, &edges, &known_facts, "1");
+        assert_eq!(base, hash(&graph, &edges, &known_facts, "1"));
+        assert_ne!(base, hash(&graph, &edges, &known_facts, "2"));
+        assert_ne!(base, hash(&graph, &[], &known_facts, "1"));
+        let other_graph = Graph {
+            parsed_puzzles_hash: 2,
+            matcher_hash: 0,
+            ..graph.clone()
+        };
+        assert_ne!(base, hash(&other_graph, &edges, &known_facts, "1"));
+
+        let mut with_board = known_facts.clone();
+        with_board.board_size = Some((2, 2));
+        assert_ne!(base, hash(&graph, &edges, &with_board, "1"));
+        let mut with_excluded = known_facts.clone();
+        with_excluded.piece_facts = vec![PieceFact::new(2, PieceFactKind::Excluded)];
+        assert_ne!(base, hash(&graph, &edges, &with_excluded, "1"));
+        let mut with_pin = known_facts.clone();
+        with_pin.piece_facts = vec![PieceFact::new(2, PieceFactKind::Pinned { row: 0, col: 1 })];
+        assert_ne!(base, hash(&graph, &edges, &with_pin, "1"));
+    }
+
+    #[test]
+    pub fn only_marked_picture_sides_are_blocked() {
+        let source = gen_pattern_image(450, 450, 3);
+        let puzzle = SyntheticPuzzle::generate(&source, 3, 3, 6);
+        let parsed_puzzles = ParsedPuzzles::new(&puzzle.image);
+        let border_figures = parsed_puzzles.calc_figures_on_border();
+        assert!(border_figures.len() >= 2);
+        let (marked, unmarked) = (&border_figures[0], &border_figures[1]);
+        let kind = if marked.is_corner() {
+            PieceFactKind::CornerPiece
+        } else {
+            PieceFactKind::BorderPiece
+        };
+        let mut known_facts = KnownFacts::from_facts(vec![]);
+        known_facts.piece_facts = vec![PieceFact::new(marked.figure_id, kind)];
+
+        let n = parsed_puzzles.figures.len();
+        let mut dist = Array4::zeros((n, 4, n, 4));
+        block_picture_sides(&mut dist, &parsed_puzzles, &known_facts);
+        let blocked = |s: Side| {
+            parsed_puzzles
+                .gen_all_sides()
+                .iter()
+                .all(|s2| dist[[s.fig, s.side, s2.fig, s2.side]] > 0.0)
+        };
+        assert!(marked.picture_sides().into_iter().all(blocked));
+        assert!(!unmarked.picture_sides().into_iter().any(blocked));
+    }
+}
@@ -0,0 +1,191 @@
+use std::fs;
+
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    borders_graph::Graph,
+    known_facts::{Fact, FactSource, KnownFacts},
+    placement::{PlacedCell, Placement},
+    utils::{dedup_edges, Side},
+};
+
+pub const DEFAULT_PATH: &str = "ground_truth.json";
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct GridCell {
+    pub fig: usize,
+    // side of the figure, which points up. If None, sides with the best scores are used
+    pub up: Option<usize>,
+}
+
+impl GridCell {
+    // Sides which could point in direction `dir`: 0 - up, 1 - right, 2 - down, 3 - left.
+    // Figure sides go counter-clockwise on the photo.
+    fn sides_to(&self, dir: usize) -> Vec<Side> {
+        match self.up {
+            Some(up) => vec![Side {
+                fig: self.fig,
+                side: (up + 4 - dir) % 4,
+            }],
+            None => (0..4)
+                .map(|side| Side {
+                    fig: self.fig,
+                    side,
+                })
+                .collect_vec(),
+        }
+    }
+}
+
+// One verified area, rows from top to bottom. Unknown cells are None.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct GridArea {
+    pub cells: Vec<Vec<Option<GridCell>>>,
+}
+
+impl GridArea {
+    // Sides in placement go clockwise, so its columns are mirrored to look like the photo.
+    fn from_cells(cells: &[PlacedCell]) -> Self {
+        let rows = cells.iter().map(|((r, _), _)| r + 1).max().unwrap_or(0);
+        let cols = cells.iter().map(|((_, c), _)| c + 1).max().unwrap_or(0);
+        let mut res = vec![vec![None; cols]; rows];
+        for &((r, c), sides) in cells.iter() {
+            res[r][cols - 1 - c] = Some(GridCell {
+                fig: sides[0].fig,
+                up: Some(sides[0].side),
+            });
+        }
+        Self { cells: res }
+    }
+
+    pub fn cnt_figures(&self) -> usize {
+        self.cells.iter().flatten().flatten().count()
+    }
+
+    fn potential_edges(&self) -> Vec<(Side, Side)> {
+        let mut res = vec![];
+        for r in 0..self.cells.len() {
+            for c in 0..self.cells[r].len() {
+                // right and down neighbours
+                for (dr, dc, dir) in [(0, 1, 1), (1, 0, 2)] {
+                    let other = self
+                        .cells
+                        .get(r + dr)
+                        .and_then(|row| row.get(c + dc))
+                        .cloned()
+                        .flatten();
+                    if let (Some(cell1), Some(cell2)) = (self.cells[r][c], other) {
+                        for s1 in cell1.sides_to(dir) {
+                            for s2 in cell2.sides_to((dir + 2) % 4) {
+                                res.push((s1, s2));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        res
+    }
+
+    // Sides of cells without rotation are chosen greedily by `dist`.
+    pub fn to_placement(&self, dist: &impl Fn(Side, Side) -> f64) -> Option<Placement> {
+        let mut all_potential_edges = self.potential_edges();
+        all_potential_edges.sort_by(|&(s1, s2), &(s3, s4)| dist(s1, s2).total_cmp(&dist(s3, s4)));
+        let mut placement = Placement::new();
+        for &(s1, s2) in all_potential_edges.iter() {
+            let placement_copy = placement.clone();
+            if placement.join_sides(s1, s2).is_some()
+                && !placement.get_all_neighbours().iter().all(|&(s1, s2)| {
+                    all_potential_edges.contains(&(s1, s2))
+                        || all_potential_edges.contains(&(s2, s1))
+                })
+            {
+                placement = placement_copy;
+            }
+        }
+        if placement.get_cnt_figures() != self.cnt_figures() {
+            eprintln!(
+                "Only {}/{} figures of the ground truth area are placed",
+                placement.get_cnt_figures(),
+                self.cnt_figures()
+            );
+            return None;
+        }
+        Some(placement)
+    }
+}
+
+// Verified parts of the puzzle, could cover only some areas.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct GroundTruth {
+    pub areas: Vec<GridArea>,
+}
+
+impl GroundTruth {
+    pub fn load(path: &str) -> Option<Self> {
+        let content = fs::read_to_string(path).ok()?;
+        Some(serde_json::from_str(&content).unwrap())
+    }
+
+    pub fn save(&self, path: &str) {
+        fs::write(path, serde_json::to_string_pretty(self).unwrap()).unwrap();
+    }
+
+    // each component is a separate area
+    pub fn from_placement(placement: &Placement) -> Self {
+        Self {
+            areas: placement
+                .get_cells_by_component()
+                .iter()
+                .map(|cells| GridArea::from_cells(cells))
+                .collect(),
+        }
+    }
+
+    pub fn from_known_facts(known_facts: &KnownFacts) -> Self {
+        Self::from_placement(&known_facts.build_placement())
+    }
+
+    pub fn cnt_figures(&self) -> usize {
+        self.areas.iter().map(|area| area.cnt_figures()).sum()
+    }
+
+    // None if areas contradict each other
+    pub fn to_placement(&self, dist: &impl Fn(Side, Side) -> f64) -> Option<Placement> {
+        let mut res = Placement::new();
+        for area in self.areas.iter() {
+            if !res.join_with(&area.to_placement(dist)?) {
+                eprintln!("Ground truth areas are inconsistent");
+                return None;
+            }
+        }
+        Some(res)
+    }
+
+    pub fn to_facts(&self, dist: &impl Fn(Side, Side) -> f64) -> Option<Vec<Fact>> {
+        let placement = self.to_placement(dist)?;
+        Some(
+            dedup_edges(&placement.get_all_neighbours())
+                .into_iter()
+                .map(|(s1, s2)| Fact::new(s1, s2, true).with_source(FactSource::Import))
+                .collect(),
+        )
+    }
+}
+
+// Verified areas from the default file, sides without rotation are picked by the graph.
+pub fn load_known_placement(graph: &Graph) -> Placement {
+    let ground_truth = match GroundTruth::load(DEFAULT_PATH) {
+        Some(ground_truth) => ground_truth,
+        None => {
+            eprintln!("No ground truth in {DEFAULT_PATH}");
+            return Placement::new();
+        }
+    };
+    let dist = graph.gen_adj_matrix();
+    let dist = |s1: Side, s2: Side| dist[[s1.fig, s1.side, s2.fig, s2.side]];
+    ground_truth
+        .to_placement(&dist)
+        .expect("Ground truth is inconsistent")
+}
@@ -0,0 +1,68 @@
+#[cfg(test)]
+mod tests {
+    use crate::{
+        ground_truth::{GridArea, GridCell, GroundTruth},
+        placement::Placement,
+        utils::{dedup_edges, Side},
+    };
+
+    fn side(fig: usize, side: usize) -> Side {
+        Side { fig, side }
+    }
+
+    #[test]
+    pub fn placement_round_trip() {
+        // 0 1
+        // 2 3, 1 and 3 are rotated
+        let mut placement = Placement::new();
+        placement.join_sides(side(0, 1), side(1, 0)).unwrap();
+        placement.join_sides(side(0, 2), side(2, 0)).unwrap();
+        placement.join_sides(side(2, 1), side(3, 0)).unwrap();
+        placement.join_sides(side(5, 3), side(6, 1)).unwrap();
+
+        let ground_truth = GroundTruth::from_placement(&placement);
+        assert_eq!(ground_truth.areas.len(), 2);
+        assert_eq!(ground_truth.cnt_figures(), 6);
+        let restored = ground_truth.to_placement(&|_, _| 0.0).unwrap();
+        assert_eq!(
+            dedup_edges(&restored.get_all_neighbours()),
+            dedup_edges(&placement.get_all_neighbours())
+        );
+        assert_eq!(restored.count_same_cells(&placement), 6);
+    }
+
+    #[test]
+    pub fn unknown_rotation_by_dist() {
+        let area = GridArea {
+            cells: vec![vec![
+                Some(GridCell { fig: 0, up: None }),
+                None,
+                Some(GridCell { fig: 2, up: None }),
+            ]],
+        };
+        // single figures are not placed
+        assert!(area.to_placement(&|_, _| 0.0).is_none());
+
+        let area = GridArea {
+            cells: vec![vec![
+                Some(GridCell {
+                    fig: 0,
+                    up: Some(3),
+                }),
+                Some(GridCell { fig: 1, up: None }),
+            ]],
+        };
+        let dist = |s1: Side, s2: Side| {
+            if s2 == side(1, 2) {
+                0.0
+            } else {
+                1.0 + (s1.side + s2.side) as f64
+            }
+        };
+        let placement = area.to_placement(&dist).unwrap();
+        assert_eq!(
+            dedup_edges(&placement.get_all_neighbours()),
+            vec![(side(0, 2), side(1, 2))]
+        );
+    }
+}
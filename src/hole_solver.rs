@@ -0,0 +1,236 @@
+use itertools::Itertools;
+
+use crate::{
+    placement::{PotentialGroupLocation, Search3StateWithScore},
+    solver_worker::ProgressSink,
+    utils::Side,
+};
+
+const MAX_EDGE_COST: f64 = 100.0;
+
+#[derive(Clone)]
+pub struct HoleSolution {
+    pub best: Search3StateWithScore,
+    pub runner_up: Option<Search3StateWithScore>,
+    // difference between sums of edge costs of the runner-up and the best fill
+    pub margin: f64,
+    // false if the search was stopped by iterations limit or cancelled
+    pub proven: bool,
+}
+
+impl HoleSolution {
+    pub fn describe(&self) -> String {
+        let runner_up = match &self.runner_up {
+            Some(state) => state.first_sides.iter().map(|s| s.fig).join(","),
+            None => "none".to_owned(),
+        };
+        format!(
+            ". hole {}: margin {:.3} (runner-up: {}){}",
+            self.best.state.locations.len(),
+            self.margin,
+            runner_up,
+            if self.proven { "" } else { " (not proven)" }
+        )
+    }
+}
+
+struct HoleSearch<'a, F: Fn(Side, Side) -> f64> {
+    hole: &'a PotentialGroupLocation,
+    order: Vec<usize>,
+    // for each location: (first side, cost of edges with existing figures), sorted by cost
+    candidates: Vec<Vec<(Side, f64)>>,
+    // sum of the cheapest candidates for order[k..]
+    rest_lower_bound: Vec<f64>,
+    dist: F,
+    used: Vec<bool>,
+    first_sides: Vec<Side>,
+    // two best fills
+    top: Vec<(f64, Vec<Side>)>,
+    iters: usize,
+    proven: bool,
+    sink: &'a ProgressSink,
+}
+
+impl<'a, F: Fn(Side, Side) -> f64> HoleSearch<'a, F> {
+    const MAX_ITERS: usize = 20_000_000;
+    const CANCEL_CHECK_ITERS: usize = 10_000;
+
+    fn bound(&self) -> f64 {
+        if self.top.len() == 2 {
+            self.top[1].0
+        } else {
+            f64::MAX
+        }
+    }
+
+    // cost of edges between location `loc` and locations, which are already filled
+    fn new_edges_cost(&self, k: usize, side: Side) -> f64 {
+        let loc = self.order[k];
+        let filled = &self.order[..k];
+        let mut res = 0.0;
+        for edge in self.hole.new_figures_edges.iter() {
+            let [id1, id2] = edge.location_ids;
+            let (s1, s2) = if id1 == loc && filled.contains(&id2) {
+                (side, self.first_sides[id2])
+            } else if id2 == loc && filled.contains(&id1) {
+                (self.first_sides[id1], side)
+            } else {
+                continue;
+            };
+            let cost = (self.dist)(s1.ne_offset(edge.sides[0]), s2.ne_offset(edge.sides[1]));
+            if cost > MAX_EDGE_COST {
+                return f64::MAX;
+            }
+            res += cost;
+        }
+        res
+    }
+
+    fn go(&mut self, k: usize, cost: f64) {
+        self.iters += 1;
+        if self.iters > Self::MAX_ITERS || !self.proven {
+            self.proven = false;
+            return;
+        }
+        // checking an atomic on every iteration is too slow
+        if self.iters % Self::CANCEL_CHECK_ITERS == 0 && self.sink.is_cancelled() {
+            self.proven = false;
+            return;
+        }
+        if k == self.order.len() {
+            self.top.push((cost, self.first_sides.clone()));
+            self.top.sort_by(|(c1, _), (c2, _)| c1.total_cmp(c2));
+            self.top.truncate(2);
+            return;
+        }
+        let loc = self.order[k];
+        for i in 0..self.candidates[loc].len() {
+            let (side, existing_cost) = self.candidates[loc][i];
+            // candidates are sorted, so nothing better later
+            if cost + existing_cost + self.rest_lower_bound[k + 1] >= self.bound() {
+                break;
+            }
+            if self.used[side.fig] {
+                continue;
+            }
+            self.first_sides[loc] = side;
+            let new_cost = self.new_edges_cost(k, side);
+            if new_cost == f64::MAX {
+                continue;
+            }
+            let next_cost = cost + existing_cost + new_cost;
+            if next_cost + self.rest_lower_bound[k + 1] >= self.bound() {
+                continue;
+            }
+            self.used[side.fig] = true;
+            self.go(k + 1, next_cost);
+            self.used[side.fig] = false;
+        }
+    }
+}
+
+// Locations with more known neighbours go first, so bounds become tight quickly.
+fn calc_search_order(hole: &PotentialGroupLocation) -> Vec<usize> {
+    let mut order = vec![];
+    while order.len() != hole.locations.len() {
+        let next = (0..hole.locations.len())
+            .filter(|id| !order.contains(id))
+            .max_by_key(|&id| {
+                let existing = hole.locations[id]
+                    .neighbors
+                    .iter()
+                    .filter(|ne| ne.is_some())
+                    .count();
+                let filled = hole
+                    .new_figures_edges
+                    .iter()
+                    .filter(|e| {
+                        let [id1, id2] = e.location_ids;
+                        (id1 == id && order.contains(&id2)) || (id2 == id && order.contains(&id1))
+                    })
+                    .count();
+                (existing + filled, usize::MAX - id)
+            })
+            .unwrap();
+        order.push(next);
+    }
+    order
+}
+
+// Branch and bound over all unused figures and rotations for every location of the hole.
+// Returns the best fill by sum of edge costs and the runner-up, edges more expensive
+// than MAX_EDGE_COST are never used.
+pub fn solve_hole(
+    hole: &PotentialGroupLocation,
+    used: &[bool],
+    dist: impl Fn(Side, Side) -> f64 + Copy,
+    sink: &ProgressSink,
+) -> Option<HoleSolution> {
+    let n = used.len();
+    let candidates = hole
+        .locations
+        .iter()
+        .map(|loc| {
+            let mut res = vec![];
+            for fig in (0..n).filter(|&fig| !used[fig]) {
+                'rotations: for side in 0..4 {
+                    let first_side = Side { fig, side };
+                    let mut cost = 0.0;
+                    for offset in 0..4 {
+                        if let Some(s) = loc.neighbors[offset] {
+                            let edge_cost = dist(s, first_side.ne_offset(offset));
+                            if edge_cost > MAX_EDGE_COST {
+                                continue 'rotations;
+                            }
+                            cost += edge_cost;
+                        }
+                    }
+                    res.push((first_side, cost));
+                }
+            }
+            res.sort_by(|(_, c1), (_, c2)| c1.total_cmp(c2));
+            res
+        })
+        .collect_vec();
+    if candidates.iter().any(|c| c.is_empty()) {
+        return None;
+    }
+    let order = calc_search_order(hole);
+    let mut rest_lower_bound = vec![0.0; order.len() + 1];
+    for k in (0..order.len()).rev() {
+        rest_lower_bound[k] = rest_lower_bound[k + 1] + candidates[order[k]][0].1;
+    }
+
+    let mut search = HoleSearch {
+        hole,
+        order,
+        candidates,
+        rest_lower_bound,
+        dist,
+        used: used.to_vec(),
+        first_sides: vec![Side::default(); hole.locations.len()],
+        top: vec![],
+        iters: 0,
+        proven: true,
+        sink,
+    };
+    search.go(0, 0.0);
+    eprintln!(
+        "Hole with {} cells solved in {} iterations",
+        hole.locations.len(),
+        search.iters
+    );
+
+    let mut top = search.top.into_iter();
+    let (best_cost, best_sides) = top.next()?;
+    let runner_up = top.next();
+    Some(HoleSolution {
+        best: hole.check_way(&best_sides, dist),
+        margin: runner_up
+            .as_ref()
+            .map(|(cost, _)| cost - best_cost)
+            .unwrap_or(f64::MAX),
+        runner_up: runner_up.map(|(_, sides)| hole.check_way(&sides, dist)),
+        proven: search.proven,
+    })
+}
@@ -0,0 +1,96 @@
+#[cfg(test)]
+mod tests {
+    use crate::{
+        hole_solver::solve_hole,
+        known_facts::{Fact, KnownFacts},
+        solver_worker::ProgressSink,
+        utils::Side,
+    };
+
+    fn side(fig: usize, side: usize) -> Side {
+        Side { fig, side }
+    }
+
+    #[test]
+    pub fn fill_one_cell_hole() {
+        // 0 1 2
+        // 3 _ 4
+        // 5 6 7
+        let known_facts = KnownFacts::from_facts(vec![
+            Fact::new(side(0, 1), side(1, 3), true),
+            Fact::new(side(1, 1), side(2, 3), true),
+            Fact::new(side(5, 1), side(6, 3), true),
+            Fact::new(side(6, 1), side(7, 3), true),
+            Fact::new(side(0, 2), side(3, 0), true),
+            Fact::new(side(3, 2), side(5, 0), true),
+            Fact::new(side(2, 2), side(4, 0), true),
+            Fact::new(side(4, 2), side(7, 0), true),
+        ]);
+        let placement = known_facts.build_placement();
+        assert_eq!(placement.get_cnt_figures(), 8);
+        let holes = placement.get_enclosed_holes(9);
+        assert_eq!(holes.len(), 1);
+        assert_eq!(holes[0].locations.len(), 1);
+
+        // figure 8 fits better than 9, but only without rotation
+        let dist = |s1: Side, s2: Side| -> f64 {
+            let base = if s1.fig == 8 || s2.fig == 8 { 0.1 } else { 0.2 };
+            if (s1.side + 2) % 4 == s2.side {
+                base
+            } else {
+                10.0
+            }
+        };
+        let mut used = vec![false; 10];
+        used[..8].fill(true);
+        let solution = solve_hole(&holes[0], &used, dist, &ProgressSink::none()).unwrap();
+        assert!(solution.proven);
+        assert_eq!(solution.best.first_sides[0].fig, 8);
+        assert_eq!(solution.runner_up.unwrap().first_sides[0].fig, 9);
+        assert!((solution.margin - 0.4).abs() < 1e-9);
+    }
+
+    #[test]
+    pub fn find_3x3_hole() {
+        // ring of 16 figures around an empty 3x3 square
+        const SIZE: usize = 5;
+        let mut ids = [[None; SIZE]; SIZE];
+        let mut cnt = 0;
+        for (r, row) in ids.iter_mut().enumerate() {
+            for (c, id) in row.iter_mut().enumerate() {
+                if r == 0 || c == 0 || r == SIZE - 1 || c == SIZE - 1 {
+                    *id = Some(cnt);
+                    cnt += 1;
+                }
+            }
+        }
+        let mut facts = vec![];
+        for r in 0..SIZE {
+            for c in 0..SIZE {
+                if let Some(fig) = ids[r][c] {
+                    if let Some(Some(right)) = ids[r].get(c + 1) {
+                        facts.push(Fact::new(side(fig, 1), side(*right, 3), true));
+                    }
+                    if let Some(Some(down)) = ids.get(r + 1).map(|row| row[c]) {
+                        facts.push(Fact::new(side(fig, 2), side(down, 0), true));
+                    }
+                }
+            }
+        }
+        let placement = KnownFacts::from_facts(facts).build_placement();
+        assert_eq!(placement.get_cnt_figures(), 16);
+        assert!(placement.get_enclosed_holes(8).is_empty());
+        let holes = placement.get_enclosed_holes(9);
+        assert_eq!(holes.len(), 1);
+        let hole = &holes[0];
+        assert_eq!(hole.locations.len(), 9);
+        // only the center cell doesn't touch the ring
+        let cnt_inner = hole
+            .locations
+            .iter()
+            .filter(|loc| loc.neighbors.iter().all(|n| n.is_none()))
+            .count();
+        assert_eq!(cnt_inner, 1);
+        assert_eq!(hole.new_figures_edges.len(), 12);
+    }
+}
@@ -1,4 +1,4 @@
-use std::time::Instant;
+use std::{f64::consts::PI, time::Instant};
 
 use eframe::epaint::ColorImage;
 use egui_extras::RetainedImage;
@@ -7,19 +7,32 @@ use ndarray::Array4;
 
 use crate::{
     borders_graph::Graph,
+    bundle_adjustment::AdjustmentReport,
     graph_solver::{gen_potential_solution, PotentialSolution},
-    known_facts::KnownFacts,
+    known_facts::{Fact, KnownFacts},
+    loop_closure::{calc_corner_closures, find_suspicious_facts},
     parsed_puzzles::ParsedPuzzles,
-    placement::{Placement, Search3StateWithScore},
     point::PointF,
     positions_cache::PositionsCache,
+    solver_worker::ProgressSink,
     surface_placer::put_solutions_on_surface,
 };
 
 pub struct InteractiveSolutionPicker {
     pub solutions_to_show: Vec<PotentialSolution>,
     pub mask_image: RetainedImage,
-    all_solutions: Vec<(Search3StateWithScore, PotentialSolution)>,
+    // known good facts around corners, which don't close well, with their spread
+    pub suspicious_facts: Vec<(Fact, f64)>,
+    // result of the last [InteractiveSolutionPicker::global_refine]
+    pub adjustment_report: Option<AdjustmentReport>,
+    // all solutions are turned by this number of 90 degrees clockwise turns
+    pub assembly_rotation: usize,
+    // candidates are shown as zoomed crops around their new figures
+    pub diff_view: bool,
+    // same as `solutions_to_show`, but before they are put on the surface
+    unplaced_solutions: Vec<PotentialSolution>,
+    // (group of the candidate, solution), only the best solution of each group is shown
+    all_solutions: Vec<(u64, PotentialSolution)>,
     start_vertex: usize,
     rot_positions: Vec<Option<Vec<PointF>>>,
     positions_cache: PositionsCache,
@@ -28,7 +41,8 @@ pub struct InteractiveSolutionPicker {
 
 impl InteractiveSolutionPicker {
     pub fn new(
-        all_solutions: Vec<(Search3StateWithScore, PotentialSolution)>,
+        // (group of the candidate, solution), only the best solution of each group is shown
+        all_solutions: Vec<(u64, PotentialSolution)>,
         start_vertex: usize,
         rot_positions: Vec<Option<Vec<PointF>>>,
         positions_cache: PositionsCache,
@@ -36,54 +50,85 @@ impl InteractiveSolutionPicker {
         known_facts: &KnownFacts,
         parsed_puzzles: &ParsedPuzzles,
         graph: &Graph,
+        sink: &ProgressSink,
     ) -> Self {
         let mut res = Self {
             solutions_to_show: vec![],
             mask_image: RetainedImage::from_color_image("", ColorImage::default()),
+            suspicious_facts: vec![],
+            adjustment_report: None,
+            assembly_rotation: 0,
+            diff_view: false,
+            unplaced_solutions: vec![],
             all_solutions,
             start_vertex,
             rot_positions,
             positions_cache,
             base_points_matrix,
         };
-        res.refresh(known_facts, parsed_puzzles, graph);
+        res.refresh(known_facts, parsed_puzzles, graph, sink);
         res
     }
 
+    // Copy for a background job, the UI keeps showing the original one. Mask image is
+    // generated again, when solutions are put on the surface.
+    pub fn copy_for_job(&self) -> Self {
+        Self {
+            solutions_to_show: self.solutions_to_show.clone(),
+            mask_image: RetainedImage::from_color_image("", ColorImage::default()),
+            suspicious_facts: self.suspicious_facts.clone(),
+            adjustment_report: self.adjustment_report.clone(),
+            assembly_rotation: self.assembly_rotation,
+            diff_view: self.diff_view,
+            unplaced_solutions: self.unplaced_solutions.clone(),
+            all_solutions: self.all_solutions.clone(),
+            start_vertex: self.start_vertex,
+            rot_positions: self.rot_positions.clone(),
+            positions_cache: self.positions_cache.clone(),
+            base_points_matrix: self.base_points_matrix.clone(),
+        }
+    }
+
     pub fn refresh(
         &mut self,
         known_facts: &KnownFacts,
         parsed_puzzles: &ParsedPuzzles,
         graph: &Graph,
+        sink: &ProgressSink,
     ) {
         eprintln!("Start interactive solution picker refresh!");
+        sink.report("Refreshing solutions", 0.0, None);
         let start = Instant::now();
         self.all_solutions = self
             .all_solutions
             .iter()
-            .filter_map(|(state, sol)| {
+            .filter_map(|(group, sol)| {
                 let new_sol = sol.refresh(known_facts)?;
-                Some((state.clone(), new_sol.clone()))
+                Some((*group, new_sol.clone()))
             })
             .collect_vec();
-        self.solutions_to_show.clear();
-        self.solutions_to_show.push(self.gen_basic_known_solution(
-            known_facts,
+        // previous view stays as is if refresh is cancelled
+        let mut solutions_to_show = vec![];
+        let basic_solution = self.gen_basic_known_solution(known_facts, parsed_puzzles, graph);
+        let closures = calc_corner_closures(
+            &known_facts.build_placement(),
             parsed_puzzles,
-            graph,
-        ));
-        self.all_solutions.sort_by(|(a, b), (c, d)| {
-            a.get_key()
-                .cmp(&c.get_key())
-                .then(b.placement_score.total_cmp(&d.placement_score))
-        });
+            &basic_solution.placed_figures,
+        );
+        self.suspicious_facts = find_suspicious_facts(known_facts, &closures);
+        if !self.suspicious_facts.is_empty() {
+            eprintln!("Suspicious facts: {}", self.suspicious_facts.len());
+        }
+        solutions_to_show.push(basic_solution);
+        self.all_solutions
+            .sort_by(|(a, b), (c, d)| a.cmp(c).then(b.ranking_score.total_cmp(&d.ranking_score)));
 
         {
             let mut i = 0;
             while i != self.all_solutions.len() {
                 let mut j = i;
                 while j != self.all_solutions.len()
-                    && self.all_solutions[i].0.get_key() == self.all_solutions[j].0.get_key()
+                    && self.all_solutions[i].0 == self.all_solutions[j].0
                 {
                     j += 1;
                 }
@@ -98,20 +143,74 @@ impl InteractiveSolutionPicker {
                         self.all_solutions[i + 1].1.placement_score
                     );
                 }
-                self.solutions_to_show.push(r);
+                solutions_to_show.push(r);
                 i = j;
             }
         }
-        self.solutions_to_show.sort();
-        self.solutions_to_show.truncate(40);
-        put_solutions_on_surface(&mut self.solutions_to_show);
+        solutions_to_show.sort();
+        solutions_to_show.truncate(40);
+        if sink.is_cancelled() {
+            return;
+        }
+        sink.report(
+            "Placing solutions on surface",
+            50.0,
+            solutions_to_show.get(1).map(|s| s.placement_score),
+        );
+        rotate_solutions(&mut solutions_to_show, self.assembly_rotation);
+        self.unplaced_solutions = solutions_to_show;
+        self.put_on_surface();
+        eprintln!("Finished refresh in {:?}", start.elapsed());
+    }
+
+    // Turns the whole assembly by 90 degrees, in case the picture is upside down.
+    pub fn rotate_assembly(&mut self) {
+        self.assembly_rotation = (self.assembly_rotation + 1) % 4;
+        rotate_solutions(&mut self.unplaced_solutions, 1);
+        self.put_on_surface();
+    }
 
-        let mask_image = RetainedImage::from_color_image(
+    pub fn set_diff_view(&mut self, diff_view: bool) {
+        self.diff_view = diff_view;
+        self.put_on_surface();
+    }
+
+    // In the diff view only solutions with new figures are shown.
+    fn put_on_surface(&mut self) {
+        let mut solutions_to_show = self
+            .unplaced_solutions
+            .iter()
+            .filter(|sol| !self.diff_view || !sol.new_figures_used.is_empty())
+            .cloned()
+            .collect_vec();
+        put_solutions_on_surface(&mut solutions_to_show, self.diff_view);
+        self.mask_image = RetainedImage::from_color_image(
             "solutions mask",
-            PotentialSolution::gen_image(&self.solutions_to_show),
+            PotentialSolution::gen_image(&solutions_to_show),
         );
-        self.mask_image = mask_image;
-        eprintln!("Finished refresh in {:?}", start.elapsed());
+        self.solutions_to_show = solutions_to_show;
+    }
+
+    // Known component is refined in the positions cache, so solutions should be refreshed
+    // after it. Components smaller than `cache_component` are not cached.
+    pub fn global_refine(&mut self, known_facts: &KnownFacts, parsed_puzzles: &ParsedPuzzles) {
+        let placement = known_facts
+            .build_placement()
+            .get_only_one_component_placement(self.start_vertex);
+        self.adjustment_report = self.positions_cache.global_refine(
+            parsed_puzzles,
+            &placement,
+            &self.base_points_matrix,
+        );
+        match &self.adjustment_report {
+            Some(report) => {
+                eprintln!("Global refine: {}", report.describe());
+                for r in report.residuals.iter().take(10) {
+                    eprintln!("{:?} - {:?}: {:.3}", r.s1, r.s2, r.rms);
+                }
+            }
+            None => eprintln!("Global refine failed, known component can't be placed"),
+        }
     }
 
     fn gen_basic_known_solution(
@@ -120,12 +219,7 @@ impl InteractiveSolutionPicker {
         parsed_puzzles: &ParsedPuzzles,
         graph: &Graph,
     ) -> PotentialSolution {
-        let mut placement = Placement::new();
-        for fact in known_facts.facts.iter() {
-            if fact.good_edge {
-                placement.join_sides(fact.side1, fact.side2).unwrap();
-            }
-        }
+        let placement = known_facts.build_placement();
         let edges = placement.get_all_neighbours_in_same_component(self.start_vertex);
         gen_potential_solution(
             &edges,
@@ -133,6 +227,7 @@ impl InteractiveSolutionPicker {
             graph,
             Some(0.0),
             known_facts,
+            &placement,
             &self.rot_positions,
             &self.positions_cache,
             &self.base_points_matrix,
@@ -140,3 +235,19 @@ impl InteractiveSolutionPicker {
         .unwrap()
     }
 }
+
+fn rotate_solutions(solutions: &mut [PotentialSolution], quarter_turns: usize) {
+    let angle = -PI / 2.0 * (quarter_turns as f64);
+    for sol in solutions.iter_mut() {
+        for fig in sol.placed_figures.iter_mut() {
+            for p in fig.positions.iter_mut() {
+                *p = p.rotate(angle);
+            }
+        }
+        for line in sol.debug_lines.iter_mut() {
+            for p in line.iter_mut() {
+                *p = p.rotate(angle);
+            }
+        }
+    }
+}
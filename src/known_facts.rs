@@ -1,48 +1,307 @@
-use std::fs;
+use std::{
+    collections::{btree_map::Entry, BTreeMap, VecDeque},
+    fs,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
+use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 
-use crate::utils::Side;
+use crate::{
+    placement::{Placement, Pos},
+    utils::Side,
+};
 
-#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum FactSource {
+    #[default]
+    Ui,
+    Import,
+    AutoAccept,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Fact {
     pub side1: Side,
     pub side2: Side,
     pub good_edge: bool,
+    // seconds since unix epoch, 0 for facts saved before we started tracking it
+    #[serde(default)]
+    pub timestamp: u64,
+    #[serde(default)]
+    pub source: FactSource,
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+// Two facts are the same if they say the same thing about the same edge,
+// provenance doesn't matter.
+impl PartialEq for Fact {
+    fn eq(&self, other: &Self) -> bool {
+        self.side1 == other.side1 && self.side2 == other.side2 && self.good_edge == other.good_edge
+    }
+}
+
+impl Eq for Fact {}
+
+pub fn now_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
 }
 
 impl Fact {
     pub fn new(side1: Side, side2: Side, good_edge: bool) -> Self {
-        if side1 < side2 {
-            Self {
-                side1,
-                side2,
-                good_edge,
-            }
+        let (side1, side2) = if side1 < side2 {
+            (side1, side2)
         } else {
-            Self {
-                side1: side2,
-                side2: side1,
-                good_edge,
+            (side2, side1)
+        };
+        Self {
+            side1,
+            side2,
+            good_edge,
+            timestamp: now_timestamp(),
+            source: FactSource::Ui,
+            note: None,
+        }
+    }
+
+    pub fn with_source(mut self, source: FactSource) -> Self {
+        self.source = source;
+        self
+    }
+
+    pub fn with_note(mut self, note: &str) -> Self {
+        if !note.is_empty() {
+            self.note = Some(note.to_owned());
+        }
+        self
+    }
+
+    pub fn same_edge(&self, other: &Self) -> bool {
+        self.side1 == other.side1 && self.side2 == other.side2
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PieceFactKind {
+    // not part of this puzzle or too damaged to be used
+    Excluded,
+    BorderPiece,
+    CornerPiece,
+    // cell on the final picture, counting from the top left corner
+    Pinned { row: i32, col: i32 },
+}
+
+impl PieceFactKind {
+    // a figure can't have two facts of the same group at the same time
+    fn replaces(&self, other: &Self) -> bool {
+        match (self, other) {
+            (PieceFactKind::Pinned { .. }, PieceFactKind::Pinned { .. }) => true,
+            (PieceFactKind::Pinned { .. }, _) | (_, PieceFactKind::Pinned { .. }) => {
+                *self == PieceFactKind::Excluded || *other == PieceFactKind::Excluded
             }
+            _ => true,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PieceFact {
+    pub fig: usize,
+    pub kind: PieceFactKind,
+    #[serde(default)]
+    pub timestamp: u64,
+    #[serde(default)]
+    pub source: FactSource,
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+impl PartialEq for PieceFact {
+    fn eq(&self, other: &Self) -> bool {
+        self.fig == other.fig && self.kind == other.kind
+    }
+}
+
+impl Eq for PieceFact {}
+
+impl PieceFact {
+    pub fn new(fig: usize, kind: PieceFactKind) -> Self {
+        Self {
+            fig,
+            kind,
+            timestamp: now_timestamp(),
+            source: FactSource::Ui,
+            note: None,
+        }
+    }
+
+    pub fn with_note(mut self, note: &str) -> Self {
+        if !note.is_empty() {
+            self.note = Some(note.to_owned());
         }
+        self
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ChangeKind {
+    Edit,
+    Undo,
+    Redo,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BoardSizeChange {
+    pub from: Option<(i32, i32)>,
+    pub to: Option<(i32, i32)>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FactsChange {
+    pub timestamp: u64,
+    pub kind: ChangeKind,
+    pub added: Vec<Fact>,
+    pub removed: Vec<Fact>,
+    #[serde(default)]
+    pub to_quarantine: Vec<Fact>,
+    #[serde(default)]
+    pub from_quarantine: Vec<Fact>,
+    #[serde(default)]
+    pub pieces_added: Vec<PieceFact>,
+    #[serde(default)]
+    pub pieces_removed: Vec<PieceFact>,
+    // None if the board size is not changed
+    #[serde(default)]
+    pub board_size: Option<BoardSizeChange>,
+}
+
+impl FactsChange {
+    fn edit() -> Self {
+        Self {
+            timestamp: now_timestamp(),
+            kind: ChangeKind::Edit,
+            added: vec![],
+            removed: vec![],
+            to_quarantine: vec![],
+            from_quarantine: vec![],
+            pieces_added: vec![],
+            pieces_removed: vec![],
+            board_size: None,
+        }
+    }
+
+    fn inverse(&self, kind: ChangeKind) -> Self {
+        Self {
+            timestamp: now_timestamp(),
+            kind,
+            added: self.removed.clone(),
+            removed: self.added.clone(),
+            to_quarantine: self.from_quarantine.clone(),
+            from_quarantine: self.to_quarantine.clone(),
+            pieces_added: self.pieces_removed.clone(),
+            pieces_removed: self.pieces_added.clone(),
+            board_size: self.board_size.map(|change| BoardSizeChange {
+                from: change.to,
+                to: change.from,
+            }),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum ConflictReason {
+    // one of the sides is already glued to some other side by this fact
+    SideUsedTwice(Fact),
+    // both figures are already in the same component, but in other positions.
+    // Facts on the path between them together with the new one form a cycle which doesn't close.
+    BrokenCycle(Vec<Fact>),
+    // two components can't be joined, some figures would be placed into the same cell
+    Overlap,
+}
+
+#[derive(Clone, Debug)]
+pub struct FactConflict {
+    pub fact: Fact,
+    pub reason: ConflictReason,
+}
+
+impl FactConflict {
+    pub fn describe(&self) -> String {
+        let edge = |f: &Fact| {
+            format!(
+                "{}:{}-{}:{}",
+                f.side1.fig, f.side1.side, f.side2.fig, f.side2.side
+            )
+        };
+        match &self.reason {
+            ConflictReason::SideUsedTwice(other) => {
+                format!("{} uses the same side as {}", edge(&self.fact), edge(other))
+            }
+            ConflictReason::BrokenCycle(cycle) => format!(
+                "{} doesn't close cycle {}",
+                edge(&self.fact),
+                cycle.iter().map(edge).collect::<Vec<_>>().join(", ")
+            ),
+            ConflictReason::Overlap => format!("{} makes figures overlap", edge(&self.fact)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct KnownFacts {
     pub facts: Vec<Fact>,
+    // append-only, every change to `facts` ends up here, including undo and redo.
+    #[serde(default)]
+    pub log: Vec<FactsChange>,
+    // indexes into `log`
+    #[serde(default)]
+    undo_stack: Vec<usize>,
+    #[serde(default)]
+    redo_stack: Vec<usize>,
+    // facts which contradict other facts, they are ignored by solvers.
+    #[serde(default)]
+    pub quarantined: Vec<Fact>,
+    #[serde(default)]
+    pub piece_facts: Vec<PieceFact>,
+    // (rows, cols) of the assembled puzzle, if known
+    #[serde(default)]
+    pub board_size: Option<(i32, i32)>,
+    // file, which is rewritten after every change, None if facts are only kept in memory
+    #[serde(skip)]
+    path: Option<String>,
 }
 
 const DEFAULT_PATH: &str = "facts.json";
 
 impl KnownFacts {
+    pub fn from_facts(facts: Vec<Fact>) -> Self {
+        Self {
+            facts,
+            log: vec![],
+            undo_stack: vec![],
+            redo_stack: vec![],
+            quarantined: vec![],
+            piece_facts: vec![],
+            board_size: None,
+            path: None,
+        }
+    }
+
     pub fn load() -> Self {
-        let res: Self = if let Ok(content) = fs::read_to_string(DEFAULT_PATH) {
+        Self::load_from(DEFAULT_PATH)
+    }
+
+    pub fn load_from(path: &str) -> Self {
+        let mut res: Self = if let Ok(content) = fs::read_to_string(path) {
             serde_json::from_str(&content).unwrap()
         } else {
-            Self { facts: vec![] }
+            Self::from_facts(vec![])
         };
+        res.path = Some(path.to_owned());
         /*let mut new_facts = vec![];
         for f in res.facts.into_iter() {
             if f.side1.fig == 844 || f.side2.fig == 844 {
@@ -56,7 +315,37 @@ impl KnownFacts {
     }
 
     pub fn save(&self) {
-        fs::write(DEFAULT_PATH, serde_json::to_string(self).unwrap()).unwrap();
+        if let Some(path) = &self.path {
+            fs::write(path, serde_json::to_string(self).unwrap()).unwrap();
+        }
+    }
+
+    fn apply(&mut self, change: &FactsChange) {
+        fn remove_all<T: PartialEq>(facts: &mut Vec<T>, to_remove: &[T]) {
+            for fact in to_remove.iter() {
+                if let Some(idx) = facts.iter().position(|f| f == fact) {
+                    facts.remove(idx);
+                }
+            }
+        }
+        remove_all(&mut self.facts, &change.removed);
+        remove_all(&mut self.quarantined, &change.from_quarantine);
+        remove_all(&mut self.piece_facts, &change.pieces_removed);
+        self.facts.extend(change.added.iter().cloned());
+        self.quarantined
+            .extend(change.to_quarantine.iter().cloned());
+        self.piece_facts.extend(change.pieces_added.iter().cloned());
+        if let Some(board_size) = change.board_size {
+            self.board_size = board_size.to;
+        }
+    }
+
+    fn record(&mut self, change: FactsChange) {
+        self.apply(&change);
+        self.log.push(change);
+        self.undo_stack.push(self.log.len() - 1);
+        self.redo_stack.clear();
+        self.save();
     }
 
     pub fn add_fact(&mut self, fact: &Fact) {
@@ -64,14 +353,41 @@ impl KnownFacts {
             return;
         }
         eprintln!("ADD NEW FACT: {:?}", fact);
-        for i in 0..self.facts.len() {
-            if self.facts[i].side1 == fact.side1 && self.facts[i].side2 == fact.side2 {
-                self.facts.remove(i);
-                break;
-            }
+        let removed = self
+            .facts
+            .iter()
+            .filter(|f| f.same_edge(fact))
+            .cloned()
+            .collect();
+        self.record(FactsChange {
+            added: vec![fact.clone()],
+            removed,
+            ..FactsChange::edit()
+        });
+    }
+
+    // all facts are added as one change, so they can be undone together
+    pub fn add_facts(&mut self, facts: &[Fact]) {
+        let facts = facts
+            .iter()
+            .filter(|f| !self.facts.contains(f))
+            .cloned()
+            .collect_vec();
+        if facts.is_empty() {
+            return;
         }
-        self.facts.push(fact.clone());
-        self.save();
+        eprintln!("ADD {} NEW FACTS", facts.len());
+        let removed = self
+            .facts
+            .iter()
+            .filter(|f| facts.iter().any(|fact| f.same_edge(fact)))
+            .cloned()
+            .collect();
+        self.record(FactsChange {
+            added: facts,
+            removed,
+            ..FactsChange::edit()
+        });
     }
 
     pub fn get_all_placed_vertices(&self) -> Vec<usize> {
@@ -88,12 +404,228 @@ impl KnownFacts {
     }
 
     pub fn remove_fact(&mut self, fact: &Fact) {
-        if let Some(idx) = self.facts.iter().position(|f| f == fact) {
-            self.facts.remove(idx);
+        if let Some(existing) = self.facts.iter().find(|f| *f == fact) {
+            let existing = existing.clone();
+            self.record(FactsChange {
+                removed: vec![existing],
+                ..FactsChange::edit()
+            });
+        }
+    }
+
+    // moves the fact out of the way of solvers, but keeps it around.
+    pub fn quarantine(&mut self, fact: &Fact) {
+        if let Some(existing) = self.facts.iter().find(|f| *f == fact) {
+            let existing = existing.clone();
+            eprintln!("QUARANTINE: {:?}", existing);
+            self.record(FactsChange {
+                removed: vec![existing.clone()],
+                to_quarantine: vec![existing],
+                ..FactsChange::edit()
+            });
+        }
+    }
+
+    pub fn release_from_quarantine(&mut self, fact: &Fact) {
+        if let Some(existing) = self.quarantined.iter().find(|f| *f == fact) {
+            let existing = existing.clone();
+            self.record(FactsChange {
+                added: vec![existing.clone()],
+                from_quarantine: vec![existing],
+                ..FactsChange::edit()
+            });
+        }
+    }
+
+    pub fn add_piece_fact(&mut self, fact: &PieceFact) {
+        if self.piece_facts.contains(fact) {
+            return;
+        }
+        eprintln!("ADD NEW PIECE FACT: {:?}", fact);
+        let removed = self
+            .piece_facts
+            .iter()
+            .filter(|f| f.fig == fact.fig && f.kind.replaces(&fact.kind))
+            .cloned()
+            .collect();
+        self.record(FactsChange {
+            pieces_added: vec![fact.clone()],
+            pieces_removed: removed,
+            ..FactsChange::edit()
+        });
+    }
+
+    pub fn remove_piece_fact(&mut self, fact: &PieceFact) {
+        if let Some(existing) = self.piece_facts.iter().find(|f| *f == fact) {
+            let existing = existing.clone();
+            self.record(FactsChange {
+                pieces_removed: vec![existing],
+                ..FactsChange::edit()
+            });
+        }
+    }
+
+    // Returns false if the board is smaller than 2x2, such size is not set.
+    pub fn set_board_size(&mut self, board_size: Option<(i32, i32)>) -> bool {
+        if let Some((rows, cols)) = board_size {
+            if rows < 2 || cols < 2 {
+                eprintln!("Board should be at least 2x2, got {rows}x{cols}");
+                return false;
+            }
+        }
+        if self.board_size == board_size {
+            return true;
+        }
+        eprintln!("SET BOARD SIZE: {:?}", board_size);
+        self.record(FactsChange {
+            board_size: Some(BoardSizeChange {
+                from: self.board_size,
+                to: board_size,
+            }),
+            ..FactsChange::edit()
+        });
+        true
+    }
+
+    pub fn get_piece_facts(&self, fig: usize) -> Vec<&PieceFact> {
+        self.piece_facts.iter().filter(|f| f.fig == fig).collect()
+    }
+
+    pub fn is_excluded(&self, fig: usize) -> bool {
+        self.piece_facts
+            .iter()
+            .any(|f| f.fig == fig && f.kind == PieceFactKind::Excluded)
+    }
+
+    pub fn get_excluded_figures(&self) -> Vec<usize> {
+        self.piece_facts
+            .iter()
+            .filter(|f| f.kind == PieceFactKind::Excluded)
+            .map(|f| f.fig)
+            .collect()
+    }
+
+    // figures, which are known to be on the border of the picture.
+    // Second value is true for corners.
+    pub fn get_known_border_figures(&self) -> Vec<(usize, bool)> {
+        self.piece_facts
+            .iter()
+            .filter_map(|f| match f.kind {
+                PieceFactKind::BorderPiece => Some((f.fig, false)),
+                PieceFactKind::CornerPiece => Some((f.fig, true)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // x is row, y is column
+    pub fn get_pinned_positions(&self) -> Vec<(usize, Pos)> {
+        self.piece_facts
+            .iter()
+            .filter_map(|f| match f.kind {
+                PieceFactKind::Pinned { row, col } => Some((f.fig, Pos::new(row, col))),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // Joins all good facts one by one. Facts which contradict previously joined
+    // ones are skipped and reported.
+    fn join_good_facts(&self) -> (Placement, Vec<FactConflict>) {
+        let mut placement = Placement::new();
+        let mut conflicts = vec![];
+        let mut used_sides: BTreeMap<Side, Fact> = BTreeMap::new();
+        let mut accepted: Vec<Fact> = vec![];
+        for fact in self.facts.iter() {
+            if !fact.good_edge {
+                continue;
+            }
+            let already_used = [fact.side1, fact.side2]
+                .iter()
+                .find_map(|s| used_sides.get(s))
+                .cloned();
+            if let Some(other) = already_used {
+                conflicts.push(FactConflict {
+                    fact: fact.clone(),
+                    reason: ConflictReason::SideUsedTwice(other),
+                });
+                continue;
+            }
+            let mut new_placement = placement.clone();
+            if new_placement.join_sides(fact.side1, fact.side2).is_none() {
+                let reason = match find_facts_path(&accepted, fact.side1.fig, fact.side2.fig) {
+                    Some(path) => ConflictReason::BrokenCycle(path),
+                    None => ConflictReason::Overlap,
+                };
+                conflicts.push(FactConflict {
+                    fact: fact.clone(),
+                    reason,
+                });
+                continue;
+            }
+            placement = new_placement;
+            used_sides.insert(fact.side1, fact.clone());
+            used_sides.insert(fact.side2, fact.clone());
+            accepted.push(fact.clone());
+        }
+        (placement, conflicts)
+    }
+
+    pub fn check_consistency(&self) -> Vec<FactConflict> {
+        self.join_good_facts().1
+    }
+
+    pub fn build_placement(&self) -> Placement {
+        let (placement, conflicts) = self.join_good_facts();
+        for conflict in conflicts.iter() {
+            eprintln!("Ignoring conflicting fact: {}", conflict.describe());
+        }
+        placement
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    pub fn undo(&mut self) {
+        if let Some(idx) = self.undo_stack.pop() {
+            let change = self.log[idx].inverse(ChangeKind::Undo);
+            eprintln!("UNDO: {:?}", change);
+            self.apply(&change);
+            self.log.push(change);
+            self.redo_stack.push(idx);
+            self.save();
+        }
+    }
+
+    pub fn redo(&mut self) {
+        if let Some(idx) = self.redo_stack.pop() {
+            let mut change = self.log[idx].clone();
+            change.timestamp = now_timestamp();
+            change.kind = ChangeKind::Redo;
+            eprintln!("REDO: {:?}", change);
+            self.apply(&change);
+            self.log.push(change);
+            self.undo_stack.push(idx);
             self.save();
         }
     }
 
+    pub fn find_fact(&self, side1: Side, side2: Side) -> Option<&Fact> {
+        let (side1, side2) = if side1 < side2 {
+            (side1, side2)
+        } else {
+            (side2, side1)
+        };
+        self.facts
+            .iter()
+            .find(|f| f.side1 == side1 && f.side2 == side2)
+    }
+
     pub fn get_edge_state(&self, side1: Side, side2: Side) -> EdgeState {
         if self.facts.contains(&Fact::new(side1, side2, true)) {
             return EdgeState::GoodEdge;
@@ -116,6 +648,44 @@ impl KnownFacts {
     }
 }
 
+// bfs over figures, edges are good facts
+fn find_facts_path(facts: &[Fact], from: usize, to: usize) -> Option<Vec<Fact>> {
+    let mut prev: BTreeMap<usize, Option<&Fact>> = BTreeMap::new();
+    prev.insert(from, None);
+    let mut queue = VecDeque::new();
+    queue.push_back(from);
+    while let Some(v) = queue.pop_front() {
+        if v == to {
+            let mut path = vec![];
+            let mut cur = to;
+            while let Some(Some(fact)) = prev.get(&cur) {
+                path.push((*fact).clone());
+                cur = if fact.side1.fig == cur {
+                    fact.side2.fig
+                } else {
+                    fact.side1.fig
+                };
+            }
+            path.reverse();
+            return Some(path);
+        }
+        for fact in facts.iter() {
+            let next = if fact.side1.fig == v {
+                fact.side2.fig
+            } else if fact.side2.fig == v {
+                fact.side1.fig
+            } else {
+                continue;
+            };
+            if let Entry::Vacant(entry) = prev.entry(next) {
+                entry.insert(Some(fact));
+                queue.push_back(next);
+            }
+        }
+    }
+    None
+}
+
 #[derive(Clone, PartialEq, Eq)]
 pub enum EdgeState {
     Unknown,
@@ -0,0 +1,192 @@
+#[cfg(test)]
+mod tests {
+    use crate::{
+        known_facts::{ConflictReason, Fact, KnownFacts, PieceFact, PieceFactKind},
+        placement::Pos,
+        utils::Side,
+    };
+
+    fn side(fig: usize, side: usize) -> Side {
+        Side { fig, side }
+    }
+
+    #[test]
+    pub fn consistent_facts() {
+        let known_facts = KnownFacts::from_facts(vec![
+            Fact::new(side(0, 1), side(1, 3), true),
+            Fact::new(side(1, 1), side(2, 3), true),
+            Fact::new(side(0, 2), side(5, 0), false),
+        ]);
+        assert!(known_facts.check_consistency().is_empty());
+        assert_eq!(known_facts.build_placement().get_cnt_figures(), 3);
+    }
+
+    #[test]
+    pub fn side_used_twice() {
+        let known_facts = KnownFacts::from_facts(vec![
+            Fact::new(side(0, 1), side(1, 3), true),
+            Fact::new(side(0, 1), side(2, 3), true),
+        ]);
+        let conflicts = known_facts.check_consistency();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].fact, Fact::new(side(0, 1), side(2, 3), true));
+        assert!(matches!(
+            conflicts[0].reason,
+            ConflictReason::SideUsedTwice(_)
+        ));
+        assert_eq!(known_facts.build_placement().get_cnt_figures(), 2);
+    }
+
+    #[test]
+    pub fn broken_cycle() {
+        // three figures in a row, the last one can't be glued back to the first one.
+        let known_facts = KnownFacts::from_facts(vec![
+            Fact::new(side(0, 1), side(1, 3), true),
+            Fact::new(side(1, 1), side(2, 3), true),
+            Fact::new(side(2, 1), side(0, 3), true),
+        ]);
+        let conflicts = known_facts.check_consistency();
+        assert_eq!(conflicts.len(), 1);
+        match &conflicts[0].reason {
+            ConflictReason::BrokenCycle(cycle) => assert_eq!(cycle.len(), 2),
+            reason => panic!("unexpected reason: {:?}", reason),
+        }
+    }
+
+    #[test]
+    pub fn anchors_and_board() {
+        // three figures in a row
+        let known_facts = KnownFacts::from_facts(vec![
+            Fact::new(side(0, 1), side(1, 3), true),
+            Fact::new(side(1, 1), side(2, 3), true),
+        ]);
+        let placement = known_facts.build_placement();
+        let pins = [(0, Pos::new(0, 0)), (2, Pos::new(0, 2))];
+        assert!(placement.is_consistent_with_anchors(&pins, None));
+        assert!(placement.is_consistent_with_anchors(&pins, Some((1, 3))));
+        assert!(!placement.is_consistent_with_anchors(&pins, Some((3, 1))));
+        assert!(placement.is_consistent_with_anchors(&[], Some((3, 1))));
+        assert!(!placement.is_consistent_with_anchors(&[], Some((2, 2))));
+        let bad_pins = [(0, Pos::new(0, 0)), (2, Pos::new(1, 1))];
+        assert!(!placement.is_consistent_with_anchors(&bad_pins, None));
+    }
+
+    #[test]
+    pub fn piece_facts_and_pins() {
+        // three figures in a row
+        let mut known_facts = KnownFacts::from_facts(vec![
+            Fact::new(side(0, 1), side(1, 3), true),
+            Fact::new(side(1, 1), side(2, 3), true),
+        ]);
+        known_facts.piece_facts = vec![
+            PieceFact::new(0, PieceFactKind::CornerPiece),
+            PieceFact::new(1, PieceFactKind::BorderPiece),
+            PieceFact::new(5, PieceFactKind::Excluded),
+            PieceFact::new(0, PieceFactKind::Pinned { row: 0, col: 0 }),
+            PieceFact::new(2, PieceFactKind::Pinned { row: 0, col: 2 }),
+        ];
+        assert!(known_facts.is_excluded(5));
+        assert!(!known_facts.is_excluded(0));
+        assert_eq!(known_facts.get_excluded_figures(), vec![5]);
+        assert_eq!(
+            known_facts.get_known_border_figures(),
+            vec![(0, true), (1, false)]
+        );
+        let pins = known_facts.get_pinned_positions();
+        assert_eq!(pins, vec![(0, Pos::new(0, 0)), (2, Pos::new(0, 2))]);
+
+        let placement = known_facts.build_placement();
+        assert!(placement.is_consistent_with_anchors(&pins, None));
+        assert!(placement.is_consistent_with_anchors(&pins, Some((1, 3))));
+        // figure 2 can't be next to figure 0
+        let bad_pins = [(0, Pos::new(0, 0)), (2, Pos::new(0, 1))];
+        assert!(!placement.is_consistent_with_anchors(&bad_pins, None));
+        // the middle figure can't be pinned to the last column
+        let middle = |col| [(1, Pos::new(0, col))];
+        assert!(placement.is_consistent_with_anchors(&middle(3), Some((1, 5))));
+        assert!(!placement.is_consistent_with_anchors(&middle(4), Some((1, 5))));
+    }
+
+    #[test]
+    pub fn undo_replaced_fact() {
+        let wrong = Fact::new(side(0, 1), side(1, 3), false);
+        let good = Fact::new(side(0, 1), side(1, 3), true);
+        let mut known_facts = KnownFacts::from_facts(vec![wrong.clone()]);
+        assert!(!known_facts.can_undo());
+        known_facts.add_fact(&good);
+        assert_eq!(known_facts.facts, vec![good.clone()]);
+
+        known_facts.undo();
+        assert_eq!(known_facts.facts, vec![wrong.clone()]);
+        assert!(!known_facts.can_undo());
+        assert!(known_facts.can_redo());
+
+        known_facts.redo();
+        assert_eq!(known_facts.facts, vec![good]);
+        assert!(known_facts.can_undo());
+        assert!(!known_facts.can_redo());
+        // undo and redo are changes too
+        assert_eq!(known_facts.log.len(), 3);
+    }
+
+    #[test]
+    pub fn new_change_clears_redo() {
+        let mut known_facts = KnownFacts::from_facts(vec![]);
+        known_facts.add_fact(&Fact::new(side(0, 1), side(1, 3), true));
+        known_facts.add_piece_fact(&PieceFact::new(2, PieceFactKind::Excluded));
+        known_facts.undo();
+        assert!(known_facts.piece_facts.is_empty());
+        assert!(known_facts.can_redo());
+
+        known_facts.add_fact(&Fact::new(side(1, 1), side(2, 3), true));
+        assert!(!known_facts.can_redo());
+        known_facts.redo();
+        assert!(known_facts.piece_facts.is_empty());
+        assert_eq!(known_facts.facts.len(), 2);
+
+        known_facts.undo();
+        known_facts.undo();
+        assert!(known_facts.facts.is_empty());
+    }
+
+    #[test]
+    pub fn saved_to_own_path() {
+        let path = std::env::temp_dir().join("known_facts_test.json");
+        let path = path.to_str().unwrap();
+        std::fs::remove_file(path).ok();
+        let mut known_facts = KnownFacts::load_from(path);
+        assert!(known_facts.facts.is_empty());
+        known_facts.add_fact(&Fact::new(side(0, 1), side(1, 3), true));
+        known_facts.undo();
+        known_facts.add_fact(&Fact::new(side(1, 1), side(2, 3), true));
+
+        let loaded = KnownFacts::load_from(path);
+        assert_eq!(loaded.facts, known_facts.facts);
+        assert_eq!(loaded.log.len(), 3);
+        assert!(loaded.can_undo());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    pub fn board_size_is_undone() {
+        let mut known_facts = KnownFacts::from_facts(vec![]);
+        assert!(!known_facts.set_board_size(Some((1, 5))));
+        assert!(!known_facts.set_board_size(Some((4, -4))));
+        assert_eq!(known_facts.board_size, None);
+        assert!(!known_facts.can_undo());
+
+        assert!(known_facts.set_board_size(Some((3, 4))));
+        assert!(known_facts.set_board_size(Some((4, 3))));
+        known_facts.undo();
+        assert_eq!(known_facts.board_size, Some((3, 4)));
+        known_facts.undo();
+        assert_eq!(known_facts.board_size, None);
+        known_facts.redo();
+        assert_eq!(known_facts.board_size, Some((3, 4)));
+
+        // other changes don't touch the board size
+        known_facts.add_fact(&Fact::new(side(0, 1), side(1, 3), true));
+        known_facts.undo();
+        assert_eq!(known_facts.board_size, Some((3, 4)));
+    }
+}
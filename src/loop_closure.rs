@@ -0,0 +1,112 @@
+use std::collections::BTreeMap;
+
+use itertools::Itertools;
+
+use crate::{
+    graph_solver::PlacedFigure,
+    known_facts::{Fact, KnownFacts},
+    parsed_puzzles::ParsedPuzzles,
+    placement::Placement,
+    point::PointF,
+    utils::fmax,
+};
+
+// relative to the side length
+const SUSPICIOUS_SPREAD: f64 = 0.15;
+
+#[derive(Clone, Debug)]
+pub struct CornerClosure {
+    pub figures: [usize; 4],
+    // max distance from the placed corners to their center, relative to the average side length.
+    // Four figures around one point should meet at the same point, so it should be ~0.
+    pub spread: f64,
+}
+
+pub fn calc_corner_closures(
+    placement: &Placement,
+    parsed_puzzles: &ParsedPuzzles,
+    placed_figures: &[PlacedFigure],
+) -> Vec<CornerClosure> {
+    calc_closures(placement.get_2x2_corners(), parsed_puzzles, placed_figures)
+}
+
+// Only corners with at least one of `figures`. Used for candidates, corners of the
+// known component are the same for all of them.
+pub fn calc_corner_closures_with_figures(
+    placement: &Placement,
+    parsed_puzzles: &ParsedPuzzles,
+    placed_figures: &[PlacedFigure],
+    figures: &[usize],
+) -> Vec<CornerClosure> {
+    let corners = placement
+        .get_2x2_corners()
+        .into_iter()
+        .filter(|corners| corners.iter().any(|(fig, _)| figures.contains(fig)))
+        .collect_vec();
+    calc_closures(corners, parsed_puzzles, placed_figures)
+}
+
+fn calc_closures(
+    corners: Vec<[(usize, usize); 4]>,
+    parsed_puzzles: &ParsedPuzzles,
+    placed_figures: &[PlacedFigure],
+) -> Vec<CornerClosure> {
+    let placed_corner = |fig: usize, corner: usize| -> Option<PointF> {
+        let placed = placed_figures.iter().find(|f| f.figure_id == fig)?;
+        let corner_positions = &parsed_puzzles.figures[fig].corner_positions;
+        Some(placed.positions[corner_positions[corner % corner_positions.len()]])
+    };
+    corners
+        .into_iter()
+        .filter_map(|corners| {
+            let mut points = vec![];
+            let mut side_len = 0.0;
+            for &(fig, corner) in corners.iter() {
+                let p = placed_corner(fig, corner)?;
+                side_len += p.dist2(&placed_corner(fig, corner + 1)?).sqrt() / 4.0;
+                points.push(p);
+            }
+            let center = points.iter().fold(PointF::ZERO, |acc, &p| acc + p) / 4.0;
+            let spread = points
+                .iter()
+                .map(|p| p.dist2(&center).sqrt())
+                .fold(0.0, fmax);
+            Some(CornerClosure {
+                figures: corners.map(|(fig, _)| fig),
+                spread: spread / side_len,
+            })
+        })
+        .collect_vec()
+}
+
+// the worst corner matters
+pub fn calc_closure_score(closures: &[CornerClosure]) -> f64 {
+    closures.iter().map(|c| c.spread).fold(0.0, fmax)
+}
+
+// Good facts between figures around corners, which don't close well.
+// Sorted from the most suspicious.
+pub fn find_suspicious_facts(
+    known_facts: &KnownFacts,
+    closures: &[CornerClosure],
+) -> Vec<(Fact, f64)> {
+    let mut res: BTreeMap<usize, f64> = BTreeMap::new();
+    for closure in closures.iter() {
+        if closure.spread < SUSPICIOUS_SPREAD {
+            continue;
+        }
+        for (id, fact) in known_facts.facts.iter().enumerate() {
+            if fact.good_edge
+                && closure.figures.contains(&fact.side1.fig)
+                && closure.figures.contains(&fact.side2.fig)
+            {
+                let spread = res.entry(id).or_insert(0.0);
+                *spread = fmax(*spread, closure.spread);
+            }
+        }
+    }
+    res.into_iter()
+        .map(|(id, spread)| (known_facts.facts[id].clone(), spread))
+        .sorted_by(|(_, s1), (_, s2)| s2.total_cmp(s1))
+        .collect_vec()
+}
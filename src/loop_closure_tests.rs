@@ -0,0 +1,93 @@
+#[cfg(test)]
+mod tests {
+    use crate::{
+        graph_solver::PlacedFigure,
+        known_facts::{Fact, KnownFacts},
+        loop_closure::{
+            calc_closure_score, calc_corner_closures, calc_corner_closures_with_figures,
+            find_suspicious_facts,
+        },
+        parsed_puzzles::ParsedPuzzles,
+        point::PointF,
+        synthetic::{gen_pattern_image, SyntheticPuzzle},
+        utils::dedup_edges,
+    };
+
+    #[test]
+    pub fn shifted_corner_piece() {
+        let (rows, cols) = (3, 4);
+        let source = gen_pattern_image(400, 300, 1);
+        let puzzle = SyntheticPuzzle::generate(&source, rows, cols, 2);
+        let parsed_puzzles = ParsedPuzzles::new(&puzzle.image);
+        let placement = puzzle
+            .to_ground_truth(&parsed_puzzles)
+            .to_placement(&|_, _| 0.0)
+            .unwrap();
+        let mut placed_figures = puzzle
+            .assembled_positions(&parsed_puzzles)
+            .into_iter()
+            .enumerate()
+            .filter_map(|(figure_id, positions)| {
+                Some(PlacedFigure {
+                    figure_id,
+                    positions: positions?,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let closures = calc_corner_closures(&placement, &parsed_puzzles, &placed_figures);
+        assert_eq!(closures.len(), (rows - 1) * (cols - 1));
+        assert!(calc_closure_score(&closures) < 0.05);
+        let known_facts = KnownFacts::from_facts(
+            dedup_edges(&placement.get_all_neighbours())
+                .into_iter()
+                .map(|(s1, s2)| Fact::new(s1, s2, true))
+                .collect(),
+        );
+        assert!(find_suspicious_facts(&known_facts, &closures).is_empty());
+
+        // corner piece of the board is in one 2x2 corner only
+        let moved = placed_figures
+            .iter()
+            .map(|f| f.figure_id)
+            .find(|&fig| closures.iter().filter(|c| c.figures.contains(&fig)).count() == 1)
+            .unwrap();
+        let moved_closure = closures
+            .iter()
+            .find(|c| c.figures.contains(&moved))
+            .unwrap();
+        let only_moved = calc_corner_closures_with_figures(
+            &placement,
+            &parsed_puzzles,
+            &placed_figures,
+            &[moved],
+        );
+        assert_eq!(only_moved.len(), 1);
+        assert_eq!(only_moved[0].figures, moved_closure.figures);
+
+        // pieces are 100 pixels
+        let shift = PointF { x: 40.0, y: 0.0 };
+        for fig in placed_figures.iter_mut().filter(|f| f.figure_id == moved) {
+            for p in fig.positions.iter_mut() {
+                *p = *p + shift;
+            }
+        }
+        let closures = calc_corner_closures(&placement, &parsed_puzzles, &placed_figures);
+        assert!(calc_closure_score(&closures) > 0.2);
+        let bad = closures
+            .iter()
+            .filter(|c| c.spread > 0.05)
+            .collect::<Vec<_>>();
+        assert_eq!(bad.len(), 1);
+        assert_eq!(bad[0].figures, moved_closure.figures);
+
+        // edges between the four figures around the bad corner
+        let suspicious = find_suspicious_facts(&known_facts, &closures);
+        assert_eq!(suspicious.len(), 4);
+        for (fact, spread) in suspicious.iter() {
+            assert!(bad[0].figures.contains(&fact.side1.fig));
+            assert!(bad[0].figures.contains(&fact.side2.fig));
+            assert_eq!(*spread, bad[0].spread);
+        }
+    }
+}
@@ -1,67 +1,114 @@
 #![feature(slice_group_by)]
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
 
-use std::fs;
+use std::{fs, sync::Arc, time::Instant};
 
 use eframe::{egui, epaint::pos2};
+use itertools::Itertools;
 
 use crate::{
-    borders_graph::Graph, crop::crop, edge_score_optimizer::optimize_edge_scores,
-    graph_solver::solve_graph_add_by_3, interactive_solutions_picker::InteractiveSolutionPicker,
-    known_facts::KnownFacts, my_widget::MyWidget, parsed_puzzles::ParsedPuzzles,
-    surface_placer::put_solutions_on_surface, utils::load_image_from_path,
+    benchmark::{hold_out_truth, run_benchmark, seed_figures},
+    borders_graph::Graph,
+    config::Config,
+    crop::crop,
+    edge_score_optimizer::{optimize_edge_scores, tune_edge_scoring},
+    frame_solver::solve_frame,
+    graph_solver::solve_graph,
+    ground_truth::GroundTruth,
+    known_facts::{Fact, KnownFacts},
+    my_widget::MyWidget,
+    parsed_puzzles::ParsedPuzzles,
+    puzzle_dimensions::print_dimensions_candidates,
+    solver_strategy::{all_strategies, find_strategy, SolverConfig},
+    solver_worker::ProgressSink,
+    surface_placer::put_solutions_on_surface,
+    synthetic::{gen_pattern_image, SyntheticPuzzle},
+    utils::{dedup_edges, load_image_from_path, save_color_image, Side},
 };
 
+mod assignment_solver;
+mod assignment_solver_tests;
 mod average_color;
+mod benchmark;
+mod benchmark_tests;
 mod border_matcher;
 mod borders_graph;
+mod borders_graph_tests;
+mod bundle_adjustment;
+mod bundle_adjustment_tests;
+mod config;
 mod coordinate_system;
 mod crop;
 mod dsu;
 mod edge_score_optimizer;
+mod edge_score_optimizer_tests;
+mod edge_scoring_params;
+mod edge_scoring_params_tests;
 mod figure;
+mod frame_solver;
+mod frame_solver_tests;
 mod graph_solver;
+mod graph_solver_tests;
+mod ground_truth;
+mod ground_truth_tests;
+mod hole_solver;
+mod hole_solver_tests;
 mod interactive_solutions_picker;
 mod known_facts;
-mod known_positions;
+mod known_facts_tests;
+mod loop_closure;
+mod loop_closure_tests;
 mod matcher_tests;
 mod my_widget;
+mod overlap;
+mod overlap_tests;
 mod parsed_puzzles;
 mod placement;
 mod point;
+mod pose_refinement;
+mod pose_refinement_tests;
 mod positions_cache;
+mod puzzle_dimensions;
+mod puzzle_dimensions_tests;
 mod rects_fitter;
+mod rects_fitter_tests;
 mod search_states_cache;
+mod solver_strategy;
+mod solver_worker;
 mod surface_placer;
+mod surface_placer_tests;
+mod synthetic;
+mod synthetic_tests;
 mod topn;
 mod utils;
 
 const BEFORE_CROP_PATH: &str = "img/prod2/24.jpg";
 const PATH: &str = "img/prod2/crop2_full.jpg";
 const GRAPH_PATH: &str = "graph_with_start.json";
-const GRAPH_SOLUTION_PATH: &str = "graph_solution.json";
-const LOAD_EXISTING_SOLUTION: bool = false;
+// could be changed with `--strategy <name>`, see [solver_strategy::all_strategies]
+const DEFAULT_STRATEGY: &str = "add_by_3";
 
 const PUZZLE_PIXEL_WHITE_THRESHOLD: usize = 460;
 
 // TODO: nicer type
 fn main_ui(
-    solutions: Option<InteractiveSolutionPicker>,
+    strategy: Option<String>,
     path: &str,
     show_parsed: bool,
     show_image: bool,
     show_matched_borders: bool,
     crop_enabled: bool,
     known_facts: KnownFacts,
-    graph: Graph,
-    parsed_puzzles: ParsedPuzzles,
+    graph: Arc<Graph>,
+    parsed_puzzles: Arc<ParsedPuzzles>,
+    config: Config,
 ) {
     let options = eframe::NativeOptions {
         initial_window_size: Some(egui::vec2(1400.0, 1100.0)),
         ..Default::default()
     };
     let app_created = Box::new(MyApp::new(
-        solutions,
+        strategy,
         path,
         show_parsed,
         show_image,
@@ -70,6 +117,7 @@ fn main_ui(
         known_facts,
         graph,
         parsed_puzzles,
+        config,
     ));
     eframe::run_native("jigsaw solver", options, Box::new(|_| app_created));
 }
@@ -77,56 +125,37 @@ fn main_ui(
 fn main_build_graph() {
     let color_image = load_image_from_path(PATH).unwrap();
     let parsed_puzzles = ParsedPuzzles::new(&color_image);
-    let graph = Graph::new(&parsed_puzzles, false);
+    let graph = Graph::new(&parsed_puzzles, false, &Config::load().matcher);
     fs::write(GRAPH_PATH, serde_json::to_string(&graph).unwrap()).unwrap();
 }
 
-fn main_load_graph() {
+fn main_load_graph(strategy: &str) {
     let color_image = load_image_from_path(PATH).unwrap();
     let parsed_puzzles = ParsedPuzzles::new(&color_image);
-    let graph: Graph = serde_json::from_str(&fs::read_to_string(GRAPH_PATH).unwrap()).unwrap();
+    let config = Config::load();
+    let graph = Graph::load(GRAPH_PATH, &config.matcher);
     eprintln!("graph loaded! n = {}", graph.n);
 
-    let mut known_facts = KnownFacts::load();
-
-    let solution_picker = {
-        let mut prev_state: Option<Graph> = if LOAD_EXISTING_SOLUTION {
-            Some(serde_json::from_str(&fs::read_to_string(GRAPH_SOLUTION_PATH).unwrap()).unwrap())
-        } else {
-            None
-        };
-        solve_graph_add_by_3(
-            &graph,
-            &parsed_puzzles,
-            prev_state.clone(),
-            &mut known_facts,
-        )
-    };
-    // fs::write(
-    //     GRAPH_SOLUTION_PATH,
-    //     serde_json::to_string(&solution_graph).unwrap(),
-    // )
-    // .unwrap();
-    // let placement = Placement::from_full_graph(&solution_graph);
-    // let positions = place_on_surface(&graph, &placement, &parsed_puzzles);
-    eprintln!("positions generated!");
+    // solving runs in the background, the window shows its progress
     main_ui(
-        Some(solution_picker),
+        Some(strategy.to_owned()),
         PATH,
         true,
         false,
         true,
         false,
-        known_facts,
-        graph,
-        parsed_puzzles,
+        KnownFacts::load(),
+        Arc::new(graph),
+        Arc::new(parsed_puzzles),
+        config,
     );
 }
 
 fn main_before_crop() {
     let color_image = load_image_from_path(PATH).unwrap();
     let parsed_puzzles = ParsedPuzzles::new(&color_image);
-    let graph: Graph = serde_json::from_str(&fs::read_to_string(GRAPH_PATH).unwrap()).unwrap();
+    let config = Config::load();
+    let graph = Graph::load(GRAPH_PATH, &config.matcher);
     main_ui(
         None,
         BEFORE_CROP_PATH,
@@ -135,15 +164,17 @@ fn main_before_crop() {
         false,
         true,
         KnownFacts::load(),
-        graph,
-        parsed_puzzles,
+        Arc::new(graph),
+        Arc::new(parsed_puzzles),
+        config,
     );
 }
 
 fn main_check_parsing() {
     let color_image = load_image_from_path(PATH).unwrap();
     let parsed_puzzles = ParsedPuzzles::new(&color_image);
-    let graph: Graph = serde_json::from_str(&fs::read_to_string(GRAPH_PATH).unwrap()).unwrap();
+    let config = Config::load();
+    let graph = Graph::load(GRAPH_PATH, &config.matcher);
     main_ui(
         None,
         PATH,
@@ -152,11 +183,87 @@ fn main_check_parsing() {
         true,
         false,
         KnownFacts::load(),
-        graph,
-        parsed_puzzles,
+        Arc::new(graph),
+        Arc::new(parsed_puzzles),
+        config,
     );
 }
 
+fn main_infer_dimensions() {
+    let color_image = load_image_from_path(PATH).unwrap();
+    let parsed_puzzles = ParsedPuzzles::new(&color_image);
+    print_dimensions_candidates(&parsed_puzzles, &KnownFacts::load());
+}
+
+fn main_solve_frame() {
+    let color_image = load_image_from_path(PATH).unwrap();
+    let parsed_puzzles = ParsedPuzzles::new(&color_image);
+    let graph = Graph::load(GRAPH_PATH, &Config::load().matcher);
+    match solve_frame(&graph, &parsed_puzzles, &KnownFacts::load()) {
+        Some(frame) => eprintln!(
+            "Frame (accept it from UI): {}",
+            frame.order.iter().map(|f| f.figure_id).join(" ")
+        ),
+        None => eprintln!("Frame not found"),
+    }
+}
+
+// long running, can be stopped at any moment and it will continue from the last checkpoint
+fn main_solve_graph() {
+    let color_image = load_image_from_path(PATH).unwrap();
+    let parsed_puzzles = ParsedPuzzles::new(&color_image);
+    let config = Config::load();
+    let graph = Graph::load(GRAPH_PATH, &config.matcher);
+    let candidates = solve_graph(
+        &graph,
+        &parsed_puzzles,
+        None,
+        &KnownFacts::load(),
+        &config.solver,
+        &ProgressSink::none(),
+    );
+    for candidate in candidates.iter().take(10) {
+        eprintln!(
+            "Solution with {} edges, score = {:.3}{}",
+            candidate.edges.len(),
+            candidate.score,
+            candidate.note
+        );
+    }
+}
+
+// all strategies on the same input, without UI
+fn main_compare_strategies() {
+    let color_image = load_image_from_path(PATH).unwrap();
+    let parsed_puzzles = ParsedPuzzles::new(&color_image);
+    let config = Config::load();
+    let graph = Graph::load(GRAPH_PATH, &config.matcher);
+    let known_facts = KnownFacts::load();
+    let config = config.solver;
+    let mut results = vec![];
+    for strategy in all_strategies() {
+        let start = Instant::now();
+        let candidates = strategy.solve_ranked(
+            &graph,
+            &parsed_puzzles,
+            &known_facts,
+            &config,
+            &ProgressSink::none(),
+        );
+        results.push((strategy.name(), candidates, start.elapsed()));
+    }
+    for (name, candidates, elapsed) in results.iter() {
+        eprintln!(
+            "{name}: {} candidates in {elapsed:?}, best score = {}",
+            candidates.len(),
+            candidates
+                .first()
+                .map(|c| format!("{:.3}{}", c.score, c.note))
+                .unwrap_or_else(|| "-".to_owned())
+        );
+    }
+}
+
 fn main_check_crop() {
     let pts = [
         pos2(1576.5, 1072.4),
@@ -170,20 +277,245 @@ fn main_check_crop() {
 // TODO: fix this part?
 // fn main_optimize_edge_scoring() {
 //     let color_image = load_image_from_path(PATH).unwrap();
-//     let graph: Graph = serde_json::from_str(&fs::read_to_string(GRAPH_PATH).unwrap()).unwrap();
+//     let config = Config::load();
+//     let graph = Graph::load(GRAPH_PATH, &config.matcher);
 //     let parsed_puzzles = ParsedPuzzles::new(&color_image);
 //
-//     let mut solutions = optimize_edge_scores(&parsed_puzzles, &graph, false);
-//     put_solutions_on_surface(&mut solutions);
+//     let mut solutions = optimize_edge_scores(&parsed_puzzles, &graph, false, &config);
+//     put_solutions_on_surface(&mut solutions, false);
 //     main_ui(None, PATH, true, false, true, false, KnownFacts::load());
 // }
 
+// graph should be rebuilt with `main_build_graph` after it
+fn main_tune_edge_scoring() {
+    let color_image = load_image_from_path(PATH).unwrap();
+    let parsed_puzzles = ParsedPuzzles::new(&color_image);
+    let mut config = Config::load();
+    let graph = Graph::load(GRAPH_PATH, &config.matcher);
+    config.matcher.scoring = tune_edge_scoring(
+        &parsed_puzzles,
+        &graph,
+        &KnownFacts::load(),
+        &config.matcher,
+    );
+    eprintln!("Tuned params: {:?}", config.matcher.scoring);
+    config.save();
+}
+
+struct CliArgs {
+    // solver for the UI or the benchmark
+    strategy: String,
+    // run all strategies without UI
+    compare: bool,
+    // ground truth file, benchmark runs instead of UI
+    benchmark: Option<String>,
+}
+
+// with `--compare` all strategies are measured
+fn main_benchmark(args: &CliArgs, ground_truth_path: &str) {
+    let color_image = load_image_from_path(PATH).unwrap();
+    let parsed_puzzles = ParsedPuzzles::new(&color_image);
+    let config = Config::load();
+    let graph = Graph::load(GRAPH_PATH, &config.matcher);
+    let config = config.solver;
+    let dist = graph.gen_adj_matrix();
+    let dist = |s1: Side, s2: Side| dist[[s1.fig, s1.side, s2.fig, s2.side]];
+    let truth_placement = GroundTruth::load(ground_truth_path)
+        .expect("can't load ground truth")
+        .to_placement(&dist)
+        .expect("ground truth is inconsistent");
+    // ground truth is usually exported from the same facts, so only a seed part is kept
+    let seed = seed_figures(&truth_placement, config.start_vertex)
+        .expect("start vertex should be in the ground truth");
+    let known_facts = hold_out_truth(&KnownFacts::load(), &truth_placement, &seed);
+    eprintln!(
+        "Benchmark starts from {} facts, seed of {} figures",
+        known_facts.facts.len(),
+        seed.len()
+    );
+    let strategies = if args.compare {
+        all_strategies()
+    } else {
+        vec![find_strategy(&args.strategy).unwrap()]
+    };
+    let results = strategies
+        .iter()
+        .map(|strategy| {
+            run_benchmark(
+                strategy.as_ref(),
+                &graph,
+                &parsed_puzzles,
+                &known_facts,
+                &config,
+                &truth_placement,
+            )
+        })
+        .collect_vec();
+    for result in results.iter() {
+        eprintln!("{}", result.describe());
+    }
+}
+
+// verified edges from known facts into the ground truth file
+fn main_export_ground_truth() {
+    let ground_truth = GroundTruth::from_known_facts(&KnownFacts::load());
+    eprintln!(
+        "Ground truth: {} figures in {} areas",
+        ground_truth.cnt_figures(),
+        ground_truth.areas.len()
+    );
+    ground_truth.save(ground_truth::DEFAULT_PATH);
+}
+
+// ground truth file into known facts, e.g. after the puzzle was assembled by hand
+fn main_import_ground_truth() {
+    let graph = Graph::load(GRAPH_PATH, &Config::load().matcher);
+    let dist = graph.gen_adj_matrix();
+    let dist = |s1: Side, s2: Side| dist[[s1.fig, s1.side, s2.fig, s2.side]];
+    let facts = GroundTruth::load(ground_truth::DEFAULT_PATH)
+        .expect("can't load ground truth")
+        .to_facts(&dist)
+        .expect("ground truth is inconsistent");
+    let mut known_facts = KnownFacts::load();
+    known_facts.add_facts(&facts);
+    eprintln!("Imported {} facts", facts.len());
+    known_facts.save();
+}
+
+// whole pipeline on a generated puzzle, where the answer is known
+fn main_gen_synthetic() {
+    const ROWS: usize = 4;
+    const COLS: usize = 5;
+    const SEED: u64 = 787788;
+    const IMAGE_PATH: &str = "img/synthetic.png";
+    const GROUND_TRUTH_PATH: &str = "ground_truth_synthetic.json";
+
+    let source = gen_pattern_image(COLS * 150, ROWS * 150, SEED);
+    let puzzle = SyntheticPuzzle::generate(&source, ROWS, COLS, SEED);
+    save_color_image(&puzzle.image, IMAGE_PATH);
+    let parsed_puzzles = ParsedPuzzles::new(&puzzle.image);
+    let ground_truth = puzzle.to_ground_truth(&parsed_puzzles);
+    eprintln!("Ground truth figures: {}", ground_truth.cnt_figures());
+    ground_truth.save(GROUND_TRUTH_PATH);
+    let truth_placement = ground_truth.to_placement(&|_, _| 0.0).unwrap();
+    let config = Config::load();
+
+    // edge scores: how many other sides are better than the correct one
+    let graph = Graph::new(&parsed_puzzles, false, &config.matcher);
+    let ranks = dedup_edges(&truth_placement.get_all_neighbours())
+        .iter()
+        .map(|&(s1, s2)| {
+            let truth_score = graph
+                .all_edges
+                .iter()
+                .find(|e| e.sides() == (s1, s2))
+                .map(|e| e.score)
+                .unwrap_or(f64::MAX);
+            graph
+                .all_edges
+                .iter()
+                .filter(|e| e.sides().0 == s1 && e.score < truth_score)
+                .count()
+        })
+        .collect_vec();
+    eprintln!(
+        "Correct side is the best one for {}/{} edges, ranks: {:?}",
+        ranks.iter().filter(|&&r| r == 0).count(),
+        ranks.len(),
+        ranks
+    );
+
+    // solvers extend the known part: everything except the last row
+    let mut known_placement = truth_placement.clone();
+    for ((row, _col), sides) in truth_placement.get_cells_by_component().remove(0) {
+        if row + 1 == ROWS {
+            known_placement.remove_figure(sides[0].fig);
+        }
+    }
+    let known_facts = KnownFacts::from_facts(
+        dedup_edges(&known_placement.get_all_neighbours())
+            .into_iter()
+            .map(|(s1, s2)| Fact::new(s1, s2, true))
+            .collect(),
+    );
+    let config = SolverConfig {
+        start_vertex: known_placement.get_all_used_figures()[0],
+        ..config.solver
+    };
+    // beam search starts from the ground truth of the real puzzle
+    for strategy in all_strategies()
+        .into_iter()
+        .filter(|s| s.name() != "beam_search")
+    {
+        let result = run_benchmark(
+            strategy.as_ref(),
+            &graph,
+            &parsed_puzzles,
+            &known_facts,
+            &config,
+            &truth_placement,
+        );
+        eprintln!("{}", result.describe());
+    }
+}
+
+// `--strategy <name>`, `--compare`, `--benchmark <ground truth path>`
+fn parse_args() -> CliArgs {
+    let args = std::env::args().collect_vec();
+    let mut res = CliArgs {
+        strategy: DEFAULT_STRATEGY.to_owned(),
+        compare: false,
+        benchmark: None,
+    };
+    let mut i = 1;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--strategy" if i + 1 < args.len() => {
+                res.strategy = args[i + 1].clone();
+                i += 1;
+            }
+            "--benchmark" if i + 1 < args.len() => {
+                res.benchmark = Some(args[i + 1].clone());
+                i += 1;
+            }
+            "--compare" => res.compare = true,
+            other => eprintln!("Unknown argument: {other}"),
+        }
+        i += 1;
+    }
+    if find_strategy(&res.strategy).is_none() {
+        eprintln!(
+            "Unknown strategy {}, available: {}",
+            res.strategy,
+            all_strategies().iter().map(|s| s.name()).join(", ")
+        );
+        res.strategy = DEFAULT_STRATEGY.to_owned();
+    }
+    res
+}
+
 fn main() {
+    let args = parse_args();
+    if let Some(ground_truth_path) = &args.benchmark {
+        main_benchmark(&args, ground_truth_path);
+        return;
+    }
+    if args.compare {
+        main_compare_strategies();
+        return;
+    }
     // main_before_crop();
     // main_check_parsing();
     // main_build_graph();
-    main_load_graph();
+    // main_infer_dimensions();
+    // main_solve_frame();
+    // main_solve_graph();
+    // main_export_ground_truth();
+    // main_import_ground_truth();
+    // main_gen_synthetic();
+    main_load_graph(&args.strategy);
     // main_optimize_edge_scoring();
+    // main_tune_edge_scoring();
 }
 
 struct MyApp {
@@ -192,20 +524,21 @@ struct MyApp {
 
 impl MyApp {
     fn new(
-        solutions: Option<InteractiveSolutionPicker>,
+        strategy: Option<String>,
         path: &str,
         show_parsed: bool,
         show_image: bool,
         show_matched_borders: bool,
         crop_enabled: bool,
         known_facts: KnownFacts,
-        graph: Graph,
-        parsed_puzzles: ParsedPuzzles,
+        graph: Arc<Graph>,
+        parsed_puzzles: Arc<ParsedPuzzles>,
+        config: Config,
     ) -> Self {
         Self {
             my_widget: MyWidget::new(
                 path,
-                solutions,
+                strategy,
                 show_parsed,
                 show_image,
                 show_matched_borders,
@@ -213,6 +546,7 @@ impl MyApp {
                 known_facts,
                 graph,
                 parsed_puzzles,
+                config,
             ),
         }
     }
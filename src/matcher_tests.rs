@@ -2,6 +2,7 @@
 mod tests {
     use crate::{
         border_matcher::match_borders,
+        config::MatcherConfig,
         parsed_puzzles::ParsedPuzzles,
         utils::{load_image_from_path, Side},
     };
@@ -33,6 +34,7 @@ mod tests {
                                 fig: other_figure_id,
                                 side: j,
                             },
+                            &MatcherConfig::default(),
                         ) {
                             options.push(result);
                         }
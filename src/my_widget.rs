@@ -1,7 +1,7 @@
-use std::cmp::min;
+use std::{cmp::min, collections::BTreeSet, sync::Arc};
 
 use eframe::{
-    egui::{Key, Sense},
+    egui::{Button, ComboBox, Key, Sense, TextEdit},
     emath::Align2,
     epaint::{
         pos2, vec2, CircleShape, Color32, ColorImage, FontFamily, FontId, Mesh, Pos2, Rect,
@@ -15,15 +15,26 @@ use rand::{rngs::ThreadRng, Rng};
 use crate::{
     border_matcher::{match_borders, MatchResult},
     borders_graph::Graph,
+    config::Config,
     crop::crop,
     dsu::Dsu,
     figure::Figure,
+    frame_solver::{solve_frame, FrameSolution},
     graph_solver::PotentialSolution,
     interactive_solutions_picker::InteractiveSolutionPicker,
-    known_facts::{self, EdgeState, Fact, KnownFacts},
+    known_facts::{
+        self, now_timestamp, EdgeState, Fact, FactConflict, FactSource, KnownFacts, PieceFact,
+        PieceFactKind,
+    },
     parsed_puzzles::ParsedPuzzles,
     point::{Point, PointF},
+    puzzle_dimensions::{infer_dimensions, DimensionsCandidate, PiecesStats},
+    rects_fitter::get_bounding_box,
+    solver_strategy::{all_strategies, find_strategy, run_strategy},
+    solver_worker::SolverWorker,
+    surface_placer::get_border,
     utils::{load_image_from_path, save_color_image, Side},
+    DEFAULT_STRATEGY,
 };
 
 use itertools::Itertools;
@@ -36,7 +47,13 @@ pub struct MyWidget {
     image_path: String,
     mask_image: RetainedImage,
     solutions_picker: Option<InteractiveSolutionPicker>,
-    parsed_puzzles: ParsedPuzzles,
+    // solving, refresh or frame search in progress, picker is moved into it for refresh
+    worker: Option<SolverWorker>,
+    // name of the selected solver strategy
+    strategy: String,
+    // same as used for the graph, so matched borders are ordered the same way
+    config: Config,
+    parsed_puzzles: Arc<ParsedPuzzles>,
     matched_borders: Vec<Vec<MatchResult>>,
     show_parsed: bool,
     show_image: bool,
@@ -47,10 +64,38 @@ pub struct MyWidget {
     selected_solution: Option<usize>,
     new_edges: Vec<EdgeState>,
     known_facts: KnownFacts,
-    graph: Graph,
+    fact_note: String,
+    // computed for `facts_version` length of the facts log
+    fact_conflicts: Vec<FactConflict>,
+    pin_row: String,
+    pin_col: String,
+    board_rows: String,
+    board_cols: String,
+    // computed from piece facts, same as `fact_conflicts`
+    pieces_stats: PiecesStats,
+    dimensions_candidates: Vec<DimensionsCandidate>,
+    facts_version: usize,
+    frame_solution: Option<FrameSolution>,
+    graph: Arc<Graph>,
+    // candidates are reviewed with keyboard shortcuts, see [MyWidget::handle_review_keys]
+    review_mode: bool,
+    // facts were changed in the review mode since the last refresh
+    review_needs_refresh: bool,
+    // new edges of candidates hidden in the review mode, no facts are written for them
+    review_hidden: BTreeSet<Vec<(Side, Side)>>,
+    // positions of picked pieces shown in the inspector, computed for the length of
+    // the facts log (it grows on every change) and the picked pieces
+    piece_positions: Option<(usize, Vec<usize>, Vec<String>)>,
 }
 
 const ZOOM_DELTA_COEF: f32 = 500.0;
+// new edges of a solution in the diff view
+const EDGE_COLORS: [Color32; 4] = [
+    Color32::RED,
+    Color32::BLUE,
+    Color32::from_rgb(0, 160, 0),
+    Color32::from_rgb(200, 0, 200),
+];
 
 fn gen_good_color(rng: &mut ThreadRng) -> Color32 {
     let parts: [u8; 3] = [rng.gen(), rng.gen(), rng.gen()];
@@ -66,14 +111,16 @@ fn gen_good_color(rng: &mut ThreadRng) -> Color32 {
 impl MyWidget {
     pub fn new(
         path: &str,
-        solutions_picker: Option<InteractiveSolutionPicker>,
+        // solving with this strategy starts right away
+        strategy: Option<String>,
         show_parsed: bool,
         show_image: bool,
         show_matched_borders: bool,
         crop_enabled: bool,
         known_facts: KnownFacts,
-        graph: Graph,
-        parsed_puzzles: ParsedPuzzles,
+        graph: Arc<Graph>,
+        parsed_puzzles: Arc<ParsedPuzzles>,
+        config: Config,
     ) -> Self {
         let color_image = load_image_from_path(path).unwrap();
 
@@ -91,8 +138,16 @@ impl MyWidget {
 
         let image = RetainedImage::from_color_image("test", color_image.clone());
         let img_size = image.size_vec2();
+        let fact_conflicts = known_facts.check_consistency();
+        let (board_rows, board_cols) = match known_facts.board_size {
+            Some((rows, cols)) => (rows.to_string(), cols.to_string()),
+            None => (String::new(), String::new()),
+        };
+        let pieces_stats = PiecesStats::new(&parsed_puzzles, &known_facts);
+        let dimensions_candidates = infer_dimensions(&pieces_stats);
+        let facts_version = known_facts.log.len();
 
-        Self {
+        let mut res = Self {
             offset: vec2(0.0, 0.0),
             zoom_log: -1.0,
             frame: vec![
@@ -106,7 +161,12 @@ impl MyWidget {
             mask_image,
             parsed_puzzles,
             matched_borders: vec![vec![]; 4],
-            solutions_picker,
+            solutions_picker: None,
+            worker: None,
+            strategy: strategy
+                .clone()
+                .unwrap_or_else(|| DEFAULT_STRATEGY.to_owned()),
+            config,
             show_parsed,
             fig_colors,
             show_image,
@@ -116,8 +176,26 @@ impl MyWidget {
             selected_solution: None,
             new_edges: vec![],
             known_facts,
+            fact_note: String::new(),
+            fact_conflicts,
+            pin_row: String::new(),
+            pin_col: String::new(),
+            board_rows,
+            board_cols,
+            pieces_stats,
+            dimensions_candidates,
+            facts_version,
+            frame_solution: None,
             graph,
+            review_mode: false,
+            review_needs_refresh: false,
+            review_hidden: BTreeSet::new(),
+            piece_positions: None,
+        };
+        if strategy.is_some() {
+            res.start_solving();
         }
+        res
     }
 
     fn get_zoom(&self) -> f32 {
@@ -248,43 +326,12 @@ impl MyWidget {
             let figure = &self.parsed_puzzles.figures[figure_id];
             self.show_border(figure, ui);
             if ui.input().pointer.primary_clicked() && self.show_matched_borders {
-                for i in 0..4 {
-                    self.matched_borders[i].clear();
-                    if self.parsed_puzzles.figures[figure_id].is_good_puzzle() {
-                        for other_figure_id in 0..self.parsed_puzzles.figures.len() {
-                            if other_figure_id == figure_id {
-                                continue;
-                            }
-                            let figure = &self.parsed_puzzles.figures[figure_id];
-                            let other_figure = &self.parsed_puzzles.figures[other_figure_id];
-                            if other_figure.is_good_puzzle() {
-                                for j in 0..4 {
-                                    if let Some(result) = match_borders(
-                                        &self.parsed_puzzles,
-                                        Side {
-                                            fig: figure_id,
-                                            side: i,
-                                        },
-                                        Side {
-                                            fig: other_figure_id,
-                                            side: j,
-                                        },
-                                    ) {
-                                        self.matched_borders[i].push(result);
-                                    }
-                                }
-                            }
-                        }
-                        self.matched_borders[i].sort_by(|a, b| a.score.total_cmp(&b.score));
-                    }
-                }
+                self.calc_matched_borders(figure_id);
             }
         }
 
-        if let Ok(id) = self.piece_picker.parse::<usize>() {
-            if id < self.parsed_puzzles.figures.len() {
-                self.show_border(&self.parsed_puzzles.figures[id], ui);
-            }
+        for fig in self.picked_pieces() {
+            self.show_border(&self.parsed_puzzles.figures[fig], ui);
         }
 
         if let Some(sol_id) = self.selected_solution {
@@ -296,6 +343,39 @@ impl MyWidget {
         }
     }
 
+    fn calc_matched_borders(&mut self, figure_id: usize) {
+        for i in 0..4 {
+            self.matched_borders[i].clear();
+            if self.parsed_puzzles.figures[figure_id].is_good_puzzle() {
+                for other_figure_id in 0..self.parsed_puzzles.figures.len() {
+                    if other_figure_id == figure_id {
+                        continue;
+                    }
+                    let other_figure = &self.parsed_puzzles.figures[other_figure_id];
+                    if other_figure.is_good_puzzle() {
+                        for j in 0..4 {
+                            if let Some(result) = match_borders(
+                                &self.parsed_puzzles,
+                                Side {
+                                    fig: figure_id,
+                                    side: i,
+                                },
+                                Side {
+                                    fig: other_figure_id,
+                                    side: j,
+                                },
+                                &self.config.matcher,
+                            ) {
+                                self.matched_borders[i].push(result);
+                            }
+                        }
+                    }
+                }
+                self.matched_borders[i].sort_by(|a, b| a.score.total_cmp(&b.score));
+            }
+        }
+    }
+
     fn show_best_matched_borders(&mut self, ui: &mut eframe::egui::Ui) {
         if !self.show_matched_borders {
             return;
@@ -384,7 +464,7 @@ impl MyWidget {
                     should_highlight[fig.figure_id] = true;
                 }
             }
-            let offset = vec2(0.0, self.image.size()[1] as f32 + 100.0);
+            let offset = self.solutions_offset();
 
             {
                 {
@@ -413,8 +493,12 @@ impl MyWidget {
                     let color = self.fig_colors[fig.figure_id];
                     let border_stroke = if should_highlight[fig.figure_id] {
                         Stroke::new(3.0, color)
-                    } else {
+                    } else if !sol_picker.diff_view {
                         Stroke::new(1.0, Color32::BLACK)
+                    } else if sol.new_figures_used.contains(&fig.figure_id) {
+                        Stroke::new(2.0, color)
+                    } else {
+                        Stroke::new(1.0, Color32::GRAY)
                     };
                     for (p1, p2) in fig.positions.iter().circular_tuple_windows() {
                         let p1 = self.convert_to_screen(p1.pos2() + offset);
@@ -441,6 +525,35 @@ impl MyWidget {
                         Color32::BLACK,
                     );
                 }
+                if sol_picker.diff_view {
+                    for (i, (&(s1, _), score)) in sol
+                        .new_edges_used
+                        .iter()
+                        .zip(sol.new_edge_scores.iter())
+                        .enumerate()
+                    {
+                        let fig = match sol.placed_figures.iter().find(|f| f.figure_id == s1.fig) {
+                            Some(fig) => fig,
+                            None => continue,
+                        };
+                        let color = EDGE_COLORS[i % EDGE_COLORS.len()];
+                        let border = get_border(&fig.positions, &self.parsed_puzzles, s1);
+                        for w in border.windows(2) {
+                            let p1 = self.convert_to_screen(w[0].pos2() + offset);
+                            let p2 = self.convert_to_screen(w[1].pos2() + offset);
+                            ui.painter().line_segment([p1, p2], Stroke::new(3.0, color));
+                        }
+                        if let Some(mid) = border.get(border.len() / 2) {
+                            ui.painter().text(
+                                self.convert_to_screen(mid.pos2() + offset),
+                                Align2::CENTER_CENTER,
+                                format!("{score:.3}"),
+                                FontId::new(12.0, FontFamily::Monospace),
+                                color,
+                            );
+                        }
+                    }
+                }
                 for debug_line in sol.debug_lines.iter() {
                     for w in debug_line.windows(2) {
                         let p1 = self.convert_to_screen(w[0].pos2() + offset);
@@ -480,40 +593,160 @@ impl MyWidget {
                 ui.painter().text(
                     self.convert_to_screen(sol.text_offset.pos2() + offset + vec2(10.0, 10.0)),
                     Align2::LEFT_TOP,
-                    format!("{:.3}{}", sol.placement_score, sol.additional_text),
+                    format!(
+                        "{:.3} (closure {:.3}, overlap {:.3}){}",
+                        sol.placement_score,
+                        sol.closure_score,
+                        sol.overlap_score,
+                        sol.additional_text
+                    ),
                     FontId::new(10.0, FontFamily::Monospace),
                     Color32::BLACK,
                 );
             }
         }
-        if let Some((sol_id, new_edges)) = change_picked_solution {
+        if let Some((sol_id, _new_edges)) = change_picked_solution {
             self.selected_solution = Some(sol_id);
-            self.new_edges.clear();
-            for &(s1, s2) in new_edges.iter() {
-                self.new_edges.push(self.known_facts.get_edge_state(s1, s2));
+            self.update_new_edges_states();
+        }
+    }
+
+    fn center_view_on(&mut self, ui: &eframe::egui::Ui, p: Pos2) {
+        self.offset = ui.max_rect().center().to_vec2() / self.get_zoom() - p.to_vec2();
+    }
+
+    // solutions are shown below the image
+    fn solutions_offset(&self) -> Vec2 {
+        vec2(0.0, self.image.size()[1] as f32 + 100.0)
+    }
+
+    fn update_new_edges_states(&mut self) {
+        self.new_edges.clear();
+        if let Some(sol_id) = self.selected_solution {
+            if let Some(sol_picker) = &self.solutions_picker {
+                for &(s1, s2) in sol_picker.solutions_to_show[sol_id].new_edges_used.iter() {
+                    self.new_edges.push(self.known_facts.get_edge_state(s1, s2));
+                }
             }
         }
     }
 
     fn show_elements(&mut self, ui: &mut eframe::egui::Ui) {
-        ui.text_edit_singleline(&mut self.piece_picker);
-        if self.solutions_picker.is_some() {
-            if ui.button("Refresh").clicked() {
-                self.solutions_picker.as_mut().unwrap().refresh(
-                    &self.known_facts,
-                    &self.parsed_puzzles,
-                    &self.graph,
-                );
-                self.selected_solution = None;
+        ui.horizontal(|ui| {
+            ui.label("Pieces:");
+            let response = ui.text_edit_singleline(&mut self.piece_picker);
+            if response.changed() {
+                self.focus_on_picked_pieces(ui);
+            }
+            if response.lost_focus() && ui.input().key_pressed(Key::Enter) {
+                if let Some(&fig) = self.picked_pieces().first() {
+                    self.calc_matched_borders(fig);
+                    self.show_matched_borders = true;
+                }
             }
+        });
+        self.show_piece_inspector(ui);
+        ui.horizontal(|ui| {
+            if self.solutions_picker.is_some() && self.worker.is_none() {
+                if ui.button("Refresh").clicked() {
+                    self.start_refresh();
+                }
+                if ui.button("Global refine").clicked() {
+                    self.start_global_refine();
+                }
+                if ui.button("Rotate 90°").clicked() {
+                    self.solutions_picker.as_mut().unwrap().rotate_assembly();
+                }
+                let picker = self.solutions_picker.as_mut().unwrap();
+                let mut diff_view = picker.diff_view;
+                if ui.checkbox(&mut diff_view, "Diff view").changed() {
+                    picker.set_diff_view(diff_view);
+                    // solutions without new figures are hidden, so ids change
+                    self.selected_solution = None;
+                    self.update_new_edges_states();
+                }
+                if ui.checkbox(&mut self.review_mode, "Review mode").changed() && self.review_mode {
+                    self.selected_solution = None;
+                    self.review_step(ui, true);
+                }
+            }
+            ComboBox::from_label("strategy")
+                .selected_text(self.strategy.clone())
+                .show_ui(ui, |ui| {
+                    for strategy in all_strategies() {
+                        ui.selectable_value(
+                            &mut self.strategy,
+                            strategy.name().to_owned(),
+                            strategy.name(),
+                        );
+                    }
+                });
+            if ui
+                .add_enabled(self.worker.is_none(), Button::new("Solve"))
+                .clicked()
+            {
+                self.start_solving();
+            }
+        });
+        self.show_worker(ui);
+        if self.review_mode {
+            ui.label(
+                "Review: Left/Right or P/N - previous/next, A - accept all edges, \
+                R - hide candidate, 1-9 - mark one edge wrong, S/Space - skip, F - refresh",
+            );
+        }
+        if let Some(report) = self
+            .solutions_picker
+            .as_ref()
+            .and_then(|picker| picker.adjustment_report.as_ref())
+        {
+            ui.label(format!("Global refine: {}", report.describe()))
+                .on_hover_text(
+                    report
+                        .residuals
+                        .iter()
+                        .take(20)
+                        .map(|r| format!("{} - {}: {:.3}", r.s1.fig, r.s2.fig, r.rms))
+                        .join("\n"),
+                );
         }
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(self.known_facts.can_undo(), Button::new("Undo"))
+                .clicked()
+            {
+                self.known_facts.undo();
+                self.update_new_edges_states();
+            }
+            if ui
+                .add_enabled(self.known_facts.can_redo(), Button::new("Redo"))
+                .clicked()
+            {
+                self.known_facts.redo();
+                self.update_new_edges_states();
+            }
+            ui.label("Note for new facts:");
+            ui.text_edit_singleline(&mut self.fact_note);
+        });
+        self.show_facts_conflicts(ui);
+        self.show_board_size(ui);
+        self.show_piece_facts(ui);
+        self.show_frame_solver(ui);
         if let Some(sol_id) = self.selected_solution {
             for i in 0..self.new_edges.len() {
                 ui.horizontal(|ui| {
-                    let (s1, s2) = self.solutions_picker.as_ref().unwrap().solutions_to_show
-                        [sol_id]
-                        .new_edges_used[i];
-                    ui.label(format!("{} - {}", s1.fig, s2.fig));
+                    let sol_picker = self.solutions_picker.as_ref().unwrap();
+                    let sol = &sol_picker.solutions_to_show[sol_id];
+                    let (s1, s2) = sol.new_edges_used[i];
+                    let text = format!("{} - {}: {:.3}", s1.fig, s2.fig, sol.new_edge_scores[i]);
+                    let label = if sol_picker.diff_view {
+                        ui.colored_label(EDGE_COLORS[i % EDGE_COLORS.len()], text)
+                    } else {
+                        ui.label(text)
+                    };
+                    if let Some(fact) = self.known_facts.find_fact(s1, s2) {
+                        label.on_hover_text(describe_fact(fact));
+                    }
                     if ui
                         .radio_value(&mut self.new_edges[i], EdgeState::Unknown, "Unknown")
                         .clicked()
@@ -525,21 +758,570 @@ impl MyWidget {
                         .radio_value(&mut self.new_edges[i], EdgeState::GoodEdge, "Good")
                         .clicked()
                     {
-                        self.known_facts.add_fact(&Fact::new(s1, s2, true));
+                        self.known_facts
+                            .add_fact(&Fact::new(s1, s2, true).with_note(&self.fact_note));
                     }
                     if ui
                         .radio_value(&mut self.new_edges[i], EdgeState::WrongEdge, "Wrong")
                         .clicked()
                     {
-                        self.known_facts.add_fact(&Fact::new(s1, s2, false));
+                        self.known_facts
+                            .add_fact(&Fact::new(s1, s2, false).with_note(&self.fact_note));
+                    }
+                });
+            }
+        }
+    }
+
+    // Next (or previous) candidate, which is not invalidated by known facts. Solutions are
+    // refreshed in the background when there are no more candidates after some decisions.
+    fn review_step(&mut self, ui: &eframe::egui::Ui, forward: bool) {
+        let picker = match &self.solutions_picker {
+            Some(picker) => picker,
+            None => return,
+        };
+        let ids = if forward {
+            (self.selected_solution.map_or(0, |id| id + 1)..picker.solutions_to_show.len())
+                .collect_vec()
+        } else {
+            (0..self.selected_solution.unwrap_or(0)).rev().collect_vec()
+        };
+        let next = ids.into_iter().find(|&id| {
+            let sol = &picker.solutions_to_show[id];
+            !sol.new_edges_used.is_empty()
+                && !self.review_hidden.contains(&sol.new_edges_used)
+                && sol.refresh(&self.known_facts).is_some()
+        });
+        match next {
+            Some(id) => {
+                let sol = &picker.solutions_to_show[id];
+                let center = ((sol.bbox.0 + sol.bbox.1) / 2.0).pos2() + self.solutions_offset();
+                self.center_view_on(ui, center);
+                self.selected_solution = Some(id);
+                self.update_new_edges_states();
+            }
+            None if forward && self.review_needs_refresh && self.worker.is_none() => {
+                self.start_refresh();
+            }
+            None => {}
+        }
+    }
+
+    // Marks new edges of the selected candidate, all of them if `edge_id` is None.
+    fn review_decide(&mut self, edge_id: Option<usize>, good: bool) {
+        let (sol_id, picker) = match (self.selected_solution, &self.solutions_picker) {
+            (Some(sol_id), Some(picker)) => (sol_id, picker),
+            _ => return,
+        };
+        // facts of the opposite kind are replaced, all of them in one undo step
+        let facts = picker.solutions_to_show[sol_id]
+            .new_edges_used
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| edge_id.is_none() || edge_id == Some(i))
+            .map(|(_, &(s1, s2))| Fact::new(s1, s2, good).with_note(&self.fact_note))
+            .collect_vec();
+        // key of an edge, which the candidate doesn't have
+        if facts.is_empty() {
+            return;
+        }
+        self.known_facts.add_facts(&facts);
+        self.review_needs_refresh = true;
+        self.update_new_edges_states();
+    }
+
+    fn review_hide(&mut self) {
+        if let (Some(sol_id), Some(picker)) = (self.selected_solution, &self.solutions_picker) {
+            let edges = picker.solutions_to_show[sol_id].new_edges_used.clone();
+            self.review_hidden.insert(edges);
+        }
+    }
+
+    fn handle_review_keys(&mut self, ui: &eframe::egui::Ui) {
+        const EDGE_KEYS: [Key; 9] = [
+            Key::Num1,
+            Key::Num2,
+            Key::Num3,
+            Key::Num4,
+            Key::Num5,
+            Key::Num6,
+            Key::Num7,
+            Key::Num8,
+            Key::Num9,
+        ];
+        if ui.ctx().wants_keyboard_input() || self.solutions_picker.is_none() {
+            return;
+        }
+        let pressed = |keys: &[Key]| keys.iter().any(|&key| ui.input().key_pressed(key));
+        if pressed(&[Key::ArrowRight, Key::N, Key::S, Key::Space]) {
+            self.review_step(ui, true);
+        } else if pressed(&[Key::ArrowLeft, Key::P]) {
+            self.review_step(ui, false);
+        } else if pressed(&[Key::A]) {
+            self.review_decide(None, true);
+            self.review_step(ui, true);
+        } else if pressed(&[Key::R]) {
+            self.review_hide();
+            self.review_step(ui, true);
+        } else if let Some(edge_id) = EDGE_KEYS.iter().position(|&key| pressed(&[key])) {
+            self.review_decide(Some(edge_id), false);
+        } else if pressed(&[Key::F]) && self.worker.is_none() {
+            self.start_refresh();
+        }
+    }
+
+    fn start_solving(&mut self) {
+        let strategy = find_strategy(&self.strategy).unwrap();
+        let known_facts = self.known_facts.clone();
+        let parsed_puzzles = self.parsed_puzzles.clone();
+        let graph = self.graph.clone();
+        let config = self.config.clone();
+        self.worker = Some(SolverWorker::start(strategy.name(), move |sink| {
+            run_strategy(
+                strategy.as_ref(),
+                &graph,
+                &parsed_puzzles,
+                &known_facts,
+                &config,
+                sink,
+            )
+        }));
+    }
+
+    // Existing picker stays as is, the frame is sent as a separate event.
+    fn start_frame_solving(&mut self) {
+        self.frame_solution = None;
+        let known_facts = self.known_facts.clone();
+        let parsed_puzzles = self.parsed_puzzles.clone();
+        let graph = self.graph.clone();
+        self.worker = Some(SolverWorker::start("frame", move |sink| {
+            sink.report("Frame search", 0.0, None);
+            match solve_frame(&graph, &parsed_puzzles, &known_facts) {
+                Some(frame) => sink.send_frame(frame),
+                None => eprintln!("Frame not found"),
+            }
+            None
+        }));
+    }
+
+    // Current solutions stay reviewable until the refreshed picker replaces them.
+    fn start_refresh(&mut self) {
+        let picker = self.solutions_picker.as_ref().unwrap().copy_for_job();
+        self.review_needs_refresh = false;
+        let known_facts = self.known_facts.clone();
+        let parsed_puzzles = self.parsed_puzzles.clone();
+        let graph = self.graph.clone();
+        self.worker = Some(SolverWorker::start_with_picker(
+            "refresh",
+            picker,
+            move |picker, sink| {
+                picker.refresh(&known_facts, &parsed_puzzles, &graph, sink);
+            },
+        ));
+    }
+
+    fn start_global_refine(&mut self) {
+        let picker = self.solutions_picker.as_ref().unwrap().copy_for_job();
+        let known_facts = self.known_facts.clone();
+        let parsed_puzzles = self.parsed_puzzles.clone();
+        let graph = self.graph.clone();
+        self.worker = Some(SolverWorker::start_with_picker(
+            "global refine",
+            picker,
+            move |picker, sink| {
+                sink.report("Global refine", 0.0, None);
+                picker.global_refine(&known_facts, &parsed_puzzles);
+                picker.refresh(&known_facts, &parsed_puzzles, &graph, sink);
+            },
+        ));
+    }
+
+    fn poll_worker(&mut self, ui: &mut eframe::egui::Ui) {
+        let worker = match &mut self.worker {
+            Some(worker) => worker,
+            None => return,
+        };
+        match worker.poll() {
+            Some((picker, cancelled)) => {
+                if cancelled {
+                    eprintln!("{} was cancelled", worker.name);
+                }
+                if let Some(frame) = worker.frame.take() {
+                    self.frame_solution = Some(frame);
+                }
+                if picker.is_some() {
+                    self.solutions_picker = picker;
+                    self.selected_solution = None;
+                    self.update_new_edges_states();
+                }
+                self.worker = None;
+                if self.review_mode {
+                    self.review_step(ui, true);
+                }
+            }
+            // events come from another thread, so keep polling
+            None => ui.ctx().request_repaint(),
+        }
+    }
+
+    fn show_worker(&mut self, ui: &mut eframe::egui::Ui) {
+        let worker = match &self.worker {
+            Some(worker) => worker,
+            None => return,
+        };
+        const SHOW_CANDIDATES: usize = 10;
+        ui.horizontal(|ui| {
+            let progress = &worker.progress;
+            ui.label(format!(
+                "{}: {} {:.0}%{}",
+                worker.name,
+                progress.stage,
+                progress.percent,
+                match progress.best_score {
+                    Some(score) => format!(", best {score:.3}"),
+                    None => String::new(),
+                }
+            ));
+            if ui
+                .add_enabled(!worker.is_cancelling(), Button::new("Cancel"))
+                .clicked()
+            {
+                worker.cancel();
+            }
+        });
+        if !worker.candidates.is_empty() {
+            ui.collapsing(
+                format!("Candidates so far: {}", worker.candidates.len()),
+                |ui| {
+                    for sol in worker.candidates.iter().take(SHOW_CANDIDATES) {
+                        ui.label(format!(
+                            "{:.3} (closure {:.3}, overlap {:.3}): {}{}",
+                            sol.placement_score,
+                            sol.closure_score,
+                            sol.overlap_score,
+                            sol.new_edges_used
+                                .iter()
+                                .map(|(s1, s2)| format!("{}-{}", s1.fig, s2.fig))
+                                .join(", "),
+                            sol.additional_text
+                        ));
+                    }
+                },
+            );
+        }
+    }
+
+    fn show_board_size(&mut self, ui: &mut eframe::egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Board rows:");
+            ui.add(TextEdit::singleline(&mut self.board_rows).desired_width(30.0));
+            ui.label("cols:");
+            ui.add(TextEdit::singleline(&mut self.board_cols).desired_width(30.0));
+            if let (Ok(rows), Ok(cols)) = (self.board_rows.parse(), self.board_cols.parse()) {
+                if ui.button("Set board").clicked() {
+                    self.known_facts.set_board_size(Some((rows, cols)));
+                }
+            }
+            if self.known_facts.board_size.is_some() && ui.button("Clear board").clicked() {
+                self.known_facts.set_board_size(None);
+            }
+            match self.known_facts.board_size {
+                Some((rows, cols)) => ui.label(format!("Current: {rows}x{cols}")),
+                None => ui.label("Current: unknown"),
+            };
+        });
+        ui.horizontal(|ui| {
+            ui.label(format!(
+                "Pieces: {}, border: {}, corners: {}. Candidates:",
+                self.pieces_stats.cnt_pieces,
+                self.pieces_stats.cnt_border,
+                self.pieces_stats.cnt_corners
+            ));
+            for candidate in self.dimensions_candidates.iter() {
+                ui.label(format!("{}x{}", candidate.rows, candidate.cols))
+                    .on_hover_text(format!(
+                        "{} pieces, {} on border, score {:.3}",
+                        candidate.cnt_pieces(),
+                        candidate.cnt_border_pieces(),
+                        candidate.score
+                    ));
+                // candidates always have rows <= cols, the photo may show the board rotated
+                let mut orientations = vec![(candidate.rows, candidate.cols)];
+                if candidate.rows != candidate.cols {
+                    orientations.push((candidate.cols, candidate.rows));
+                }
+                for (rows, cols) in orientations {
+                    if ui.button(format!("Use {rows}x{cols}")).clicked() {
+                        self.board_rows = rows.to_string();
+                        self.board_cols = cols.to_string();
+                        self.known_facts.set_board_size(Some((rows, cols)));
+                    }
+                }
+            }
+        });
+    }
+
+    fn show_frame_solver(&mut self, ui: &mut eframe::egui::Ui) {
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(self.worker.is_none(), Button::new("Solve frame"))
+                .clicked()
+            {
+                self.start_frame_solving();
+            }
+            if let Some(frame) = &self.frame_solution {
+                ui.label(format!(
+                    "Frame: {} figures, segments {:?}, cost {:.3}, max edge {:.3}",
+                    frame.order.len(),
+                    frame.segments,
+                    frame.cost,
+                    frame.max_edge_dist
+                ))
+                .on_hover_text(frame.order.iter().map(|f| f.figure_id).join(" "));
+                if ui.button("Accept frame").clicked() {
+                    let note = if self.fact_note.is_empty() {
+                        "frame solver"
+                    } else {
+                        &self.fact_note
+                    };
+                    let facts = frame
+                        .edges()
+                        .into_iter()
+                        .map(|(s1, s2)| {
+                            Fact::new(s1, s2, true)
+                                .with_source(FactSource::AutoAccept)
+                                .with_note(note)
+                        })
+                        .collect_vec();
+                    self.known_facts.add_facts(&facts);
+                    self.update_new_edges_states();
+                }
+            }
+        });
+    }
+
+    fn show_piece_facts(&mut self, ui: &mut eframe::egui::Ui) {
+        let fig = match self.picked_pieces().first() {
+            Some(&fig) => fig,
+            None => return,
+        };
+        let mut to_add = None;
+        ui.horizontal(|ui| {
+            ui.label(format!("Piece {fig}:"));
+            if ui.button("Exclude").clicked() {
+                to_add = Some(PieceFactKind::Excluded);
+            }
+            if ui.button("Border").clicked() {
+                to_add = Some(PieceFactKind::BorderPiece);
+            }
+            if ui.button("Corner").clicked() {
+                to_add = Some(PieceFactKind::CornerPiece);
+            }
+            ui.label("row:");
+            ui.add(TextEdit::singleline(&mut self.pin_row).desired_width(30.0));
+            ui.label("col:");
+            ui.add(TextEdit::singleline(&mut self.pin_col).desired_width(30.0));
+            if let (Ok(row), Ok(col)) = (self.pin_row.parse(), self.pin_col.parse()) {
+                if ui.button("Pin").clicked() {
+                    to_add = Some(PieceFactKind::Pinned { row, col });
+                }
+            }
+        });
+        if let Some(kind) = to_add {
+            self.known_facts
+                .add_piece_fact(&PieceFact::new(fig, kind).with_note(&self.fact_note));
+        }
+        let mut to_remove = None;
+        for fact in self.known_facts.get_piece_facts(fig) {
+            ui.horizontal(|ui| {
+                let label = ui.label(format!("{:?}", fact.kind));
+                if let Some(note) = &fact.note {
+                    label.on_hover_text(note);
+                }
+                if ui.button("Remove").clicked() {
+                    to_remove = Some(fact.clone());
+                }
+            });
+        }
+        if let Some(fact) = to_remove {
+            self.known_facts.remove_piece_fact(&fact);
+        }
+    }
+
+    // ids of pieces, typed in the piece picker, separated by anything
+    fn picked_pieces(&self) -> Vec<usize> {
+        self.piece_picker
+            .split(|c: char| !c.is_ascii_digit())
+            .filter_map(|id| id.parse::<usize>().ok())
+            .filter(|&fig| fig < self.parsed_puzzles.figures.len())
+            .unique()
+            .collect_vec()
+    }
+
+    // Picked pieces take about a third of the view.
+    fn focus_on_picked_pieces(&mut self, ui: &eframe::egui::Ui) {
+        const VIEW_PART: f32 = 0.3;
+
+        let pts = self
+            .picked_pieces()
+            .into_iter()
+            .flat_map(|fig| self.parsed_puzzles.figures[fig].border.iter())
+            .map(|p| p.conv_f64())
+            .collect_vec();
+        if pts.is_empty() {
+            return;
+        }
+        let (p0, p1) = get_bounding_box(&pts);
+        let size = (p1 - p0).pos2();
+        let view = ui.max_rect().size();
+        self.zoom_log = ((view.x / size.x).min(view.y / size.y) * VIEW_PART).ln();
+        self.center_view_on(ui, ((p0 + p1) / 2.0).pos2());
+    }
+
+    // Position of each picked piece in the known placement and its edge facts.
+    fn show_piece_inspector(&mut self, ui: &mut eframe::egui::Ui) {
+        let pieces = self.picked_pieces();
+        if pieces.is_empty() {
+            return;
+        }
+        let facts_version = self.known_facts.log.len();
+        match &self.piece_positions {
+            Some((version, cached, _)) if *version == facts_version && *cached == pieces => {}
+            _ => {
+                let components = self.known_facts.build_placement().get_cells_by_component();
+                let positions = pieces
+                    .iter()
+                    .map(|&fig| {
+                        components
+                            .iter()
+                            .find_map(|cells| {
+                                let &((row, col), sides) =
+                                    cells.iter().find(|(_, sides)| sides[0].fig == fig)?;
+                                Some(format!(
+                                    "row {row}, col {col} in a component of {} pieces, \
+                                    side {} is up",
+                                    cells.len(),
+                                    sides[0].side
+                                ))
+                            })
+                            .unwrap_or_else(|| "not placed".to_owned())
+                    })
+                    .collect_vec();
+                self.piece_positions = Some((facts_version, pieces.clone(), positions));
+            }
+        }
+        let positions = &self.piece_positions.as_ref().unwrap().2;
+        for (fig, position) in pieces.into_iter().zip(positions.iter()) {
+            ui.label(format!("Piece {fig}: {position}"));
+            for fact in self
+                .known_facts
+                .facts
+                .iter()
+                .filter(|f| f.side1.fig == fig || f.side2.fig == fig)
+            {
+                ui.label(format!(
+                    "    {}:{} - {}:{} {}",
+                    fact.side1.fig,
+                    fact.side1.side,
+                    fact.side2.fig,
+                    fact.side2.side,
+                    if fact.good_edge { "good" } else { "wrong" }
+                ))
+                .on_hover_text(describe_fact(fact));
+            }
+        }
+    }
+
+    fn show_facts_conflicts(&mut self, ui: &mut eframe::egui::Ui) {
+        // conflicts are kept up to date by `refresh_facts_state`
+        ui.label(format!(
+            "Conflicts: {}. Quarantined: {}",
+            self.fact_conflicts.len(),
+            self.known_facts.quarantined.len()
+        ));
+        let mut to_quarantine = None;
+        for conflict in self.fact_conflicts.iter() {
+            ui.horizontal(|ui| {
+                ui.label(conflict.describe());
+                if ui.button("Quarantine").clicked() {
+                    to_quarantine = Some(conflict.fact.clone());
+                }
+            });
+        }
+        if let Some(picker) = &self.solutions_picker {
+            if !picker.suspicious_facts.is_empty() {
+                ui.collapsing("Suspicious facts (corners don't close)", |ui| {
+                    for (fact, spread) in picker.suspicious_facts.iter() {
+                        ui.horizontal(|ui| {
+                            ui.label(format!(
+                                "{}:{} - {}:{}, spread {:.3}",
+                                fact.side1.fig,
+                                fact.side1.side,
+                                fact.side2.fig,
+                                fact.side2.side,
+                                spread
+                            ))
+                            .on_hover_text(describe_fact(fact));
+                            if ui.button("Quarantine").clicked() {
+                                to_quarantine = Some(fact.clone());
+                            }
+                        });
                     }
                 });
             }
         }
+        let mut to_release = None;
+        if !self.known_facts.quarantined.is_empty() {
+            ui.collapsing("Quarantined facts", |ui| {
+                for fact in self.known_facts.quarantined.iter() {
+                    ui.horizontal(|ui| {
+                        ui.label(format!(
+                            "{}:{} - {}:{}",
+                            fact.side1.fig, fact.side1.side, fact.side2.fig, fact.side2.side
+                        ))
+                        .on_hover_text(describe_fact(fact));
+                        if ui.button("Release").clicked() {
+                            to_release = Some(fact.clone());
+                        }
+                    });
+                }
+            });
+        }
+        if to_quarantine.is_some() || to_release.is_some() {
+            if let Some(fact) = to_quarantine {
+                self.known_facts.quarantine(&fact);
+            }
+            if let Some(fact) = to_release {
+                self.known_facts.release_from_quarantine(&fact);
+            }
+            self.update_new_edges_states();
+        }
+    }
+
+    // Everything computed from facts is recomputed here, after any change including undo and
+    // redo. Returns true if something was recomputed.
+    fn refresh_facts_state(&mut self) -> bool {
+        let facts_version = self.known_facts.log.len();
+        if facts_version == self.facts_version {
+            return false;
+        }
+        self.facts_version = facts_version;
+        self.fact_conflicts = self.known_facts.check_consistency();
+        self.pieces_stats = PiecesStats::new(&self.parsed_puzzles, &self.known_facts);
+        self.dimensions_candidates = infer_dimensions(&self.pieces_stats);
+        true
     }
 
     pub fn ui(&mut self, ui: &mut eframe::egui::Ui) -> eframe::egui::Response {
+        self.poll_worker(ui);
         self.show_elements(ui);
+        if self.review_mode {
+            self.handle_review_keys(ui);
+        }
+        // panels above were drawn with the old state
+        if self.refresh_facts_state() {
+            ui.ctx().request_repaint();
+        }
 
         let (rect, response) = ui.allocate_exact_size(ui.available_size(), Sense::drag());
         {
@@ -625,6 +1407,22 @@ impl MyWidget {
     }
 }
 
+fn describe_fact(fact: &Fact) -> String {
+    let age = now_timestamp().saturating_sub(fact.timestamp);
+    let mut res = format!(
+        "{} from {:?}",
+        if fact.good_edge { "good" } else { "wrong" },
+        fact.source
+    );
+    if fact.timestamp != 0 {
+        res += &format!(", {} min ago", age / 60);
+    }
+    if let Some(note) = &fact.note {
+        res += &format!(": {note}");
+    }
+    res
+}
+
 fn calc_center(positions: &[PointF]) -> PointF {
     let mut res = PointF::ZERO;
     for p in positions.iter() {
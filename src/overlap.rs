@@ -0,0 +1,137 @@
+use itertools::Itertools;
+
+use crate::{
+    graph_solver::{is_inside, PlacedFigure},
+    point::PointF,
+    rects_fitter::get_bounding_box,
+    solver_strategy::SolverConfig,
+    utils::fmax,
+};
+
+// every n-th border point is used, pixel borders are too detailed
+const SIMPLIFY_STEP: usize = 3;
+
+#[derive(Clone, Debug)]
+pub struct FigureOverlap {
+    pub fig: usize,
+    pub other: usize,
+    // intersection area relative to the area of `fig`
+    pub overlap: f64,
+}
+
+fn signed_area(poly: &[PointF]) -> f64 {
+    poly.iter()
+        .circular_tuple_windows()
+        .map(|(p1, p2)| p1.x * p2.y - p2.x * p1.y)
+        .sum::<f64>()
+        / 2.0
+}
+
+// with positive signed area
+fn simplify(poly: &[PointF]) -> Vec<PointF> {
+    let mut res = poly.iter().step_by(SIMPLIFY_STEP).cloned().collect_vec();
+    if signed_area(&res) < 0.0 {
+        res.reverse();
+    }
+    res
+}
+
+// position of the intersection on (a1, a2) from 0 to 1
+fn intersect_segments(a1: PointF, a2: PointF, b1: PointF, b2: PointF) -> Option<f64> {
+    let da = a2 - a1;
+    let db = b2 - b1;
+    let denom = da.x * db.y - da.y * db.x;
+    if denom == 0.0 {
+        return None;
+    }
+    let d = b1 - a1;
+    let t = (d.x * db.y - d.y * db.x) / denom;
+    let u = (d.x * da.y - d.y * da.x) / denom;
+    if (0.0..=1.0).contains(&t) && (0.0..=1.0).contains(&u) {
+        Some(t)
+    } else {
+        None
+    }
+}
+
+// Part of the Green's theorem integral for the intersection boundary, which goes along `a`.
+fn boundary_inside_integral(a: &[PointF], b: &[PointF]) -> f64 {
+    let mut res = 0.0;
+    for (&p1, &p2) in a.iter().circular_tuple_windows() {
+        let mut ts = vec![0.0, 1.0];
+        for (&q1, &q2) in b.iter().circular_tuple_windows() {
+            if let Some(t) = intersect_segments(p1, p2, q1, q2) {
+                ts.push(t);
+            }
+        }
+        ts.sort_by(f64::total_cmp);
+        for (&t1, &t2) in ts.iter().tuple_windows() {
+            let mid = p1 + (p2 - p1) * ((t1 + t2) / 2.0);
+            if is_inside(b, mid) {
+                let u = p1 + (p2 - p1) * t1;
+                let v = p1 + (p2 - p1) * t2;
+                res += u.x * v.y - v.x * u.y;
+            }
+        }
+    }
+    res
+}
+
+// Both polygons should be simple, they could be non-convex.
+pub fn intersection_area(a: &[PointF], b: &[PointF]) -> f64 {
+    (boundary_inside_integral(a, b) + boundary_inside_integral(b, a)) / 2.0
+}
+
+fn boxes_intersect(a: (PointF, PointF), b: (PointF, PointF)) -> bool {
+    a.0.x < b.1.x && b.0.x < a.1.x && a.0.y < b.1.y && b.0.y < a.1.y
+}
+
+// Overlaps of new figures with all other placed figures. Pairs of new figures are counted once.
+pub fn calc_overlaps(
+    placed_figures: &[PlacedFigure],
+    new_figures: &[usize],
+    config: &SolverConfig,
+) -> Vec<FigureOverlap> {
+    let polygons = placed_figures
+        .iter()
+        .map(|f| simplify(&f.positions))
+        .collect_vec();
+    let boxes = placed_figures
+        .iter()
+        .map(|f| get_bounding_box(&f.positions))
+        .collect_vec();
+    let mut res = vec![];
+    for i in 0..placed_figures.len() {
+        let fig = placed_figures[i].figure_id;
+        if !new_figures.contains(&fig) {
+            continue;
+        }
+        let area = signed_area(&polygons[i]);
+        for j in 0..placed_figures.len() {
+            let other = placed_figures[j].figure_id;
+            if i == j || (new_figures.contains(&other) && j < i) {
+                continue;
+            }
+            if !boxes_intersect(boxes[i], boxes[j]) {
+                continue;
+            }
+            let overlap = intersection_area(&polygons[i], &polygons[j]) / area;
+            if overlap > config.ok_overlap {
+                res.push(FigureOverlap {
+                    fig,
+                    other,
+                    overlap,
+                });
+            }
+        }
+    }
+    res
+}
+
+// the worst pair matters, small overlaps are ignored
+pub fn calc_overlap_score(overlaps: &[FigureOverlap], config: &SolverConfig) -> f64 {
+    overlaps
+        .iter()
+        .map(|o| o.overlap - config.ok_overlap)
+        .fold(0.0, fmax)
+}
@@ -0,0 +1,84 @@
+#[cfg(test)]
+mod tests {
+    use crate::{
+        graph_solver::PlacedFigure,
+        overlap::{calc_overlap_score, calc_overlaps, intersection_area},
+        point::PointF,
+        solver_strategy::SolverConfig,
+    };
+
+    // border with a point on each pixel, like parsed figures have
+    fn rect(x0: f64, y0: f64, w: f64, h: f64) -> Vec<PointF> {
+        let corners = [(x0, y0), (x0 + w, y0), (x0 + w, y0 + h), (x0, y0 + h)];
+        let mut res = vec![];
+        for i in 0..4 {
+            let (ax, ay) = corners[i];
+            let (bx, by) = corners[(i + 1) % 4];
+            let steps = ((bx - ax).abs() + (by - ay).abs()) as usize;
+            for k in 0..steps {
+                let t = k as f64 / steps as f64;
+                res.push(PointF {
+                    x: ax + (bx - ax) * t,
+                    y: ay + (by - ay) * t,
+                });
+            }
+        }
+        res
+    }
+
+    #[test]
+    pub fn rect_intersections() {
+        let a = rect(0.0, 0.0, 10.0, 10.0);
+        assert!((intersection_area(&a, &rect(5.0, 5.0, 10.0, 10.0)) - 25.0).abs() < 1e-6);
+        assert!((intersection_area(&a, &rect(2.0, 2.0, 4.0, 4.0)) - 16.0).abs() < 1e-6);
+        assert!(intersection_area(&a, &rect(20.0, 0.0, 10.0, 10.0)).abs() < 1e-6);
+
+        // U shape around the rect, only the bottom part could intersect
+        let mut u = vec![];
+        for (x, y) in [
+            (-5, -5),
+            (-3, -5),
+            (-3, 12),
+            (13, 12),
+            (13, -5),
+            (15, -5),
+            (15, 14),
+            (-5, 14),
+        ] {
+            u.push(PointF {
+                x: x as f64,
+                y: y as f64,
+            });
+        }
+        assert!(intersection_area(&a, &u).abs() < 1e-6);
+        let moved = u
+            .iter()
+            .map(|p| *p + PointF { x: 0.0, y: -3.0 })
+            .collect::<Vec<_>>();
+        assert!((intersection_area(&a, &moved) - 10.0).abs() < 1e-6);
+    }
+
+    #[test]
+    pub fn only_new_figures_are_checked() {
+        let figure = |figure_id, x0, y0| PlacedFigure {
+            figure_id,
+            positions: rect(x0, y0, 30.0, 30.0),
+        };
+        // 0 and 1 are neighbours, which overlap a bit, 2 intrudes into 1 by one third
+        let placed = vec![
+            figure(0, 0.0, 0.0),
+            figure(1, 29.5, 0.3),
+            figure(2, 49.5, 0.6),
+        ];
+        let config = SolverConfig::default();
+        assert_eq!(
+            calc_overlap_score(&calc_overlaps(&placed, &[0], &config), &config),
+            0.0
+        );
+        let overlaps = calc_overlaps(&placed, &[2], &config);
+        assert_eq!(overlaps.len(), 1);
+        assert_eq!(overlaps[0].other, 1);
+        assert!((overlaps[0].overlap - 1.0 / 3.0).abs() < 0.02);
+        assert!(calc_overlap_score(&overlaps, &config) > 0.25);
+    }
+}
@@ -11,7 +11,7 @@ use rand::Rng;
 
 use crate::{
     average_color::AverareColor,
-    border_matcher::is_picture_border,
+    border_matcher::{is_picture_border, picture_border_deviation},
     dsu::Dsu,
     figure::{BorderFigure, Figure},
     known_facts::KnownFacts,
@@ -296,6 +296,42 @@ impl ParsedPuzzles {
             .collect_vec()
     }
 
+    // same as [calc_figures_on_border], but excluded figures are removed and
+    // figures marked as border or corner pieces are added even if we failed to detect them.
+    pub fn calc_figures_on_border_with_facts(&self, known_facts: &KnownFacts) -> Vec<BorderFigure> {
+        let mut res = self
+            .calc_figures_on_border()
+            .into_iter()
+            .filter(|f| !known_facts.is_excluded(f.figure_id))
+            .collect_vec();
+        for (fig, is_corner) in known_facts.get_known_border_figures() {
+            if known_facts.is_excluded(fig) || fig >= self.figures.len() {
+                continue;
+            }
+            res.retain(|f| f.figure_id != fig);
+            let figure = &self.figures[fig];
+            let deviation = (0..4)
+                .map(|border_id| picture_border_deviation(figure, border_id))
+                .collect_vec();
+            if is_corner {
+                let i = (0..4)
+                    .min_by(|&i, &j| {
+                        (deviation[i] + deviation[(i + 1) % 4])
+                            .total_cmp(&(deviation[j] + deviation[(j + 1) % 4]))
+                    })
+                    .unwrap();
+                res.push(BorderFigure::new(fig, i + 2, i + 3));
+            } else {
+                let i = (0..4)
+                    .min_by(|&i, &j| deviation[i].total_cmp(&deviation[j]))
+                    .unwrap();
+                res.push(BorderFigure::new(fig, i + 1, i + 3));
+            }
+        }
+        res.sort_by_key(|f| f.figure_id);
+        res
+    }
+
     pub fn gen_all_sides(&self) -> Vec<Side> {
         let mut res = vec![];
         for fig in 0..self.figures.len() {
@@ -1,5 +1,5 @@
 use std::{
-    collections::hash_map::DefaultHasher,
+    collections::{hash_map::DefaultHasher, BTreeMap, BTreeSet},
     hash::{Hash, Hasher},
 };
 
@@ -15,6 +15,10 @@ pub struct Pos {
 }
 
 impl Pos {
+    pub fn new(x: i32, y: i32) -> Self {
+        Self { x, y }
+    }
+
     pub fn shift(&self, dx: i32, dy: i32) -> Self {
         Self {
             x: self.x + dx,
@@ -54,6 +58,9 @@ impl Pos {
     }
 }
 
+// (row, col) of a figure in its component and its sides pointing up, right, down and left
+pub type PlacedCell = ((usize, usize), [Side; 4]);
+
 #[derive(Clone)]
 struct FigureInfo {
     figure_id: usize,
@@ -513,7 +520,11 @@ impl Placement {
         res
     }
 
-    pub fn get_potential_group_locations(&self, max_cnt: u32) -> Vec<PotentialGroupLocation> {
+    pub fn get_potential_group_locations(
+        &self,
+        max_cnt: u32,
+        window_size: i32,
+    ) -> Vec<PotentialGroupLocation> {
         for i in 1..self.figures.len() {
             assert_eq!(self.figures[i].comp_id, self.figures[0].comp_id);
         }
@@ -521,15 +532,16 @@ impl Placement {
         let mut res = vec![];
         let locations = self.get_potential_locations(false);
         let (p0, p1) = self.get_bounding_box_points();
-        const WINDOW_SIZE: i32 = 3;
         for x0 in p0.x - 1..p1.x {
             for y0 in p0.y - 1..p1.y {
                 let mut inside = vec![];
                 for loc in locations.iter() {
-                    if loc.pos.x >= x0 && loc.pos.x < x0 + WINDOW_SIZE {
-                        if loc.pos.y >= y0 && loc.pos.y < y0 + WINDOW_SIZE {
-                            inside.push(loc.clone());
-                        }
+                    if loc.pos.x >= x0
+                        && loc.pos.x < x0 + window_size
+                        && loc.pos.y >= y0
+                        && loc.pos.y < y0 + window_size
+                    {
+                        inside.push(loc.clone());
                     }
                 }
                 for mask in 0u32..(1 << inside.len()) {
@@ -540,32 +552,6 @@ impl Placement {
                                 used_inside.push(inside[i].clone());
                             }
                         }
-                        let (p0, p1) = calc_bbox(&used_inside.iter().map(|l| l.pos).collect_vec());
-                        let p0 = p0.shift(-1, -1);
-                        let p1 = p1.shift(1, 1);
-                        let existing_figures = (0..self.figures.len())
-                            .filter_map(|idx| {
-                                let corner = self.get_top_left_corner_by_idx(idx);
-                                if corner.x >= p0.x
-                                    && corner.x <= p1.x
-                                    && corner.y >= p0.y
-                                    && corner.y <= p1.y
-                                {
-                                    Some(self.figures[idx].figure_id)
-                                } else {
-                                    None
-                                }
-                            })
-                            .collect_vec();
-                        let existing_edges = all_neighbours
-                            .iter()
-                            .filter(|(s1, s2)| {
-                                existing_figures.contains(&s1.fig)
-                                    && existing_figures.contains(&s2.fig)
-                                    && s1.fig < s2.fig
-                            })
-                            .cloned()
-                            .collect_vec();
                         let new_figures_edges = calc_new_figures_edges(&used_inside);
                         if new_figures_edges.len() < used_inside.len() - 1 {
                             // checking that everything is connected.
@@ -573,15 +559,7 @@ impl Placement {
                             assert!(max_cnt <= 3);
                             continue;
                         }
-                        // we only care about sides nearby, so we can hash the state
-                        for loc in used_inside.iter_mut() {
-                            loc.pos = Pos { x: 0, y: 0 };
-                        }
-                        let potential_group = PotentialGroupLocation {
-                            locations: used_inside,
-                            existing_edges,
-                            new_figures_edges,
-                        };
+                        let potential_group = self.gen_group_location(used_inside, &all_neighbours);
                         if potential_group.each_new_figure_has_at_least_two_edges()
                         // && potential_group.any_with_two_existing_edges()
                         {
@@ -596,6 +574,134 @@ impl Placement {
         res
     }
 
+    pub fn gen_group_location(
+        &self,
+        mut locations: Vec<PotentialLocation>,
+        all_neighbours: &[(Side, Side)],
+    ) -> PotentialGroupLocation {
+        let (p0, p1) = calc_bbox(&locations.iter().map(|l| l.pos).collect_vec());
+        let p0 = p0.shift(-1, -1);
+        let p1 = p1.shift(1, 1);
+        let existing_figures = (0..self.figures.len())
+            .filter_map(|idx| {
+                let corner = self.get_top_left_corner_by_idx(idx);
+                if corner.x >= p0.x && corner.x <= p1.x && corner.y >= p0.y && corner.y <= p1.y {
+                    Some(self.figures[idx].figure_id)
+                } else {
+                    None
+                }
+            })
+            .collect_vec();
+        let existing_edges = all_neighbours
+            .iter()
+            .filter(|(s1, s2)| {
+                existing_figures.contains(&s1.fig)
+                    && existing_figures.contains(&s2.fig)
+                    && s1.fig < s2.fig
+            })
+            .cloned()
+            .collect_vec();
+        let new_figures_edges = calc_new_figures_edges(&locations);
+        // we only care about sides nearby, so we can hash the state
+        for loc in locations.iter_mut() {
+            loc.pos = Pos { x: 0, y: 0 };
+        }
+        PotentialGroupLocation {
+            locations,
+            existing_edges,
+            new_figures_edges,
+        }
+    }
+
+    // Empty cells, which are completely surrounded by placed figures (or other
+    // empty cells of the same hole). Holes bigger than `max_cells` are skipped.
+    // Cells in the middle of a hole don't touch any figure, so all empty cells inside
+    // the bounding box of each component are checked, not only potential locations.
+    pub fn get_enclosed_holes(&self, max_cells: usize) -> Vec<PotentialGroupLocation> {
+        // cell on the other side of the location side
+        const SIDE_SHIFT: [(i32, i32); 4] = [(-1, 0), (0, 1), (1, 0), (0, -1)];
+
+        let all_neighbours = self.get_all_neighbours();
+        let mut res = vec![];
+        for comp_id in self.get_all_comp_ids() {
+            let mut occupied = BTreeSet::new();
+            let mut neighbors: BTreeMap<Pos, [Option<Side>; 4]> = BTreeMap::new();
+            for idx in 0..self.figures.len() {
+                let fig = &self.figures[idx];
+                if fig.comp_id != comp_id {
+                    continue;
+                }
+                occupied.insert(self.get_top_left_corner_by_idx(idx));
+                for i in 0..4 {
+                    let p2 = fig.positions[i];
+                    let p1 = fig.positions[(i + 1) % 4];
+                    let (corner, side_id) = Self::get_corner_by_side(p1, p2);
+                    neighbors.entry(corner).or_insert([None; 4])[side_id] = Some(Side {
+                        fig: fig.figure_id,
+                        side: i,
+                    });
+                }
+            }
+            let (p0, p1) = calc_bbox(&occupied.iter().cloned().collect_vec());
+            let inside_bbox = |p: Pos| p.x >= p0.x && p.x <= p1.x && p.y >= p0.y && p.y <= p1.y;
+            let mut used = BTreeSet::new();
+            for x in p0.x..=p1.x {
+                for y in p0.y..=p1.y {
+                    let start = Pos::new(x, y);
+                    if occupied.contains(&start) || used.contains(&start) {
+                        continue;
+                    }
+                    used.insert(start);
+                    let mut hole = vec![start];
+                    let mut enclosed = true;
+                    let mut it = 0;
+                    while it < hole.len() {
+                        let pos = hole[it];
+                        it += 1;
+                        for (dx, dy) in SIDE_SHIFT {
+                            let next_pos = pos.shift(dx, dy);
+                            if !inside_bbox(next_pos) {
+                                enclosed = false;
+                            } else if !occupied.contains(&next_pos) && used.insert(next_pos) {
+                                hole.push(next_pos);
+                            }
+                        }
+                    }
+                    if enclosed && hole.len() <= max_cells {
+                        let hole = hole
+                            .into_iter()
+                            .map(|pos| PotentialLocation {
+                                neighbors: neighbors.get(&pos).cloned().unwrap_or([None; 4]),
+                                pos,
+                            })
+                            .collect_vec();
+                        res.push(self.gen_group_location(hole, &all_neighbours));
+                    }
+                }
+            }
+        }
+        res
+    }
+
+    // Grid points, where four figures meet. For each figure returns its id and index
+    // of the corner (same as in `Figure::corner_positions`).
+    pub fn get_2x2_corners(&self) -> Vec<[(usize, usize); 4]> {
+        // different components have independent coordinates
+        let mut by_point: BTreeMap<(usize, Pos), Vec<(usize, usize)>> = BTreeMap::new();
+        for fig in self.figures.iter() {
+            for (corner, pos) in fig.positions.iter().enumerate() {
+                by_point
+                    .entry((fig.comp_id, *pos))
+                    .or_default()
+                    .push((fig.figure_id, corner));
+            }
+        }
+        by_point
+            .into_values()
+            .filter_map(|corners| corners.try_into().ok())
+            .collect()
+    }
+
     pub fn get_figure_by_position(&self, top_left: Pos) -> Option<[Side; 4]> {
         for (idx, fig) in self.figures.iter().enumerate() {
             if self.get_top_left_corner_by_idx(idx) == top_left {
@@ -617,6 +723,38 @@ impl Placement {
         None
     }
 
+    // For each component: cells of its figures, counted from the top left corner of the
+    // component.
+    pub fn get_cells_by_component(&self) -> Vec<Vec<PlacedCell>> {
+        let mut res = vec![];
+        for comp_id in self.get_all_comp_ids() {
+            let cells = (0..self.figures.len())
+                .filter(|&idx| self.figures[idx].comp_id == comp_id)
+                .map(|idx| {
+                    // can't use `get_figure_by_position`, other components could have
+                    // a figure in the same position
+                    let fig = &self.figures[idx];
+                    let top_left = self.get_top_left_corner_by_idx(idx);
+                    let offset = fig.positions.iter().position(|p| *p == top_left).unwrap();
+                    let sides = [0, 1, 2, 3].map(|i| Side {
+                        fig: fig.figure_id,
+                        side: (offset + i) % 4,
+                    });
+                    (top_left, sides)
+                })
+                .collect_vec();
+            let min_x = cells.iter().map(|(p, _)| p.x).min().unwrap();
+            let min_y = cells.iter().map(|(p, _)| p.y).min().unwrap();
+            res.push(
+                cells
+                    .into_iter()
+                    .map(|(p, sides)| (((p.x - min_x) as usize, (p.y - min_y) as usize), sides))
+                    .collect_vec(),
+            );
+        }
+        res
+    }
+
     pub fn get_all_used_figures(&self) -> Vec<usize> {
         self.figures.iter().map(|f| f.figure_id).collect_vec()
     }
@@ -634,6 +772,99 @@ impl Placement {
         }
     }
 
+    // Pins are absolute cells, but placement is only known up to rotation and shift.
+    // Each component should have some rotation and shift which moves all its pinned
+    // figures to their cells and keeps the whole component inside the board.
+    // Board is (rows, cols).
+    pub fn is_consistent_with_anchors(
+        &self,
+        pins: &[(usize, Pos)],
+        board: Option<(i32, i32)>,
+    ) -> bool {
+        for comp_id in self.get_all_comp_ids() {
+            let comp_figures = self
+                .figures
+                .iter()
+                .filter(|f| f.comp_id == comp_id)
+                .collect_vec();
+            let comp_pins = pins
+                .iter()
+                .filter_map(|&(fig, cell)| {
+                    let idx = self.get_fig_index(fig)?;
+                    if self.figures[idx].comp_id == comp_id {
+                        Some((self.figures[idx].positions, cell))
+                    } else {
+                        None
+                    }
+                })
+                .collect_vec();
+            if comp_pins.len() <= 1 && board.is_none() {
+                continue;
+            }
+            let mut ok = false;
+            for rotations in 0..4 {
+                let rotate = |p: Pos| (0..rotations).fold(p, |p, _| p.rotate());
+                let corner =
+                    |positions: &[Pos; 4]| positions.iter().map(|&p| rotate(p)).min().unwrap();
+                let all_pos = comp_figures
+                    .iter()
+                    .flat_map(|f| f.positions.iter())
+                    .map(|&p| rotate(p))
+                    .collect_vec();
+                let (bb_min, bb_max) = calc_bbox(&all_pos);
+                let shift = match comp_pins.first() {
+                    Some((positions, cell)) => *cell - corner(positions),
+                    None => Pos::new(0, 0) - bb_min,
+                };
+                if !comp_pins
+                    .iter()
+                    .all(|(positions, cell)| corner(positions) + shift == *cell)
+                {
+                    continue;
+                }
+                if let Some((rows, cols)) = board {
+                    let (bb_min, bb_max) = (bb_min + shift, bb_max + shift);
+                    if bb_min.x < 0 || bb_min.y < 0 || bb_max.x > rows || bb_max.y > cols {
+                        continue;
+                    }
+                }
+                ok = true;
+                break;
+            }
+            if !ok {
+                return false;
+            }
+        }
+        true
+    }
+
+    // Number of figures which are in the same cell with the same rotation as in `other`.
+    // Each component is compared after its best rotation and shift.
+    pub fn count_same_cells(&self, other: &Self) -> usize {
+        let mut res = 0;
+        for comp_id in self.get_all_comp_ids() {
+            let mut best = 0;
+            for rotations in 0..4 {
+                let rotate = |p: Pos| (0..rotations).fold(p, |p, _| p.rotate());
+                let mut shifts: BTreeMap<Pos, usize> = BTreeMap::new();
+                for f in self.figures.iter().filter(|f| f.comp_id == comp_id) {
+                    let other_idx = match other.get_fig_index(f.figure_id) {
+                        Some(idx) => idx,
+                        None => continue,
+                    };
+                    let other_positions = other.figures[other_idx].positions;
+                    let shift = other_positions[0] - rotate(f.positions[0]);
+                    if (0..4).all(|i| rotate(f.positions[i]) + shift == other_positions[i]) {
+                        *shifts.entry(shift).or_default() += 1;
+                    }
+                }
+                best = shifts.values().cloned().max().unwrap_or(0).max(best);
+            }
+            res += best;
+        }
+        res
+    }
+
     pub fn join_with(&mut self, another: &Self) -> bool {
         for (s1, s2) in another.get_all_neighbours() {
             if self.join_sides(s1, s2).is_none() {
@@ -659,8 +890,8 @@ pub struct PotentialLocation {
 
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct NewFigureEdge {
-    location_ids: [usize; 2],
-    sides: [usize; 2],
+    pub location_ids: [usize; 2],
+    pub sides: [usize; 2],
 }
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
@@ -790,7 +1021,10 @@ impl Search3StateWithScore {
         self.state.get_way_edges(&self.first_sides)
     }
 
-    pub fn get_key(&self) -> &PotentialGroupLocation {
-        &self.state
+    // states filling the same group of cells have the same group hash
+    pub fn get_group_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.state.hash(&mut hasher);
+        hasher.finish()
     }
 }
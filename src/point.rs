@@ -9,8 +9,8 @@ pub struct Point {
 
 impl Point {
     pub fn dist2(&self, other: &Point) -> usize {
-        let dx = self.x - other.x;
-        let dy = self.y - other.y;
+        let dx = self.x.abs_diff(other.x);
+        let dy = self.y.abs_diff(other.y);
         dx * dx + dy * dy
     }
 
@@ -0,0 +1,319 @@
+use std::collections::{BTreeMap, HashMap};
+
+use itertools::Itertools;
+
+use crate::{
+    config::SurfaceConfig,
+    parsed_puzzles::ParsedPuzzles,
+    point::{find_center, PointF},
+    surface_placer::get_border,
+    utils::Side,
+};
+
+// Rigid move of one figure: rotation around its center, then shift.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pose {
+    pub angle: f64,
+    pub shift: PointF,
+}
+
+impl Pose {
+    pub const ZERO: Self = Self {
+        angle: 0.0,
+        shift: PointF::ZERO,
+    };
+
+    pub fn apply(&self, center: PointF, p: PointF) -> PointF {
+        center + (p - center).rotate(self.angle) + self.shift
+    }
+
+    // derivative of `apply` by angle
+    fn d_angle(&self, center: PointF, p: PointF) -> PointF {
+        let q = (p - center).rotate(self.angle);
+        PointF { x: q.y, y: -q.x }
+    }
+}
+
+// Figure id of points, which never move, e.g. targets of priors.
+pub const ANCHOR: usize = usize::MAX;
+
+// Point `p1` of `fig1` should be at the same place as point `p2` of `fig2`.
+#[derive(Clone, Copy, Debug)]
+pub struct Correspondence {
+    pub fig1: usize,
+    pub p1: PointF,
+    pub fig2: usize,
+    pub p2: PointF,
+    pub weight: f64,
+}
+
+// Evenly spaced points of each border are paired with the closest points of the other one.
+pub fn gen_correspondences(
+    parsed_puzzles: &ParsedPuzzles,
+    edges: &[(Side, Side)],
+    positions: &[Option<Vec<PointF>>],
+    samples: usize,
+) -> Vec<Correspondence> {
+    let mut res = vec![];
+    for &(s1, s2) in edges.iter() {
+        let (pos1, pos2) = match (&positions[s1.fig], &positions[s2.fig]) {
+            (Some(pos1), Some(pos2)) => (pos1, pos2),
+            _ => continue,
+        };
+        let border1 = get_border(pos1, parsed_puzzles, s1);
+        let border2 = get_border(pos2, parsed_puzzles, s2);
+        for (from, to, fig1, fig2) in [
+            (&border1, &border2, s1.fig, s2.fig),
+            (&border2, &border1, s2.fig, s1.fig),
+        ] {
+            if from.len() < 2 || to.is_empty() {
+                continue;
+            }
+            for k in 0..samples {
+                let p1 = from[k * (from.len() - 1) / (samples - 1).max(1)];
+                let p2 = *to
+                    .iter()
+                    .min_by(|a, b| a.dist2(&p1).total_cmp(&b.dist2(&p1)))
+                    .unwrap();
+                res.push(Correspondence {
+                    fig1,
+                    p1,
+                    fig2,
+                    p2,
+                    weight: 1.0,
+                });
+            }
+        }
+    }
+    res
+}
+
+// Sparse J^T J and J^T r. Each figure has 3 variables: angle, shift.x, shift.y.
+struct NormalEquations {
+    matrix: HashMap<(usize, usize), f64>,
+    rhs: Vec<f64>,
+}
+
+impl NormalEquations {
+    fn new(n: usize) -> Self {
+        Self {
+            matrix: HashMap::new(),
+            rhs: vec![0.0; n],
+        }
+    }
+
+    // `jac` - derivatives of the 2d residual `r` by variables
+    fn add_residual(&mut self, jac: &[(usize, PointF)], r: PointF) {
+        for &(i, ji) in jac.iter() {
+            self.rhs[i] += ji.scal_mul(&r);
+            for &(j, jj) in jac.iter() {
+                *self.matrix.entry((i, j)).or_default() += ji.scal_mul(&jj);
+            }
+        }
+    }
+
+    // (H + lambda * diag(H)) x = -rhs, preconditioned conjugate gradient
+    fn solve(&self, lambda: f64, max_iters: usize) -> Vec<f64> {
+        const EPS: f64 = 1e-9;
+        let n = self.rhs.len();
+        let entries = self
+            .matrix
+            .iter()
+            .map(|(&(i, j), &v)| (i, j, v))
+            .collect_vec();
+        let mut diag = vec![EPS; n];
+        for &(i, j, v) in entries.iter() {
+            if i == j {
+                diag[i] += v * (1.0 + lambda);
+            }
+        }
+        let mul = |x: &[f64]| -> Vec<f64> {
+            let mut res = vec![0.0; n];
+            for &(i, j, v) in entries.iter() {
+                res[i] += v * x[j];
+            }
+            for i in 0..n {
+                let orig = self.matrix.get(&(i, i)).cloned().unwrap_or(0.0);
+                res[i] += (diag[i] - orig) * x[i];
+            }
+            res
+        };
+        let dot = |a: &[f64], b: &[f64]| a.iter().zip(b.iter()).map(|(x, y)| x * y).sum::<f64>();
+
+        let mut x = vec![0.0; n];
+        let mut r = self.rhs.iter().map(|v| -v).collect_vec();
+        let mut z = (0..n).map(|i| r[i] / diag[i]).collect_vec();
+        let mut p = z.clone();
+        let mut rz = dot(&r, &z);
+        let start_norm = dot(&r, &r);
+        for _ in 0..max_iters {
+            if dot(&r, &r) <= start_norm * 1e-12 {
+                break;
+            }
+            let ap = mul(&p);
+            let alpha = rz / dot(&p, &ap);
+            for i in 0..n {
+                x[i] += alpha * p[i];
+                r[i] -= alpha * ap[i];
+            }
+            z = (0..n).map(|i| r[i] / diag[i]).collect_vec();
+            let new_rz = dot(&r, &z);
+            for i in 0..n {
+                p[i] = z[i] + (new_rz / rz) * p[i];
+            }
+            rz = new_rz;
+        }
+        x
+    }
+}
+
+fn calc_cost(
+    correspondences: &[Correspondence],
+    centers: &BTreeMap<usize, PointF>,
+    poses: &BTreeMap<usize, Pose>,
+) -> f64 {
+    let moved = |fig: usize, p: PointF| match poses.get(&fig) {
+        Some(pose) => pose.apply(centers[&fig], p),
+        None => p,
+    };
+    correspondences
+        .iter()
+        .map(|c| moved(c.fig1, c.p1).dist2(&moved(c.fig2, c.p2)) * c.weight)
+        .sum()
+}
+
+// Levenberg-Marquardt over poses of all figures except `fixed` ones, correspondences
+// are not changed. Returns poses relative to current positions.
+pub fn fit_poses(
+    correspondences: &[Correspondence],
+    centers: &BTreeMap<usize, PointF>,
+    fixed: &[usize],
+    max_steps: usize,
+) -> BTreeMap<usize, Pose> {
+    const CG_ITERS: usize = 300;
+    const MAX_TRIES: usize = 10;
+
+    let var_ids: BTreeMap<usize, usize> = correspondences
+        .iter()
+        .flat_map(|c| [c.fig1, c.fig2])
+        .filter(|fig| *fig != ANCHOR && !fixed.contains(fig))
+        .sorted()
+        .dedup()
+        .enumerate()
+        .map(|(id, fig)| (fig, id))
+        .collect();
+    let mut poses: BTreeMap<usize, Pose> = var_ids.keys().map(|&fig| (fig, Pose::ZERO)).collect();
+    let mut cost = calc_cost(correspondences, centers, &poses);
+    let mut lambda = 1e-3;
+    for _step in 0..max_steps {
+        let mut equations = NormalEquations::new(var_ids.len() * 3);
+        for c in correspondences.iter() {
+            let mut jac = vec![];
+            let mut moved = [c.p1, c.p2];
+            let w = c.weight.sqrt();
+            for (k, (fig, p, sign)) in [(c.fig1, c.p1, w), (c.fig2, c.p2, -w)]
+                .into_iter()
+                .enumerate()
+            {
+                if let Some(&id) = var_ids.get(&fig) {
+                    let pose = poses[&fig];
+                    moved[k] = pose.apply(centers[&fig], p);
+                    jac.push((id * 3, pose.d_angle(centers[&fig], p) * sign));
+                    jac.push((id * 3 + 1, PointF { x: sign, y: 0.0 }));
+                    jac.push((id * 3 + 2, PointF { x: 0.0, y: sign }));
+                }
+            }
+            equations.add_residual(&jac, (moved[0] - moved[1]) * w);
+        }
+        let mut improved = false;
+        for _try in 0..MAX_TRIES {
+            let delta = equations.solve(lambda, CG_ITERS);
+            let check = poses
+                .iter()
+                .map(|(&fig, pose)| {
+                    let id = var_ids[&fig];
+                    let pose = Pose {
+                        angle: pose.angle + delta[id * 3],
+                        shift: pose.shift
+                            + PointF {
+                                x: delta[id * 3 + 1],
+                                y: delta[id * 3 + 2],
+                            },
+                    };
+                    (fig, pose)
+                })
+                .collect();
+            let new_cost = calc_cost(correspondences, centers, &check);
+            if new_cost < cost {
+                improved = cost - new_cost > cost * 1e-6;
+                poses = check;
+                cost = new_cost;
+                lambda /= 3.0;
+                break;
+            }
+            lambda *= 5.0;
+        }
+        if !improved {
+            break;
+        }
+    }
+    poses
+}
+
+// weights are ignored
+pub fn rms(correspondences: &[Correspondence]) -> f64 {
+    if correspondences.is_empty() {
+        return 0.0;
+    }
+    let sum: f64 = correspondences.iter().map(|c| c.p1.dist2(&c.p2)).sum();
+    (sum / correspondences.len() as f64).sqrt()
+}
+
+// Moves figures of `edges` so matched borders are as close as possible. Correspondences
+// are rebuilt after each fit, like in ICP, `prior` ones are added to them.
+// Returns RMS distance between matched borders.
+pub fn refine_poses(
+    parsed_puzzles: &ParsedPuzzles,
+    edges: &[(Side, Side)],
+    positions: &mut [Option<Vec<PointF>>],
+    fixed: &[usize],
+    config: &SurfaceConfig,
+    prior: impl Fn(&[Option<Vec<PointF>>]) -> Vec<Correspondence>,
+) -> f64 {
+    const STEPS_PER_FIT: usize = 5;
+
+    let mut correspondences =
+        gen_correspondences(parsed_puzzles, edges, positions, config.pose_samples);
+    let start_rms = rms(&correspondences);
+    for _iter in 0..config.pose_iters {
+        let mut all_correspondences = correspondences.clone();
+        all_correspondences.extend(prior(positions));
+        let centers: BTreeMap<usize, PointF> = all_correspondences
+            .iter()
+            .flat_map(|c| [c.fig1, c.fig2])
+            .filter(|&fig| fig != ANCHOR)
+            .sorted()
+            .dedup()
+            .map(|fig| (fig, find_center(positions[fig].as_ref().unwrap())))
+            .collect();
+        let poses = fit_poses(&all_correspondences, &centers, fixed, STEPS_PER_FIT);
+        let before = rms(&correspondences);
+        for (&fig, pose) in poses.iter() {
+            for p in positions[fig].as_mut().unwrap().iter_mut() {
+                *p = pose.apply(centers[&fig], *p);
+            }
+        }
+        correspondences =
+            gen_correspondences(parsed_puzzles, edges, positions, config.pose_samples);
+        let after = rms(&correspondences);
+        if before - after < config.pose_min_improvement {
+            break;
+        }
+    }
+    let final_rms = rms(&correspondences);
+    eprintln!(
+        "Pose refinement of {} edges: rms {start_rms:.3} -> {final_rms:.3}",
+        edges.len()
+    );
+    final_rms
+}
@@ -0,0 +1,48 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use crate::{
+        point::{find_center, PointF},
+        pose_refinement::{fit_poses, Correspondence, Pose},
+    };
+
+    #[test]
+    pub fn recover_rigid_move() {
+        let pts = (0..20)
+            .map(|i| PointF {
+                x: (i as f64 * 0.7).cos() * 30.0 + i as f64,
+                y: (i as f64 * 1.3).sin() * 20.0,
+            })
+            .collect::<Vec<_>>();
+        let center = find_center(&pts);
+        let moved_by = Pose {
+            angle: 0.2,
+            shift: PointF { x: 5.0, y: -3.0 },
+        };
+        let moved = pts
+            .iter()
+            .map(|&p| moved_by.apply(center, p))
+            .collect::<Vec<_>>();
+        let correspondences = pts
+            .iter()
+            .zip(moved.iter())
+            .map(|(&p1, &p2)| Correspondence {
+                fig1: 0,
+                p1,
+                fig2: 1,
+                p2,
+                weight: 1.0,
+            })
+            .collect::<Vec<_>>();
+        let centers = BTreeMap::from([(0, center), (1, find_center(&moved))]);
+
+        let poses = fit_poses(&correspondences, &centers, &[0], 20);
+        assert!(!poses.contains_key(&0));
+        let pose = poses[&1];
+        assert!((pose.angle + 0.2).abs() < 1e-6, "{pose:?}");
+        for (&p, &q) in pts.iter().zip(moved.iter()) {
+            assert!(pose.apply(centers[&1], q).dist2(&p) < 1e-6);
+        }
+    }
+}
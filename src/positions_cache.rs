@@ -7,7 +7,12 @@ use ndarray::Array4;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    parsed_puzzles::ParsedPuzzles, point::PointF, surface_placer::place_one_connected_component,
+    bundle_adjustment::{adjust_placement, AdjustmentReport},
+    config::Config,
+    parsed_puzzles::ParsedPuzzles,
+    placement::Placement,
+    point::PointF,
+    surface_placer::place_one_connected_component,
     utils::Side,
 };
 
@@ -16,16 +21,17 @@ pub struct PositionsCacheData {
     pts: Vec<Option<Vec<PointF>>>,
 }
 
+// Clones share the cached positions.
+#[derive(Clone)]
 pub struct PositionsCache {
     data: Arc<Mutex<PositionsCacheData>>,
+    config: Config,
 }
 
 const DEFAULT_PATH: &str = "positions_cache.json";
 
-const CACHE_GRAPH_SIZE: usize = 26;
-
 impl PositionsCache {
-    pub fn load(parsed_puzzles: &ParsedPuzzles) -> Self {
+    pub fn load(parsed_puzzles: &ParsedPuzzles, config: &Config) -> Self {
         let data = if let Ok(content) = fs::read_to_string(DEFAULT_PATH) {
             serde_json::from_str(&content).unwrap()
         } else {
@@ -35,9 +41,14 @@ impl PositionsCache {
         };
         Self {
             data: Arc::new(Mutex::new(data)),
+            config: config.clone(),
         }
     }
 
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
     fn save(data: &PositionsCacheData) {
         fs::write(DEFAULT_PATH, serde_json::to_string(data).unwrap()).unwrap();
     }
@@ -50,13 +61,14 @@ impl PositionsCache {
         positions: &mut Vec<Option<Vec<PointF>>>,
         base_points_matrix: &Array4<[PointF; 2]>,
     ) -> Option<f64> {
-        if component.len() < CACHE_GRAPH_SIZE {
+        if component.len() < self.config.surface.cache_component {
             return place_one_connected_component(
                 parsed_puzzles,
                 component,
                 used_edges,
                 positions,
                 base_points_matrix,
+                &self.config,
             );
         }
         let mut data = self.data.lock().unwrap();
@@ -70,6 +82,7 @@ impl PositionsCache {
             used_edges,
             positions,
             base_points_matrix,
+            &self.config,
         );
         for &fig in component.iter() {
             data.pts[fig] = positions[fig].clone();
@@ -77,4 +90,39 @@ impl PositionsCache {
         Self::save(&data);
         res
     }
+
+    // All figures of the placement are moved together, so the drift of incremental
+    // placement is removed. Figures, which are not cached yet, are placed first.
+    pub fn global_refine(
+        &self,
+        parsed_puzzles: &ParsedPuzzles,
+        placement: &Placement,
+        base_points_matrix: &Array4<[PointF; 2]>,
+    ) -> Option<AdjustmentReport> {
+        let component = placement.get_all_used_figures();
+        let mut data = self.data.lock().unwrap();
+        let mut positions = vec![None; parsed_puzzles.figures.len()];
+        for &fig in component.iter() {
+            positions[fig] = data.pts[fig].clone();
+        }
+        place_one_connected_component(
+            parsed_puzzles,
+            &component,
+            &placement.get_all_neighbours(),
+            &mut positions,
+            base_points_matrix,
+            &self.config,
+        )?;
+        let report = adjust_placement(
+            parsed_puzzles,
+            placement,
+            &mut positions,
+            &self.config.surface,
+        );
+        for &fig in component.iter() {
+            data.pts[fig] = positions[fig].clone();
+        }
+        Self::save(&data);
+        Some(report)
+    }
 }
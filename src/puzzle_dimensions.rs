@@ -0,0 +1,101 @@
+use std::cmp::max;
+
+use itertools::Itertools;
+
+use crate::{known_facts::KnownFacts, parsed_puzzles::ParsedPuzzles};
+
+#[derive(Clone, Copy, Debug)]
+pub struct DimensionsCandidate {
+    pub rows: i32,
+    pub cols: i32,
+    // smaller is better
+    pub score: f64,
+}
+
+impl DimensionsCandidate {
+    pub fn cnt_pieces(&self) -> i32 {
+        self.rows * self.cols
+    }
+
+    pub fn cnt_border_pieces(&self) -> i32 {
+        2 * (self.rows + self.cols) - 4
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PiecesStats {
+    pub cnt_pieces: usize,
+    pub cnt_border: usize,
+    pub cnt_corners: usize,
+}
+
+impl PiecesStats {
+    pub fn new(parsed_puzzles: &ParsedPuzzles, known_facts: &KnownFacts) -> Self {
+        let cnt_pieces = (0..parsed_puzzles.figures.len())
+            .filter(|&fig| {
+                parsed_puzzles.figures[fig].is_good_puzzle() && !known_facts.is_excluded(fig)
+            })
+            .count();
+        let on_border = parsed_puzzles.calc_figures_on_border_with_facts(known_facts);
+        Self {
+            cnt_pieces,
+            cnt_border: on_border.len(),
+            cnt_corners: on_border.iter().filter(|f| f.is_corner()).count(),
+        }
+    }
+}
+
+// Parsing is not perfect: some pieces could be lost or not detected as border ones,
+// so we don't require exact match, just sort all shapes by relative error.
+pub fn infer_dimensions(stats: &PiecesStats) -> Vec<DimensionsCandidate> {
+    const MAX_CANDIDATES: usize = 5;
+    const MAX_ERROR: f64 = 0.3;
+    // pieces are rarely very long
+    const MAX_ASPECT_RATIO: i32 = 4;
+
+    let cnt_pieces = max(stats.cnt_pieces, 1) as f64;
+    let cnt_border = max(stats.cnt_border, 1) as f64;
+    let mut res = vec![];
+    for rows in 2.. {
+        if rows * rows > (cnt_pieces * (1.0 + MAX_ERROR)) as i32 {
+            break;
+        }
+        for cols in rows..=rows * MAX_ASPECT_RATIO {
+            let mut candidate = DimensionsCandidate {
+                rows,
+                cols,
+                score: 0.0,
+            };
+            let pieces_error = (candidate.cnt_pieces() as f64 - cnt_pieces).abs() / cnt_pieces;
+            let border_error =
+                (candidate.cnt_border_pieces() as f64 - cnt_border).abs() / cnt_border;
+            if pieces_error > MAX_ERROR || border_error > MAX_ERROR {
+                continue;
+            }
+            candidate.score = pieces_error + border_error;
+            res.push(candidate);
+        }
+    }
+    res.sort_by(|c1, c2| c1.score.total_cmp(&c2.score));
+    res.truncate(MAX_CANDIDATES);
+    res
+}
+
+pub fn print_dimensions_candidates(parsed_puzzles: &ParsedPuzzles, known_facts: &KnownFacts) {
+    let stats = PiecesStats::new(parsed_puzzles, known_facts);
+    eprintln!(
+        "Pieces: {}, on border: {}, corners: {}",
+        stats.cnt_pieces, stats.cnt_border, stats.cnt_corners
+    );
+    if stats.cnt_corners != 4 {
+        eprintln!("Expected 4 corners, border detection is probably wrong");
+    }
+    let candidates = infer_dimensions(&stats);
+    eprintln!(
+        "Candidates: {}",
+        candidates
+            .iter()
+            .map(|c| format!("{}x{} ({:.3})", c.rows, c.cols, c.score))
+            .join(", ")
+    );
+}
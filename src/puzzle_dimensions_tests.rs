@@ -0,0 +1,40 @@
+#[cfg(test)]
+mod tests {
+    use crate::puzzle_dimensions::{infer_dimensions, PiecesStats};
+
+    fn stats(cnt_pieces: usize, cnt_border: usize) -> PiecesStats {
+        PiecesStats {
+            cnt_pieces,
+            cnt_border,
+            cnt_corners: 4,
+        }
+    }
+
+    fn shapes(stats: &PiecesStats) -> Vec<(i32, i32)> {
+        infer_dimensions(stats)
+            .iter()
+            .map(|c| (c.rows, c.cols))
+            .collect()
+    }
+
+    #[test]
+    pub fn exact_counts() {
+        assert_eq!(shapes(&stats(600, 96))[0], (20, 30));
+        assert_eq!(shapes(&stats(100, 36))[0], (10, 10));
+        assert_eq!(shapes(&stats(12, 10))[0], (3, 4));
+        let candidates = infer_dimensions(&stats(600, 96));
+        assert_eq!(candidates[0].score, 0.0);
+        assert!(candidates.windows(2).all(|w| w[0].score <= w[1].score));
+    }
+
+    #[test]
+    pub fn lost_pieces() {
+        // one inner piece was not parsed
+        let res = shapes(&stats(599, 96));
+        assert_eq!(res[0], (20, 30), "{res:?}");
+        assert!(res.iter().all(|&(rows, cols)| rows <= cols));
+        // border doesn't match anything
+        assert!(shapes(&stats(600, 20)).is_empty());
+        assert!(shapes(&stats(0, 0)).is_empty());
+    }
+}
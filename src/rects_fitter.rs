@@ -1,31 +1,17 @@
 use crate::{point::PointF, utils::fmax};
 
-#[derive(Clone)]
-struct Rect {
-    start: PointF,
-    end: PointF,
-}
-
-impl Rect {
-    pub fn intersects(&self, other: &Self) -> bool {
-        if self.start.x >= other.end.x || self.end.x <= other.start.x {
-            return false;
-        }
-        if other.start.x >= self.end.x || other.end.x <= self.start.x {
-            return false;
-        }
-        if self.start.y >= other.end.y || self.end.y <= other.start.y {
-            return false;
-        }
-        if other.start.y >= self.end.y || other.end.y <= self.start.y {
-            return false;
-        }
-        true
-    }
+#[derive(Clone, Copy)]
+struct Shelf {
+    y: f64,
+    height: f64,
+    used_width: f64,
 }
 
+// Each rect is put into the first shelf, where it fits, or into a new shelf below all others.
+// Width of shelves is limited by the square root of the total area, so the result is squarish.
 pub struct RectsFitter {
-    rects: Vec<Rect>,
+    shelves: Vec<Shelf>,
+    total_area: f64,
 }
 
 const OFFSET: f64 = 20.0;
@@ -53,40 +39,39 @@ pub fn get_bounding_box(pts: &[PointF]) -> (PointF, PointF) {
 impl RectsFitter {
     pub fn new() -> Self {
         Self {
-            rects: vec![Rect {
-                start: PointF::ZERO,
-                end: PointF::ZERO,
-            }],
+            shelves: vec![],
+            total_area: 0.0,
         }
     }
 
     // returns shift, which we need to apply to points
     pub fn add_points(&mut self, pts: &[PointF]) -> PointF {
         let (cur_start, cur_end) = get_bounding_box(pts);
-        let mut options = vec![];
-        for x in self.rects.iter().map(|r| r.end.x) {
-            for y in self.rects.iter().map(|r| r.end.y) {
-                options.push(PointF { x, y });
-            }
-        }
-        options.sort_by(|p1, p2| fmax(p1.x, p1.y).total_cmp(&fmax(p2.x, p2.y)));
-        for &start in options.iter() {
-            let mut ok = true;
-            let rect_to_check = Rect {
-                start,
-                end: start + (cur_end - cur_start),
-            };
-            for r in self.rects.iter() {
-                if r.intersects(&rect_to_check) {
-                    ok = false;
-                    break;
-                }
+        let size = cur_end - cur_start;
+        self.total_area += size.x * size.y;
+        let max_width = fmax(self.total_area.sqrt(), size.x);
+        let shelf_id = match self
+            .shelves
+            .iter()
+            .position(|s| size.y <= s.height && s.used_width + size.x <= max_width)
+        {
+            Some(id) => id,
+            None => {
+                let y = self.shelves.last().map(|s| s.y + s.height).unwrap_or(0.0);
+                self.shelves.push(Shelf {
+                    y,
+                    height: size.y,
+                    used_width: 0.0,
+                });
+                self.shelves.len() - 1
             }
-            if ok {
-                self.rects.push(rect_to_check.clone());
-                return rect_to_check.start - cur_start;
-            }
-        }
-        unreachable!("Should have found position");
+        };
+        let shelf = &mut self.shelves[shelf_id];
+        let start = PointF {
+            x: shelf.used_width,
+            y: shelf.y,
+        };
+        shelf.used_width += size.x;
+        start - cur_start
     }
 }
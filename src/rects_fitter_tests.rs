@@ -0,0 +1,49 @@
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use crate::{
+        point::PointF,
+        rects_fitter::{get_bounding_box, RectsFitter},
+    };
+
+    #[test]
+    pub fn rects_do_not_intersect() {
+        let mut rng = StdRng::seed_from_u64(787788);
+        let mut rects_fitter = RectsFitter::new();
+        let mut placed: Vec<(PointF, PointF)> = vec![];
+        let mut total_area = 0.0;
+        for _ in 0..100 {
+            let start = PointF {
+                x: rng.gen_range(-500.0..500.0),
+                y: rng.gen_range(-500.0..500.0),
+            };
+            let size = PointF {
+                x: rng.gen_range(10.0..300.0),
+                y: rng.gen_range(10.0..300.0),
+            };
+            let pts = [start, start + size];
+            let shift = rects_fitter.add_points(&pts);
+            let (p0, p1) = get_bounding_box(&pts);
+            let rect = (p0 + shift, p1 + shift);
+            assert!(rect.0.x > -1e-6 && rect.0.y > -1e-6);
+            for other in placed.iter() {
+                const EPS: f64 = 1e-6;
+                let intersects = rect.0.x + EPS < other.1.x
+                    && other.0.x + EPS < rect.1.x
+                    && rect.0.y + EPS < other.1.y
+                    && other.0.y + EPS < rect.1.y;
+                assert!(!intersects, "{rect:?} {other:?}");
+            }
+            total_area += (p1.x - p0.x) * (p1.y - p0.y);
+            placed.push(rect);
+        }
+        let max_x = placed.iter().map(|r| r.1.x).fold(0.0, f64::max);
+        let max_y = placed.iter().map(|r| r.1.y).fold(0.0, f64::max);
+        assert!(max_x * max_y < total_area * 2.0, "{max_x} x {max_y}");
+        assert!(
+            max_x < max_y * 2.0 && max_y < max_x * 2.0,
+            "{max_x} x {max_y}"
+        );
+    }
+}
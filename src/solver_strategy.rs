@@ -0,0 +1,258 @@
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    borders_graph::Graph,
+    config::Config,
+    frame_solver::solve_frame,
+    graph_solver::{
+        build_solutions_picker, solve_graph, solve_graph_add_by_3, solve_graph_assignment,
+        solve_local_improvement, CandidatePlacement,
+    },
+    interactive_solutions_picker::InteractiveSolutionPicker,
+    known_facts::KnownFacts,
+    parsed_puzzles::ParsedPuzzles,
+    solver_worker::ProgressSink,
+};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct SolverConfig {
+    // candidates after ranking, the rest is dropped
+    pub max_candidates: usize,
+    // add_by_3: groups of up to `group_max_cnt` figures inside `group_window` x `group_window`
+    pub group_max_cnt: u32,
+    pub group_window: i32,
+    // add_by_3: closest sides, which are checked for each side, and max cost of them
+    pub check_best: usize,
+    pub too_big_cost: f64,
+    // add_by_3: states checked and kept for each start state
+    pub limit: usize,
+    pub limit_res: usize,
+    // add_by_3: holes with more cells are not solved exactly
+    pub max_hole_cells: usize,
+    // assignment: most confident slots, which are proposed one by one
+    pub max_single: usize,
+    // ranking: multiplier of the worst spread of new 2x2 corners, added to the placement
+    // score. Spread is relative to the side length (0.2 is already a bad corner), so it
+    // only reorders candidates with close placement scores.
+    pub closure_weight: f64,
+    // ranking: multiplier of the overlap of new figures with the known ones
+    pub overlap_weight: f64,
+    // relative to the figure area, neighbours always overlap a bit because of imperfect
+    // borders, smaller overlaps are ignored
+    pub ok_overlap: f64,
+    // candidates, where a new figure overlaps others more, are rejected
+    pub max_overlap: f64,
+    // figure of the known component, which is extended by solvers. Depends on the puzzle,
+    // for synthetic ones any figure of the ground truth works.
+    pub start_vertex: usize,
+    // solve_graph: max cost of an edge, multiplied by the value for the number of
+    // sides the new figure touches (index is the number of sides)
+    pub max_dist: f64,
+    pub max_dist_multipliers: [f64; 5],
+    // solve_graph: states kept for each number of figures
+    pub max_cnt: usize,
+}
+
+impl Default for SolverConfig {
+    fn default() -> Self {
+        Self {
+            max_candidates: 1000,
+            group_max_cnt: 3,
+            group_window: 3,
+            check_best: 500,
+            too_big_cost: 100.0,
+            limit: 250,
+            limit_res: 50,
+            max_hole_cells: 9,
+            max_single: 30,
+            closure_weight: 1.0,
+            overlap_weight: 10.0,
+            ok_overlap: 0.03,
+            max_overlap: 0.2,
+            start_vertex: 628,
+            max_dist: 35.8,
+            max_dist_multipliers: [0.0, 1.0, 1.1, 1.3, 2.0],
+            max_cnt: 5000,
+        }
+    }
+}
+
+// Common interface of all solvers, so they could be picked from CLI/UI and compared.
+pub trait SolverStrategy: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    // candidates in any order, see [SolverStrategy::solve_ranked]
+    fn solve(
+        &self,
+        graph: &Graph,
+        parsed_puzzles: &ParsedPuzzles,
+        known_facts: &KnownFacts,
+        config: &SolverConfig,
+        sink: &ProgressSink,
+    ) -> Vec<CandidatePlacement>;
+
+    // best candidates first
+    fn solve_ranked(
+        &self,
+        graph: &Graph,
+        parsed_puzzles: &ParsedPuzzles,
+        known_facts: &KnownFacts,
+        config: &SolverConfig,
+        sink: &ProgressSink,
+    ) -> Vec<CandidatePlacement> {
+        let mut candidates = self.solve(graph, parsed_puzzles, known_facts, config, sink);
+        candidates.sort_by(|c1, c2| c1.score.total_cmp(&c2.score));
+        candidates.truncate(config.max_candidates);
+        candidates
+    }
+}
+
+pub struct AddBy3Strategy;
+
+impl SolverStrategy for AddBy3Strategy {
+    fn name(&self) -> &'static str {
+        "add_by_3"
+    }
+
+    fn solve(
+        &self,
+        graph: &Graph,
+        parsed_puzzles: &ParsedPuzzles,
+        known_facts: &KnownFacts,
+        config: &SolverConfig,
+        sink: &ProgressSink,
+    ) -> Vec<CandidatePlacement> {
+        solve_graph_add_by_3(graph, parsed_puzzles, known_facts, config, sink)
+    }
+}
+
+pub struct AssignmentStrategy;
+
+impl SolverStrategy for AssignmentStrategy {
+    fn name(&self) -> &'static str {
+        "assignment"
+    }
+
+    fn solve(
+        &self,
+        graph: &Graph,
+        parsed_puzzles: &ParsedPuzzles,
+        known_facts: &KnownFacts,
+        config: &SolverConfig,
+        sink: &ProgressSink,
+    ) -> Vec<CandidatePlacement> {
+        solve_graph_assignment(graph, parsed_puzzles, known_facts, config, sink)
+    }
+}
+
+pub struct BeamSearchStrategy;
+
+impl SolverStrategy for BeamSearchStrategy {
+    fn name(&self) -> &'static str {
+        "beam_search"
+    }
+
+    fn solve(
+        &self,
+        graph: &Graph,
+        parsed_puzzles: &ParsedPuzzles,
+        known_facts: &KnownFacts,
+        config: &SolverConfig,
+        sink: &ProgressSink,
+    ) -> Vec<CandidatePlacement> {
+        solve_graph(graph, parsed_puzzles, None, known_facts, config, sink)
+    }
+}
+
+pub struct FrameStrategy;
+
+impl SolverStrategy for FrameStrategy {
+    fn name(&self) -> &'static str {
+        "frame"
+    }
+
+    fn solve(
+        &self,
+        graph: &Graph,
+        parsed_puzzles: &ParsedPuzzles,
+        known_facts: &KnownFacts,
+        _config: &SolverConfig,
+        sink: &ProgressSink,
+    ) -> Vec<CandidatePlacement> {
+        sink.report("Searching frame", 0.0, None);
+        match solve_frame(graph, parsed_puzzles, known_facts) {
+            Some(frame) => vec![CandidatePlacement::from_edges(
+                frame.edges(),
+                frame.cost,
+                format!(". frame, max edge {:.3}", frame.max_edge_dist),
+            )],
+            None => vec![],
+        }
+    }
+}
+
+pub struct LocalImprovementStrategy;
+
+impl SolverStrategy for LocalImprovementStrategy {
+    fn name(&self) -> &'static str {
+        "local_improvement"
+    }
+
+    fn solve(
+        &self,
+        graph: &Graph,
+        parsed_puzzles: &ParsedPuzzles,
+        known_facts: &KnownFacts,
+        config: &SolverConfig,
+        sink: &ProgressSink,
+    ) -> Vec<CandidatePlacement> {
+        sink.report("Replacing figures", 0.0, None);
+        solve_local_improvement(graph, parsed_puzzles, known_facts, config)
+    }
+}
+
+pub fn all_strategies() -> Vec<Box<dyn SolverStrategy>> {
+    vec![
+        Box::new(AddBy3Strategy),
+        Box::new(AssignmentStrategy),
+        Box::new(BeamSearchStrategy),
+        Box::new(FrameStrategy),
+        Box::new(LocalImprovementStrategy),
+    ]
+}
+
+pub fn find_strategy(name: &str) -> Option<Box<dyn SolverStrategy>> {
+    all_strategies().into_iter().find(|s| s.name() == name)
+}
+
+// If solving is cancelled, the picker is built from solutions generated before it. Returns
+// None if there are no such solutions.
+pub fn run_strategy(
+    strategy: &dyn SolverStrategy,
+    graph: &Graph,
+    parsed_puzzles: &ParsedPuzzles,
+    known_facts: &KnownFacts,
+    config: &Config,
+    sink: &ProgressSink,
+) -> Option<InteractiveSolutionPicker> {
+    let start = Instant::now();
+    let candidates =
+        strategy.solve_ranked(graph, parsed_puzzles, known_facts, &config.solver, sink);
+    eprintln!(
+        "Strategy {} found {} candidates in {:?}",
+        strategy.name(),
+        candidates.len(),
+        start.elapsed()
+    );
+    build_solutions_picker(
+        &candidates,
+        graph,
+        parsed_puzzles,
+        known_facts,
+        config,
+        sink,
+    )
+}
@@ -0,0 +1,181 @@
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{channel, Receiver, Sender, TryRecvError},
+        Arc,
+    },
+    thread,
+};
+
+use crate::{
+    frame_solver::FrameSolution, graph_solver::PotentialSolution,
+    interactive_solutions_picker::InteractiveSolutionPicker,
+};
+
+#[derive(Clone, Debug, Default)]
+pub struct SolverProgress {
+    pub stage: String,
+    // 0..100, for the current stage
+    pub percent: f32,
+    // best (smallest) placement score found so far
+    pub best_score: Option<f64>,
+}
+
+pub enum SolverEvent {
+    Progress(SolverProgress),
+    // candidates found so far, sent before the whole run is finished
+    Candidates(Vec<PotentialSolution>),
+    // result of the frame search, it doesn't create a picker
+    FrameFound(FrameSolution),
+    // picker is None if nothing should replace the current one, it is boxed as it is
+    // much bigger than other events
+    Finished {
+        picker: Option<Box<InteractiveSolutionPicker>>,
+        cancelled: bool,
+    },
+}
+
+// Passed into solver functions, so they could report what they do and stop early.
+// Without a worker it only prints progress.
+#[derive(Clone)]
+pub struct ProgressSink {
+    sender: Option<Sender<SolverEvent>>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl ProgressSink {
+    pub fn none() -> Self {
+        Self {
+            sender: None,
+            cancel: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn report(&self, stage: &str, percent: f32, best_score: Option<f64>) {
+        match &self.sender {
+            Some(sender) => {
+                sender
+                    .send(SolverEvent::Progress(SolverProgress {
+                        stage: stage.to_owned(),
+                        percent,
+                        best_score,
+                    }))
+                    .ok();
+            }
+            None => eprintln!("{stage}: {percent:.0}%"),
+        }
+    }
+
+    pub fn send_candidates(&self, candidates: Vec<PotentialSolution>) {
+        if let Some(sender) = &self.sender {
+            sender.send(SolverEvent::Candidates(candidates)).ok();
+        }
+    }
+
+    pub fn send_frame(&self, frame: FrameSolution) {
+        if let Some(sender) = &self.sender {
+            sender.send(SolverEvent::FrameFound(frame)).ok();
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed)
+    }
+}
+
+// Runs one solver or refresh job in a background thread. UI should call `poll` every frame.
+pub struct SolverWorker {
+    receiver: Receiver<SolverEvent>,
+    cancel: Arc<AtomicBool>,
+    pub name: String,
+    pub progress: SolverProgress,
+    // best first, in the order of `PotentialSolution`
+    pub candidates: Vec<PotentialSolution>,
+    pub frame: Option<FrameSolution>,
+}
+
+impl SolverWorker {
+    pub fn start(
+        name: &str,
+        job: impl FnOnce(&ProgressSink) -> Option<InteractiveSolutionPicker> + Send + 'static,
+    ) -> Self {
+        let (sender, receiver) = channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let sink = ProgressSink {
+            sender: Some(sender.clone()),
+            cancel: cancel.clone(),
+        };
+        let name_copy = name.to_owned();
+        thread::spawn(move || {
+            let picker = job(&sink);
+            let cancelled = sink.is_cancelled();
+            eprintln!("Worker job {name_copy} finished, cancelled = {cancelled}");
+            sender
+                .send(SolverEvent::Finished {
+                    picker: picker.map(Box::new),
+                    cancelled,
+                })
+                .ok();
+        });
+        Self {
+            receiver,
+            cancel,
+            name: name.to_owned(),
+            progress: SolverProgress::default(),
+            candidates: vec![],
+            frame: None,
+        }
+    }
+
+    // Job works on a copy of the picker, while the UI keeps showing the current one. The
+    // copy is returned only if the job is finished, not if it is cancelled or panics.
+    pub fn start_with_picker(
+        name: &str,
+        mut picker: InteractiveSolutionPicker,
+        job: impl FnOnce(&mut InteractiveSolutionPicker, &ProgressSink) + Send + 'static,
+    ) -> Self {
+        let name_copy = name.to_owned();
+        Self::start(name, move |sink| {
+            if panic::catch_unwind(AssertUnwindSafe(|| job(&mut picker, sink))).is_err() {
+                eprintln!("Worker job {name_copy} panicked, current picker is kept");
+                return None;
+            }
+            if sink.is_cancelled() {
+                None
+            } else {
+                Some(picker)
+            }
+        })
+    }
+
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelling(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed)
+    }
+
+    // Handles all pending events. Returns (picker, cancelled) when the job is finished.
+    pub fn poll(&mut self) -> Option<(Option<InteractiveSolutionPicker>, bool)> {
+        loop {
+            match self.receiver.try_recv() {
+                Ok(SolverEvent::Progress(progress)) => self.progress = progress,
+                Ok(SolverEvent::Candidates(candidates)) => {
+                    self.candidates.extend(candidates);
+                    self.candidates.sort();
+                }
+                Ok(SolverEvent::FrameFound(frame)) => self.frame = Some(frame),
+                Ok(SolverEvent::Finished { picker, cancelled }) => {
+                    return Some((picker.map(|picker| *picker), cancelled))
+                }
+                Err(TryRecvError::Empty) => return None,
+                Err(TryRecvError::Disconnected) => {
+                    eprintln!("Worker job {} panicked", self.name);
+                    return Some((None, false));
+                }
+            }
+        }
+    }
+}
@@ -1,8 +1,4 @@
-use std::{
-    cmp::min,
-    collections::{BTreeMap, BTreeSet, VecDeque},
-    f64::consts::PI,
-};
+use std::{cmp::min, collections::BTreeSet, f64::consts::PI};
 
 use eframe::egui::plot::Corner;
 use itertools::Itertools;
@@ -10,11 +6,9 @@ use ndarray::Array4;
 use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
 
 use crate::{
-    border_matcher::{
-        local_optimize_coordinate_systems, match_borders, match_placed_borders, BorderAndNeighbors,
-        MatchResult,
-    },
+    border_matcher::{match_borders, match_placed_borders, BorderAndNeighbors, MatchResult},
     borders_graph::Graph,
+    config::{Config, MatcherConfig},
     coordinate_system::CoordinateSystem,
     dsu::Dsu,
     figure::Figure,
@@ -22,6 +16,7 @@ use crate::{
     parsed_puzzles::ParsedPuzzles,
     placement::{Placement, PotentialLocation},
     point::{Point, PointF},
+    pose_refinement::refine_poses,
     rects_fitter::{get_bounding_box, RectsFitter},
     utils::{dedup_edges, fmax, fmin, Side},
 };
@@ -47,17 +42,6 @@ impl MoveCS {
         Self { from_cs, to_cs }
     }
 
-    pub fn new(figure: &Figure, positions: &[PointF]) -> Self {
-        let n = figure.border.len() / 2;
-        let from_cs = from_cs_from_figure(figure);
-        assert!(figure.border[0] != figure.border[n]);
-        assert_eq!(figure.border.len(), positions.len());
-        Self {
-            from_cs,
-            to_cs: CoordinateSystem::new(positions[0], positions[n] - positions[0]),
-        }
-    }
-
     pub fn conv_point(&self, p: PointF) -> PointF {
         self.to_cs.to_real(self.from_cs.create(p))
     }
@@ -85,7 +69,7 @@ impl EdgesScores {
     }
 }
 
-fn get_border(pos: &[PointF], parsed_puzzles: &ParsedPuzzles, side: Side) -> Vec<PointF> {
+pub fn get_border(pos: &[PointF], parsed_puzzles: &ParsedPuzzles, side: Side) -> Vec<PointF> {
     let figure = &parsed_puzzles.figures[side.fig];
     let mut cur = figure.corner_positions[side.side];
     let end = figure.corner_positions[(side.side + 1) % figure.corner_positions.len()];
@@ -102,16 +86,17 @@ pub fn get_border_and_neighbors(
     pos: &[PointF],
     parsed_puzzles: &ParsedPuzzles,
     side: Side,
+    neighbors: usize,
 ) -> BorderAndNeighbors {
     let mut prev = get_border(pos, parsed_puzzles, side.pr());
     {
-        let sz = min(BorderAndNeighbors::NEIGHBORS, prev.len());
+        let sz = min(neighbors, prev.len());
         prev.rotate_right(sz);
         prev.truncate(sz);
         prev.reverse();
     }
     let mut next = get_border(pos, parsed_puzzles, side.ne());
-    next.truncate(BorderAndNeighbors::NEIGHBORS);
+    next.truncate(neighbors);
 
     BorderAndNeighbors {
         border: get_border(pos, parsed_puzzles, side),
@@ -120,167 +105,85 @@ pub fn get_border_and_neighbors(
     }
 }
 
-fn get_borders_dist(
+pub fn get_borders_dist(
     pos1: &[PointF],
     pos2: &[PointF],
     parsed_puzzles: &ParsedPuzzles,
     s1: Side,
     s2: Side,
+    config: &MatcherConfig,
 ) -> f64 {
-    let border1 = get_border_and_neighbors(pos1, parsed_puzzles, s1);
-    let border2 = get_border_and_neighbors(pos2, parsed_puzzles, s2);
+    let config = &config.for_placement();
+    let neighbors = config.scoring.neighbors;
+    let border1 = get_border_and_neighbors(pos1, parsed_puzzles, s1, neighbors);
+    let border2 = get_border_and_neighbors(pos2, parsed_puzzles, s2, neighbors);
     let border2 = border2.reverse();
-    match_placed_borders(&border1, &border2)
+    match_placed_borders(&border1, &border2, config)
 }
 
+// Poses are refined by least squares over border correspondences, the result is kept
+// only if it is better by the matcher score.
 fn local_optimize_positions(
     all_edges: &[(Side, Side)],
     positions: &mut [Option<Vec<PointF>>],
     parsed_puzzles: &ParsedPuzzles,
     cur_component: &[usize],
-    new_points: &[usize],
+    config: &Config,
 ) -> f64 {
-    let local_id_mapping: BTreeMap<usize, usize> = cur_component
-        .iter()
-        .enumerate()
-        .map(|(local_id, &global_id)| (global_id, local_id))
-        .collect();
-
     let all_edges = all_edges
         .iter()
         .cloned()
         .filter(|(s1, s2)| {
             positions[s1.fig].is_some()
                 && positions[s2.fig].is_some()
-                && local_id_mapping.contains_key(&s1.fig)
-                && local_id_mapping.contains_key(&s2.fig)
+                && cur_component.contains(&s1.fig)
+                && cur_component.contains(&s2.fig)
         })
         .collect_vec();
 
-    let mut final_score = 0.0;
-
-    let very_big = cur_component.len() > 26;
-    for glob_iter in 0..1 {
-        let move_cs = cur_component
-            .iter()
-            .map(|&v| MoveCS::new(&parsed_puzzles.figures[v], &positions[v].as_ref().unwrap()))
-            .collect_vec();
-
-        let calc_new_positions = |to_cs: &[CoordinateSystem]| {
-            (0..to_cs.len())
-                .map(|local_id| {
-                    parsed_puzzles.figures[cur_component[local_id]]
-                        .border
-                        .iter()
-                        .map(|&p| {
-                            to_cs[local_id].to_real(move_cs[local_id].from_cs.create(p.conv_f64()))
-                        })
-                        .collect_vec()
-                })
-                .collect_vec()
-        };
-
-        let calc_score = |to_cs: &[CoordinateSystem]| {
-            let my_pos = calc_new_positions(to_cs);
-            let mut res = EdgesScores::new();
-            let all_dists: Vec<_> = all_edges
-                .par_iter()
-                .map(|&(s1, s2)| {
-                    get_borders_dist(
-                        &my_pos[local_id_mapping[&s1.fig]],
-                        &my_pos[local_id_mapping[&s2.fig]],
-                        parsed_puzzles,
-                        s1,
-                        s2,
-                    )
-                })
-                .collect();
-            for &dist in all_dists.iter() {
-                res.add_score(dist);
-            }
-            res
-        };
-
-        let mut to_cs = move_cs
-            .iter()
-            .map(|move_cs| move_cs.to_cs.clone())
-            .collect_vec();
-        if very_big {
-            let mut new_points = new_points.to_vec();
-            {
-                const LIMIT_NEW: usize = 30;
-                let my_pos = calc_new_positions(&to_cs);
-                let mut sum_dists = vec![0.0; cur_component.len()];
-                let mut cnt = vec![0; cur_component.len()];
-                for &(s1, s2) in all_edges.iter() {
-                    let d = get_borders_dist(
-                        &my_pos[local_id_mapping[&s1.fig]],
-                        &my_pos[local_id_mapping[&s2.fig]],
-                        parsed_puzzles,
-                        s1,
-                        s2,
-                    );
-                    sum_dists[local_id_mapping[&s1.fig]] += d;
-                    sum_dists[local_id_mapping[&s2.fig]] += d;
-                    cnt[local_id_mapping[&s1.fig]] += 1;
-                    cnt[local_id_mapping[&s2.fig]] += 1;
-                }
-                for i in 0..sum_dists.len() {
-                    sum_dists[i] /= cnt[i] as f64;
-                }
-                let mut more_points = vec![];
-                for i in 0..sum_dists.len() {
-                    more_points.push(i);
-                }
-                more_points
-                    .sort_by(|&id1, &id2| sum_dists[id1].total_cmp(&sum_dists[id2]).reverse());
-                more_points.truncate(LIMIT_NEW);
-                new_points.extend(more_points);
-            }
-            eprintln!(
-                "Very big, will use subset of coords. Start score: {}. New pts: {}",
-                calc_score(&to_cs).get_final_score(),
-                new_points.len()
-            );
-            to_cs = local_optimize_coordinate_systems(
-                &to_cs,
-                |to_cs| calc_score(to_cs).get_final_score(),
-                &new_points,
-            );
-            eprintln!(
-                "After optimize score: {}",
-                calc_score(&to_cs).get_final_score()
-            );
-            let new_pos = calc_new_positions(&to_cs);
-            for local_id in 0..cur_component.len() {
-                positions[cur_component[local_id]] = Some(new_pos[local_id].clone());
-            }
-        }
-        let start_score = calc_score(&to_cs);
-        if very_big {
-            eprintln!("start score: {}", start_score.get_final_score());
-        }
-        if start_score.get_final_score() < 150.0 && very_big {
-            final_score = start_score.get_final_score();
-            break;
+    let calc_score = |positions: &[Option<Vec<PointF>>]| {
+        let mut res = EdgesScores::new();
+        let all_dists: Vec<_> = all_edges
+            .par_iter()
+            .map(|&(s1, s2)| {
+                get_borders_dist(
+                    positions[s1.fig].as_ref().unwrap(),
+                    positions[s2.fig].as_ref().unwrap(),
+                    parsed_puzzles,
+                    s1,
+                    s2,
+                    &config.matcher,
+                )
+            })
+            .collect();
+        for &dist in all_dists.iter() {
+            res.add_score(dist);
         }
-        let to_cs = local_optimize_coordinate_systems(
-            &to_cs,
-            |to_cs| calc_score(to_cs).get_final_score(),
-            &(0..to_cs.len()).collect_vec(),
-        );
-        let new_score = calc_score(&to_cs);
-        final_score = new_score.get_final_score();
-        if new_score.get_final_score() < start_score.get_final_score() {
-            let new_pos = calc_new_positions(&to_cs);
-            for local_id in 0..cur_component.len() {
-                positions[cur_component[local_id]] = Some(new_pos[local_id].clone());
-            }
-        } else {
-            break;
+        res.get_final_score()
+    };
+
+    let start_positions = positions.to_vec();
+    let start_score = calc_score(positions);
+    refine_poses(
+        parsed_puzzles,
+        &all_edges,
+        positions,
+        &[],
+        &config.surface,
+        |_| vec![],
+    );
+    let new_score = calc_score(positions);
+    if cur_component.len() > config.surface.big_component {
+        eprintln!("Score after pose refinement: {start_score} -> {new_score}");
+    }
+    if new_score < start_score {
+        new_score
+    } else {
+        for &fig in cur_component.iter() {
+            positions[fig] = start_positions[fig].clone();
         }
+        start_score
     }
-    final_score
 }
 
 pub fn rotate_component(
@@ -348,6 +251,60 @@ pub fn rotate_component(
             *p = p.rotate(best_angle);
         }
     }
+    if let Some(angle) = estimate_frame_angle(component, positions, parsed_puzzles) {
+        for &c in component.iter() {
+            for p in positions[c].as_mut().unwrap().iter_mut() {
+                *p = p.rotate(angle);
+            }
+        }
+    }
+}
+
+// Angle from -PI/4 to PI/4, rotating by which makes sides of the figures axis-aligned.
+// Straight sides weigh much more, so it is mostly defined by the outer frame.
+pub fn estimate_frame_angle(
+    component: &[usize],
+    positions: &[Option<Vec<PointF>>],
+    parsed_puzzles: &ParsedPuzzles,
+) -> Option<f64> {
+    // ratio of chord to path length is ~0.6 for sides with tabs
+    const STRAIGHTNESS_POWER: i32 = 8;
+
+    let mut sum = PointF::ZERO;
+    for &c in component.iter() {
+        let figure = &parsed_puzzles.figures[c];
+        let pts = match &positions[c] {
+            Some(pts) if figure.corner_positions.len() == 4 => pts,
+            _ => continue,
+        };
+        for side in 0..4 {
+            let from = figure.corner_positions[side];
+            let to = figure.corner_positions[(side + 1) % 4];
+            let mut path_len = 0.0;
+            let mut cur = from;
+            while cur != to {
+                let next = (cur + 1) % pts.len();
+                path_len += pts[cur].dist2(&pts[next]).sqrt();
+                cur = next;
+            }
+            let chord = pts[to] - pts[from];
+            if path_len == 0.0 {
+                continue;
+            }
+            let weight = chord.len() * (chord.len() / path_len).powi(STRAIGHTNESS_POWER);
+            let angle = chord.y.atan2(chord.x) * 4.0;
+            sum = sum
+                + PointF {
+                    x: angle.cos(),
+                    y: angle.sin(),
+                } * weight;
+        }
+    }
+    if sum.len2() == 0.0 {
+        return None;
+    }
+    // `rotate` turns directions by minus angle
+    Some(sum.y.atan2(sum.x) / 4.0)
 }
 
 pub fn place_one_connected_component(
@@ -356,10 +313,11 @@ pub fn place_one_connected_component(
     used_edges: &[(Side, Side)],
     positions: &mut Vec<Option<Vec<PointF>>>,
     base_points_matrix: &Array4<[PointF; 2]>,
+    config: &Config,
 ) -> Option<f64> {
     // let mut matched_borders = BTreeMap::new();
 
-    let very_big = component.len() > 26;
+    let very_big = component.len() > config.surface.big_component;
 
     let used_edges = dedup_edges(used_edges);
     let mut used_edges_two_sides = vec![];
@@ -394,17 +352,6 @@ pub fn place_one_connected_component(
         to_cs[fig] = Some(CoordinateSystem::new(p1_placed, p2_placed - p1_placed));
         some_placed = true;
     }
-    let new_figures = component
-        .iter()
-        .enumerate()
-        .filter_map(|(pos, &fig)| {
-            if positions[fig].is_none() {
-                Some(pos)
-            } else {
-                None
-            }
-        })
-        .collect_vec();
     if !some_placed {
         let root = component[0];
         positions[root] = Some(gen_basic_position(&parsed_puzzles.figures[root]));
@@ -508,7 +455,7 @@ pub fn place_one_connected_component(
         positions,
         parsed_puzzles,
         component,
-        &new_figures,
+        config,
     ))
 }
 
@@ -517,6 +464,7 @@ pub fn place_on_surface(
     placement: &Placement,
     parsed_puzzles: &ParsedPuzzles,
     base_points_matrix: &Array4<[PointF; 2]>,
+    config: &Config,
 ) -> Vec<Option<Vec<PointF>>> {
     assert_eq!(graph.n, parsed_puzzles.figures.len());
     eprintln!("Start placing on the surface!");
@@ -549,6 +497,7 @@ pub fn place_on_surface(
             &used_edges,
             &mut positions,
             base_points_matrix,
+            config,
         );
 
         eprintln!("Rotate component!");
@@ -580,28 +529,127 @@ pub fn place_on_surface(
     positions
 }
 
-pub fn put_solutions_on_surface(solutions: &mut [PotentialSolution]) {
-    let mut rects_fitter = RectsFitter::new();
-    for i in 0..solutions.len() {
-        let new_pts: Vec<PointF> = solutions[i]
+fn new_figures_center(sol: &PotentialSolution) -> Option<PointF> {
+    let pts = sol
+        .placed_figures
+        .iter()
+        .filter(|pf| sol.new_figures_used.contains(&pf.figure_id))
+        .flat_map(|pf| pf.positions.iter())
+        .cloned()
+        .collect_vec();
+    if pts.is_empty() {
+        return None;
+    }
+    let (p0, p1) = get_bounding_box(&pts);
+    Some((p0 + p1) / 2.0)
+}
+
+// Only figures near new ones are left, everything is zoomed around new figures.
+// The crop size is the same for all solutions, so they have the same scale.
+fn crop_to_new_figures(solutions: &mut [PotentialSolution]) {
+    // half of the crop size in sizes of figures
+    const CROP_FIGURES: f64 = 1.8;
+    const ZOOM: f64 = 2.0;
+
+    let figure_sizes = solutions
+        .iter()
+        .flat_map(|sol| sol.placed_figures.iter())
+        .map(|pf| {
+            let (p0, p1) = get_bounding_box(&pf.positions);
+            fmax(p1.x - p0.x, p1.y - p0.y)
+        })
+        .collect_vec();
+    if figure_sizes.is_empty() {
+        return;
+    }
+    let half_size = CROP_FIGURES * figure_sizes.iter().sum::<f64>() / (figure_sizes.len() as f64);
+    for sol in solutions.iter_mut() {
+        let center = match new_figures_center(sol) {
+            Some(center) => center,
+            None => continue,
+        };
+        let is_near =
+            |p: &PointF| (p.x - center.x).abs() < half_size && (p.y - center.y).abs() < half_size;
+        sol.placed_figures
+            .retain(|pf| pf.positions.iter().any(&is_near));
+        for p in sol
             .placed_figures
-            .iter()
-            .flat_map(|pf| pf.positions.iter())
-            .cloned()
-            .collect();
-        let bbox = get_bounding_box(&new_pts);
-        let shift = rects_fitter.add_points(&new_pts);
-        for fig in solutions[i].placed_figures.iter_mut() {
+            .iter_mut()
+            .flat_map(|pf| pf.positions.iter_mut())
+            .chain(sol.debug_lines.iter_mut().flatten())
+        {
+            *p = center + (*p - center) * ZOOM;
+        }
+    }
+}
+
+// Solutions are shown in a grid, the known component (figures, which are not new) is at the
+// same place of each cell, so only new figures differ between cells. With `diff_view` cells
+// are zoomed crops around new figures.
+pub fn put_solutions_on_surface(solutions: &mut [PotentialSolution], diff_view: bool) {
+    if solutions.is_empty() {
+        return;
+    }
+    if diff_view {
+        crop_to_new_figures(solutions);
+    }
+    let bboxes = solutions
+        .iter()
+        .map(|sol| {
+            let all_pts = sol
+                .placed_figures
+                .iter()
+                .flat_map(|pf| pf.positions.iter())
+                .cloned()
+                .collect_vec();
+            let known_pts = sol
+                .placed_figures
+                .iter()
+                .filter(|pf| !sol.new_figures_used.contains(&pf.figure_id))
+                .flat_map(|pf| pf.positions.iter())
+                .cloned()
+                .collect_vec();
+            let anchor = match new_figures_center(sol) {
+                Some(center) if diff_view => center,
+                _ if known_pts.is_empty() => get_bounding_box(&all_pts).0,
+                _ => get_bounding_box(&known_pts).0,
+            };
+            let bbox = get_bounding_box(&all_pts);
+            (bbox.0 - anchor, bbox.1 - anchor, anchor)
+        })
+        .collect_vec();
+    let cell_start = PointF {
+        x: bboxes.iter().map(|b| b.0.x).fold(f64::MAX, fmin),
+        y: bboxes.iter().map(|b| b.0.y).fold(f64::MAX, fmin),
+    };
+    let cell_end = PointF {
+        x: bboxes.iter().map(|b| b.1.x).fold(f64::MIN, fmax),
+        y: bboxes.iter().map(|b| b.1.y).fold(f64::MIN, fmax),
+    };
+    let cell_size = cell_end - cell_start;
+    // the whole grid is squarish
+    let cols = ((solutions.len() as f64 * cell_size.y / cell_size.x)
+        .sqrt()
+        .ceil() as usize)
+        .clamp(1, solutions.len());
+    for (i, sol) in solutions.iter_mut().enumerate() {
+        let cell = PointF {
+            x: (i % cols) as f64 * cell_size.x,
+            y: (i / cols) as f64 * cell_size.y,
+        };
+        let (_, _, anchor) = bboxes[i];
+        let shift = cell - cell_start - anchor;
+        for fig in sol.placed_figures.iter_mut() {
             for p in fig.positions.iter_mut() {
                 *p = *p + shift;
             }
         }
-        for line in solutions[i].debug_lines.iter_mut() {
+        for line in sol.debug_lines.iter_mut() {
             for p in line.iter_mut() {
                 *p = *p + shift;
             }
         }
-        solutions[i].text_offset = bbox.0 + shift;
-        solutions[i].bbox = (bbox.0 + shift, bbox.1 + shift)
+        sol.text_offset = cell;
+        sol.bbox = (cell, cell + cell_size);
     }
 }
@@ -0,0 +1,50 @@
+#[cfg(test)]
+mod tests {
+    use crate::{
+        borders_graph::Graph,
+        parsed_puzzles::ParsedPuzzles,
+        surface_placer::{estimate_frame_angle, rotate_component},
+        synthetic::{gen_pattern_image, SyntheticPuzzle},
+    };
+
+    #[test]
+    pub fn frame_becomes_axis_aligned() {
+        let source = gen_pattern_image(400, 300, 1);
+        let puzzle = SyntheticPuzzle::generate(&source, 3, 4, 2);
+        let parsed_puzzles = ParsedPuzzles::new(&puzzle.image);
+        let graph = Graph {
+            n: parsed_puzzles.figures.len(),
+            all_edges: vec![],
+            parsed_puzzles_hash: 0,
+            matcher_hash: 0,
+        };
+        let truth = puzzle.assembled_positions(&parsed_puzzles);
+        let component = (0..truth.len())
+            .filter(|&fig| truth[fig].is_some())
+            .collect::<Vec<_>>();
+        // parsed borders are a bit noisy, so the frame is aligned up to ~0.005 rad
+        let angle = estimate_frame_angle(&component, &truth, &parsed_puzzles).unwrap();
+        assert!(angle.abs() < 0.01, "{angle}");
+
+        let mut positions = truth.clone();
+        for &fig in component.iter() {
+            for p in positions[fig].as_mut().unwrap().iter_mut() {
+                *p = p.rotate(0.3);
+            }
+        }
+        let angle = estimate_frame_angle(&component, &positions, &parsed_puzzles).unwrap();
+        assert!((angle + 0.3).abs() < 0.01, "{angle}");
+
+        // only the first figure is the reference, so the frame is upright up to 90 degrees
+        rotate_component(
+            &component,
+            &mut positions,
+            &graph,
+            &parsed_puzzles,
+            &[],
+            &[],
+        );
+        let angle = estimate_frame_angle(&component, &positions, &parsed_puzzles).unwrap();
+        assert!(angle.abs() < 0.01, "{angle}");
+    }
+}
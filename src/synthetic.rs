@@ -0,0 +1,304 @@
+use std::f64::consts::PI;
+
+use eframe::epaint::{Color32, ColorImage};
+use itertools::Itertools;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+use crate::{
+    ground_truth::{GridArea, GridCell, GroundTruth},
+    parsed_puzzles::ParsedPuzzles,
+    point::PointF,
+    utils::Side,
+};
+
+const BACKGROUND: Color32 = Color32::from_rgb(20, 20, 25);
+// distance between centers of scattered pieces, relative to the piece size
+const SLOT_SIZE: f64 = 1.8;
+
+#[derive(Clone, Copy, Debug)]
+struct Tab {
+    // +1 if the tab belongs to the first piece (top or left one) and goes into the second one
+    dir: f64,
+    // position of the tab center along the edge, 0..1
+    along: f64,
+    // distance from the edge to the tab center, relative to the piece size. Bigger offsets
+    // give sharp spikes near the edge, which break border detection.
+    offset: f64,
+    radius: f64,
+}
+
+impl Tab {
+    fn gen(rng: &mut StdRng) -> Self {
+        Self {
+            dir: if rng.gen_bool(0.5) { 1.0 } else { -1.0 },
+            along: rng.gen_range(0.4..0.6),
+            offset: rng.gen_range(0.0..0.06),
+            radius: rng.gen_range(0.17..0.22),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SyntheticPiece {
+    pub row: usize,
+    pub col: usize,
+    // center on the generated photo
+    pub center: PointF,
+    // [PointF::rotate] angle, which moves the piece on the photo back to the source image
+    pub rotation: f64,
+}
+
+pub struct SyntheticPuzzle {
+    pub rows: usize,
+    pub cols: usize,
+    // in pixels
+    pub piece_size: usize,
+    pub pieces: Vec<SyntheticPiece>,
+    pub image: ColorImage,
+    // tabs on edges between (r, c) and (r, c + 1)
+    vertical_tabs: Vec<Vec<Tab>>,
+    // tabs on edges between (r, c) and (r + 1, c)
+    horizontal_tabs: Vec<Vec<Tab>>,
+}
+
+// Smooth colorful picture, so puzzles could be generated without any photos.
+pub fn gen_pattern_image(width: usize, height: usize, seed: u64) -> ColorImage {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let waves = (0..3)
+        .map(|_| {
+            (
+                rng.gen_range(0.005..0.03),
+                rng.gen_range(0.005..0.03),
+                rng.gen_range(0.0..2.0 * PI),
+            )
+        })
+        .collect_vec();
+    let mut res = ColorImage::new([width, height], Color32::BLACK);
+    for x in 0..width {
+        for y in 0..height {
+            let channel = |i: usize| -> u8 {
+                let (fx, fy, phase) = waves[i];
+                let v = ((x as f64) * fx + (y as f64) * fy + phase).sin();
+                ((v + 1.0) * 127.5) as u8
+            };
+            res[(x, y)] = Color32::from_rgb(channel(0), channel(1), channel(2));
+        }
+    }
+    res
+}
+
+// Pieces should be much brighter than the background, otherwise they are not detected.
+fn brighten(color: Color32) -> Color32 {
+    let f = |c: u8| 210 + ((c as u32) * 45 / 255) as u8;
+    Color32::from_rgb(f(color.r()), f(color.g()), f(color.b()))
+}
+
+impl SyntheticPuzzle {
+    // Cuts `source` into rows x cols pieces with random tabs, and scatters them
+    // with random rotations on a dark background.
+    pub fn generate(source: &ColorImage, rows: usize, cols: usize, seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let piece_size = (source.size[0] / cols).min(source.size[1] / rows);
+        let vertical_tabs = (0..rows)
+            .map(|_| (0..cols - 1).map(|_| Tab::gen(&mut rng)).collect_vec())
+            .collect_vec();
+        let horizontal_tabs = (0..rows - 1)
+            .map(|_| (0..cols).map(|_| Tab::gen(&mut rng)).collect_vec())
+            .collect_vec();
+
+        let slot = (piece_size as f64) * SLOT_SIZE;
+        let slots_per_row = ((rows * cols) as f64).sqrt().ceil() as usize;
+        let slots_per_col = (rows * cols + slots_per_row - 1) / slots_per_row;
+        let width = (slot * (slots_per_row as f64 + 1.0)) as usize;
+        let height = (slot * (slots_per_col as f64 + 1.0)) as usize;
+
+        let mut order = (0..rows * cols).collect_vec();
+        order.shuffle(&mut rng);
+        let jitter = (piece_size as f64) * 0.1;
+        let pieces = order
+            .iter()
+            .enumerate()
+            .map(|(slot_id, &piece_id)| SyntheticPiece {
+                row: piece_id / cols,
+                col: piece_id % cols,
+                center: PointF {
+                    x: slot * ((slot_id % slots_per_row) as f64 + 1.0)
+                        + rng.gen_range(-jitter..jitter),
+                    y: slot * ((slot_id / slots_per_row) as f64 + 1.0)
+                        + rng.gen_range(-jitter..jitter),
+                },
+                rotation: rng.gen_range(0.0..2.0 * PI),
+            })
+            .collect_vec();
+
+        let mut res = Self {
+            rows,
+            cols,
+            piece_size,
+            pieces,
+            image: ColorImage::new([width, height], BACKGROUND),
+            vertical_tabs,
+            horizontal_tabs,
+        };
+        res.draw(source);
+        res
+    }
+
+    // (row, col) of the piece, which contains point of the source image
+    fn owner(&self, p: PointF) -> Option<(usize, usize)> {
+        let size = self.piece_size as f64;
+        if p.x < 0.0 || p.y < 0.0 {
+            return None;
+        }
+        let in_tab = |tab: &Tab, edge_pos: PointF, normal: PointF, along: PointF| -> bool {
+            let center =
+                edge_pos + along * (tab.along * size) + normal * (tab.dir * tab.offset * size);
+            p.dist2(&center) < (tab.radius * size).powi(2)
+        };
+        let (row, col) = ((p.y / size) as usize, (p.x / size) as usize);
+        for r in row.saturating_sub(1)..=row + 1 {
+            for c in col.saturating_sub(1)..=col + 1 {
+                if r < self.rows && c + 1 < self.cols {
+                    let tab = &self.vertical_tabs[r][c];
+                    let edge_pos = PointF {
+                        x: (c + 1) as f64 * size,
+                        y: r as f64 * size,
+                    };
+                    if in_tab(
+                        tab,
+                        edge_pos,
+                        PointF { x: 1.0, y: 0.0 },
+                        PointF { x: 0.0, y: 1.0 },
+                    ) {
+                        return Some(if tab.dir > 0.0 { (r, c) } else { (r, c + 1) });
+                    }
+                }
+                if r + 1 < self.rows && c < self.cols {
+                    let tab = &self.horizontal_tabs[r][c];
+                    let edge_pos = PointF {
+                        x: c as f64 * size,
+                        y: (r + 1) as f64 * size,
+                    };
+                    if in_tab(
+                        tab,
+                        edge_pos,
+                        PointF { x: 0.0, y: 1.0 },
+                        PointF { x: 1.0, y: 0.0 },
+                    ) {
+                        return Some(if tab.dir > 0.0 { (r, c) } else { (r + 1, c) });
+                    }
+                }
+            }
+        }
+        if row < self.rows && col < self.cols {
+            Some((row, col))
+        } else {
+            None
+        }
+    }
+
+    fn cell_center(&self, row: usize, col: usize) -> PointF {
+        let size = self.piece_size as f64;
+        PointF {
+            x: (col as f64 + 0.5) * size,
+            y: (row as f64 + 0.5) * size,
+        }
+    }
+
+    fn draw(&mut self, source: &ColorImage) {
+        let half = (self.piece_size as f64) * SLOT_SIZE / 2.0;
+        for piece in self.pieces.clone().iter() {
+            let src_center = self.cell_center(piece.row, piece.col);
+            let x_range = (piece.center.x - half).max(0.0) as usize
+                ..((piece.center.x + half) as usize).min(self.image.size[0]);
+            for x in x_range {
+                let y_range = (piece.center.y - half).max(0.0) as usize
+                    ..((piece.center.y + half) as usize).min(self.image.size[1]);
+                for y in y_range {
+                    let p = PointF {
+                        x: x as f64,
+                        y: y as f64,
+                    };
+                    let src = (p - piece.center).rotate(piece.rotation) + src_center;
+                    if self.owner(src) == Some((piece.row, piece.col)) {
+                        let (sx, sy) = (src.x as usize, src.y as usize);
+                        if sx < source.size[0] && sy < source.size[1] {
+                            self.image[(x, y)] = brighten(source[(sx, sy)]);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Direction of the parsed figure side in the assembled puzzle:
+    // 0 - up, 1 - right, 2 - down, 3 - left.
+    fn side_direction(piece: &SyntheticPiece, parsed_puzzles: &ParsedPuzzles, side: Side) -> usize {
+        let figure = &parsed_puzzles.figures[side.fig];
+        let corners = &figure.corner_positions;
+        let p1 = figure.border[corners[side.side]].conv_f64();
+        let p2 = figure.border[corners[(side.side + 1) % 4]].conv_f64();
+        let v = ((p1 + p2) / 2.0 - piece.center).rotate(piece.rotation);
+        if v.x.abs() > v.y.abs() {
+            if v.x > 0.0 {
+                1
+            } else {
+                3
+            }
+        } else if v.y > 0.0 {
+            2
+        } else {
+            0
+        }
+    }
+
+    fn find_piece(&self, center: PointF) -> Option<&SyntheticPiece> {
+        let max_dist = (self.piece_size as f64) / 2.0;
+        self.pieces
+            .iter()
+            .find(|piece| piece.center.dist2(&center) < max_dist * max_dist)
+    }
+
+    // Borders of good parsed figures, moved to their places in the source image.
+    pub fn assembled_positions(&self, parsed_puzzles: &ParsedPuzzles) -> Vec<Option<Vec<PointF>>> {
+        parsed_puzzles
+            .figures
+            .iter()
+            .map(|figure| {
+                if !figure.is_good_puzzle() {
+                    return None;
+                }
+                let piece = self.find_piece(figure.center.conv_f64())?;
+                let src_center = self.cell_center(piece.row, piece.col);
+                Some(
+                    figure
+                        .border
+                        .iter()
+                        .map(|p| (p.conv_f64() - piece.center).rotate(piece.rotation) + src_center)
+                        .collect_vec(),
+                )
+            })
+            .collect_vec()
+    }
+
+    // Matches parsed figures with pieces by position. Pieces, which were not parsed
+    // as good figures, are left as unknown cells.
+    pub fn to_ground_truth(&self, parsed_puzzles: &ParsedPuzzles) -> GroundTruth {
+        let mut cells = vec![vec![None; self.cols]; self.rows];
+        for (fig, figure) in parsed_puzzles.figures.iter().enumerate() {
+            if !figure.is_good_puzzle() {
+                continue;
+            }
+            let piece = match self.find_piece(figure.center.conv_f64()) {
+                Some(piece) => piece,
+                None => continue,
+            };
+            let up = (0..4)
+                .find(|&side| Self::side_direction(piece, parsed_puzzles, Side { fig, side }) == 0);
+            cells[piece.row][piece.col] = Some(GridCell { fig, up });
+        }
+        GroundTruth {
+            areas: vec![GridArea { cells }],
+        }
+    }
+}
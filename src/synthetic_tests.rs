@@ -0,0 +1,94 @@
+#[cfg(test)]
+mod tests {
+    use crate::{
+        benchmark::evaluate,
+        border_matcher::match_borders,
+        borders_graph::Graph,
+        config::MatcherConfig,
+        graph_solver::CandidatePlacement,
+        known_facts::{Fact, KnownFacts},
+        parsed_puzzles::ParsedPuzzles,
+        solver_strategy::{find_strategy, SolverConfig},
+        solver_worker::ProgressSink,
+        synthetic::{gen_pattern_image, SyntheticPuzzle},
+        utils::dedup_edges,
+    };
+
+    #[test]
+    pub fn parse_synthetic_puzzle() {
+        let (rows, cols) = (3, 4);
+        let source = gen_pattern_image(400, 300, 1);
+        let puzzle = SyntheticPuzzle::generate(&source, rows, cols, 2);
+        let parsed_puzzles = ParsedPuzzles::new(&puzzle.image);
+        let good_figures = parsed_puzzles
+            .figures
+            .iter()
+            .filter(|f| f.is_good_puzzle())
+            .count();
+        assert_eq!(good_figures, rows * cols);
+
+        let ground_truth = puzzle.to_ground_truth(&parsed_puzzles);
+        assert_eq!(ground_truth.cnt_figures(), rows * cols);
+        // all rotations are known, so distances are not used
+        let placement = ground_truth.to_placement(&|_, _| 0.0).unwrap();
+        assert_eq!(placement.get_cnt_figures(), rows * cols);
+        let edges = dedup_edges(&placement.get_all_neighbours());
+        assert_eq!(edges.len(), rows * (cols - 1) + (rows - 1) * cols);
+
+        let cnt_matched = edges
+            .iter()
+            .filter(|&&(s1, s2)| {
+                match_borders(&parsed_puzzles, s1, s2, &MatcherConfig::default()).is_some()
+            })
+            .count();
+        assert_eq!(cnt_matched, edges.len());
+    }
+
+    #[test]
+    pub fn solve_synthetic_puzzle() {
+        let (rows, cols) = (4, 5);
+        let source = gen_pattern_image(500, 400, 3);
+        let puzzle = SyntheticPuzzle::generate(&source, rows, cols, 6);
+        let parsed_puzzles = ParsedPuzzles::new(&puzzle.image);
+        let graph = Graph::new(&parsed_puzzles, false, &MatcherConfig::default());
+        let truth_placement = puzzle
+            .to_ground_truth(&parsed_puzzles)
+            .to_placement(&|_, _| 0.0)
+            .unwrap();
+
+        // everything except the last row is known
+        let cells = truth_placement.get_cells_by_component().remove(0);
+        let mut known_placement = truth_placement.clone();
+        for ((row, _col), sides) in cells.iter() {
+            if *row + 1 == rows {
+                known_placement.remove_figure(sides[0].fig);
+            }
+        }
+        let known_facts = KnownFacts::from_facts(
+            dedup_edges(&known_placement.get_all_neighbours())
+                .into_iter()
+                .map(|(s1, s2)| Fact::new(s1, s2, true))
+                .collect(),
+        );
+        let known_placement = known_facts.build_placement();
+        assert_eq!(known_placement.get_cnt_figures(), (rows - 1) * cols);
+        let config = SolverConfig {
+            start_vertex: known_placement.get_all_used_figures()[0],
+            ..SolverConfig::default()
+        };
+
+        let nothing = CandidatePlacement::from_edges(vec![], 0.0, String::new());
+        let known_accuracy = evaluate(&nothing, &known_placement, &truth_placement);
+        let candidates = find_strategy("add_by_3").unwrap().solve_ranked(
+            &graph,
+            &parsed_puzzles,
+            &known_facts,
+            &config,
+            &ProgressSink::none(),
+        );
+        let accuracy = evaluate(&candidates[0], &known_placement, &truth_placement);
+        assert!(accuracy.cnt_new_edges > 0);
+        assert_eq!(accuracy.new_edges, 1.0);
+        assert!(accuracy.neighbour > known_accuracy.neighbour);
+    }
+}
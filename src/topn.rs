@@ -1,6 +1,8 @@
 use std::{cmp::Ordering, collections::BTreeSet};
 
-#[derive(Clone)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct TopN<T: Clone + Ord> {
     pub set: BTreeSet<T>,
     max_n: usize,